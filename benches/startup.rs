@@ -0,0 +1,82 @@
+//! Startup in a process with many shared objects, which is where loading images in parallel
+//! pays off: a few hundred libraries with debug info are compiled and loaded, then
+//! `image::load_all` is timed. Compare `cargo bench --bench startup` with and without
+//! `--features parallel`. Needs a C compiler.
+
+#[cfg(target_os = "linux")]
+fn main() {
+    use std::process::Command;
+    use std::time::{Duration, Instant};
+
+    const LIBRARIES: usize = 300;
+    const FUNCTIONS: usize = 200;
+    const RUNS: usize = 10;
+
+    let dir = std::env::temp_dir().join(format!("unwinder-startup-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let jobs = std::thread::available_parallelism().map_or(1, |x| x.get());
+    let libraries: Vec<_> = (0..LIBRARIES)
+        .map(|i| dir.join(format!("libstartup{}.so", i)))
+        .collect();
+    for (i, chunk) in libraries.chunks(jobs).enumerate() {
+        let children: Vec<_> = chunk
+            .iter()
+            .enumerate()
+            .map(|(j, path)| {
+                let source = path.with_extension("c");
+                let functions = (0..FUNCTIONS).map(|k| {
+                    format!(
+                        "int f{}_{}(int x) {{ return x * {} + {}; }}\n",
+                        i * jobs + j,
+                        k,
+                        k,
+                        j
+                    )
+                });
+                std::fs::write(&source, functions.collect::<String>()).unwrap();
+                Command::new("cc")
+                    .args(["-shared", "-fPIC", "-g", "-O1", "-o"])
+                    .arg(path)
+                    .arg(&source)
+                    .spawn()
+            })
+            .collect();
+        for child in children {
+            if !child.and_then(|mut x| x.wait()).is_ok_and(|x| x.success()) {
+                eprintln!("skipped: no C compiler to build the libraries with");
+                std::fs::remove_dir_all(&dir).ok();
+                return;
+            }
+        }
+    }
+    for path in &libraries {
+        let path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        assert!(!handle.is_null());
+    }
+
+    let mut times: Vec<Duration> = Vec::with_capacity(RUNS);
+    let mut images = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        images = std::hint::black_box(unwinder::image::load_all()).len();
+        times.push(start.elapsed());
+    }
+    times.sort();
+    println!(
+        "load_all of {} images ({}, {} threads): median {:?}, min {:?}",
+        images,
+        if cfg!(feature = "parallel") {
+            "parallel"
+        } else {
+            "sequential"
+        },
+        jobs,
+        times[RUNS / 2],
+        times[0],
+    );
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
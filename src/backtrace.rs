@@ -0,0 +1,412 @@
+//! Owned backtraces of the current thread, and their capture at panic time.
+use crate::cursor::state::NativeState;
+use crate::cursor::{DynamicCursor, StopReason, Unwinding};
+use crate::{GlobalContext, PublicFrame, UnwindError};
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::sync::OnceLock;
+
+/// Functions of the panic runtime, which run between the panicking frame and the hook.
+const PANIC_RUNTIME: &[&str] = &[
+    "core::panicking::",
+    "std::panicking::",
+    "std::sys::backtrace::",
+    "std::sys_common::backtrace::",
+    "rust_begin_unwind",
+    "__rustc::rust_begin_unwind",
+];
+
+/// The context the panic hook resolves through, loaded at the first panic.
+static PANIC_CONTEXT: OnceLock<GlobalContext<'static>> = OnceLock::new();
+
+thread_local! {
+    static PANIC_BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// A resolved stack frame. Frames compare and hash by object, SVMA and function names, so
+/// identical stacks can be aggregated.
+#[derive(Clone, Debug)]
+pub struct BacktraceFrame {
+    pub pc: usize,
+    pub object: Option<String>,
+    pub svma: Option<usize>,
+    /// The frames at `pc`, innermost first. More than one if calls were inlined.
+    pub symbols: Vec<PublicFrame>,
+    /// Whether the function of this frame was reached through a tail call, so that the frame
+    /// of the function its caller called is missing. Set by [`Backtrace::mark_tail_calls`].
+    pub tail_called: bool,
+}
+
+/// An owned backtrace, innermost frame first.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Backtrace {
+    pub frames: Vec<BacktraceFrame>,
+}
+
+impl BacktraceFrame {
+    fn names(&self) -> impl Iterator<Item = Option<&str>> {
+        self.symbols.iter().map(|x| x.name.as_deref())
+    }
+}
+
+impl PartialEq for BacktraceFrame {
+    fn eq(&self, other: &Self) -> bool {
+        self.object == other.object && self.svma == other.svma && self.names().eq(other.names())
+    }
+}
+
+impl Eq for BacktraceFrame {}
+
+impl Hash for BacktraceFrame {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.object.hash(state);
+        self.svma.hash(state);
+        self.names().for_each(|x| x.hash(state));
+    }
+}
+
+impl Backtrace {
+    /// Capture the stack of the current thread, starting at the caller. The functions of this
+    /// crate that do the capture are left out. Frames other than the innermost are resolved at
+    /// their calls, so their lines are those of the calls.
+    ///
+    /// The frames unwound so far are kept if a step fails midway, and the error is returned
+    /// alongside them. It is `None` if the end of the stack was reached.
+    ///
+    /// This is always inlined so that the registers are captured in the caller's frame.
+    #[inline(always)]
+    pub fn capture<'a>(g_ctx: &'a GlobalContext<'a>) -> (Self, Option<UnwindError>) {
+        let mut cursor = match DynamicCursor::<NativeState>::new(g_ctx) {
+            Ok(cursor) => cursor,
+            Err(e) => return (Self { frames: Vec::new() }, Some(e)),
+        };
+        let mut frames = Vec::new();
+        let error = loop {
+            let info = cursor.get_call_site_info();
+            frames.push(BacktraceFrame {
+                pc: info.avma,
+                object: info.object_name.map(str::to_string),
+                svma: info.svma,
+                symbols: info.public_frames(),
+                tail_called: false,
+            });
+            match cursor.next() {
+                Ok(()) => {}
+                Err(_) if cursor.stop_reason() == Some(StopReason::CleanEnd) => break None,
+                Err(e) => break Some(e),
+            }
+        };
+        (Self { frames }, error)
+    }
+
+    /// Flag the frames whose caller called another function, according to the call site info
+    /// of the caller. These mark where optimized code made tail calls, whose frames are gone.
+    pub fn mark_tail_calls(&mut self, g_ctx: &GlobalContext) {
+        for i in 1..self.frames.len() {
+            // Every frame but the innermost sits at a return address.
+            let pc = self.frames[i - 1].pc - (i > 1) as usize;
+            self.frames[i - 1].tail_called = g_ctx.is_tail_call_gap(pc, self.frames[i].pc);
+        }
+    }
+
+    /// Drop the hook and the panic runtime, so that the panicking function comes first.
+    fn skip_panic_runtime(&mut self) {
+        let is_runtime =
+            |frame: &BacktraceFrame| frame.symbols.iter().any(|x| matches_any(x, PANIC_RUNTIME));
+        // The runtime may show up again further down, e.g. in `catch_unwind`.
+        if let Some(start) = self.frames.iter().position(is_runtime) {
+            let end = self.frames[start..]
+                .iter()
+                .position(|x| !is_runtime(x))
+                .map_or(self.frames.len(), |x| start + x);
+            self.frames.drain(..end);
+        }
+    }
+}
+
+/// Whether the name of `frame` starts with one of `prefixes`. Trait implementations match by
+/// the implementing type.
+fn matches_any(frame: &PublicFrame, prefixes: &[&str]) -> bool {
+    frame.name.as_deref().is_some_and(|name| {
+        let name = without_disambiguators(name);
+        let name = name.trim_start_matches('<');
+        prefixes.iter().any(|p| name.starts_with(p))
+    })
+}
+
+/// Remove the crate disambiguators of v0 mangled names, e.g. `std[0123abcd]::panicking`.
+fn without_disambiguators(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut depth = 0usize;
+    for c in name.chars() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth > 0 => depth -= 1,
+            _ if depth == 0 => result.push(c),
+            _ => {}
+        }
+    }
+    result
+}
+
+impl Display for Backtrace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, frame) in self.frames.iter().enumerate() {
+            if frame.symbols.is_empty() {
+                writeln!(
+                    f,
+                    "{:4}: {:#x} in {}",
+                    i,
+                    frame.pc,
+                    frame.object.as_deref().unwrap_or("??")
+                )?;
+            }
+            for (j, symbol) in frame.symbols.iter().enumerate() {
+                let name = symbol.name.as_deref().unwrap_or("<unknown>");
+                if j == 0 {
+                    write!(f, "{:4}: {}", i, name)?;
+                } else {
+                    write!(f, "      {}", name)?;
+                }
+                match symbol.offset {
+                    Some(offset) => writeln!(f, "+{:#x}", offset)?,
+                    None => writeln!(f)?,
+                }
+                if let Some(file) = symbol.file.as_deref() {
+                    write!(f, "             at {}", file)?;
+                    if let Some(line) = symbol.line {
+                        write!(f, ":{}", line)?;
+                        if let Some(column) = symbol.column {
+                            write!(f, ":{}", column)?;
+                        }
+                    }
+                    writeln!(f)?;
+                }
+            }
+            if frame.tail_called {
+                writeln!(f, "      [tail call]")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Install a panic hook capturing the backtrace of the panicking thread. The previous hook
+/// still runs afterwards. Retrieve the backtrace with [`take_panic_backtrace`].
+///
+/// The images of the process are loaded once, at the first panic, and shared by the panics of
+/// all threads after it. Code of libraries loaded after the first panic is not resolved.
+pub fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let g_ctx = PANIC_CONTEXT.get_or_init(GlobalContext::new);
+        // A truncated backtrace still holds the panicking frames.
+        let (mut backtrace, _) = Backtrace::capture(g_ctx);
+        backtrace.skip_panic_runtime();
+        PANIC_BACKTRACE.with(|x| *x.borrow_mut() = Some(backtrace));
+        previous(info)
+    }));
+}
+
+/// The backtrace of the last panic on this thread captured by the hook of
+/// [`install_panic_hook`].
+pub fn take_panic_backtrace() -> Option<Backtrace> {
+    PANIC_BACKTRACE.with(|x| x.borrow_mut().take())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        install_panic_hook, take_panic_backtrace, Backtrace, BacktraceFrame, PANIC_CONTEXT,
+    };
+    use crate::{FrameKind, PublicFrame};
+
+    fn frame(name: &str) -> BacktraceFrame {
+        BacktraceFrame {
+            pc: 0,
+            object: None,
+            svma: None,
+            symbols: vec![PublicFrame {
+                name: Some(name.to_string()),
+                file: None,
+                line: None,
+                column: None,
+                discriminator: None,
+                function_start: None,
+                function_size: None,
+                offset: None,
+                call_file: None,
+                call_line: None,
+                call_column: None,
+                language: None,
+                kind: FrameKind::SymbolMap,
+            }],
+            tail_called: false,
+        }
+    }
+
+    #[inline(never)]
+    fn panicking_function(x: usize) -> usize {
+        if std::hint::black_box(x) > 0 {
+            panic!("expected panic in test");
+        }
+        x
+    }
+
+    #[test]
+    fn it_skips_panic_runtime() {
+        let mut backtrace = Backtrace {
+            frames: [
+                "unwinder::backtrace::install_panic_hook::{{closure}}",
+                "std[e28293b1aa0f68bd]::panicking::panic_with_hook",
+                "core::panicking::panic_fmt",
+                "app::parse",
+                "std::panicking::catch_unwind",
+                "app::main",
+            ]
+            .into_iter()
+            .map(frame)
+            .collect(),
+        };
+        backtrace.skip_panic_runtime();
+        assert_eq!(backtrace.frames.len(), 3);
+        assert_eq!(
+            backtrace.frames[0].symbols[0].name.as_deref(),
+            Some("app::parse")
+        );
+    }
+
+    #[test]
+    fn it_skips_capture_path() {
+        let g = crate::GlobalContext::new();
+        let (backtrace, error) = Backtrace::capture(&g);
+        assert!(error.is_none(), "{:?}", error);
+        let name = backtrace.frames[0].symbols[0].name.as_deref().unwrap();
+        assert!(name.contains("it_skips_capture_path"), "{}", name);
+    }
+
+    #[test]
+    fn it_captures_panic_backtrace() {
+        install_panic_hook();
+        assert!(std::panic::catch_unwind(|| panicking_function(1)).is_err());
+        let backtrace = take_panic_backtrace().unwrap();
+        let first = backtrace.frames.first().unwrap();
+        assert!(first.symbols.iter().any(|x| x
+            .name
+            .as_deref()
+            .unwrap_or_default()
+            .contains("panicking_function")));
+        assert!(backtrace.to_string().contains("panicking_function"));
+        assert!(take_panic_backtrace().is_none());
+
+        // Later panics, on any thread, resolve through the context of the first.
+        let context = PANIC_CONTEXT.get().unwrap() as *const _;
+        std::thread::spawn(|| std::panic::catch_unwind(|| panicking_function(2)))
+            .join()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(PANIC_CONTEXT.get().unwrap() as *const _, context);
+    }
+
+    #[test]
+    fn it_aggregates_identical_backtraces() {
+        let g = crate::GlobalContext::new();
+        let mut counts = std::collections::HashMap::new();
+        for _ in 0..2 {
+            *counts.entry(Backtrace::capture(&g).0).or_insert(0u64) += 1;
+        }
+        *counts.entry(Backtrace::capture(&g).0).or_insert(0u64) += 1;
+        let mut values: Vec<_> = counts.into_values().collect();
+        values.sort();
+        assert_eq!(values, [1, 2]);
+    }
+
+    #[test]
+    fn it_keeps_frames_of_truncated_backtraces() {
+        use crate::tests::Fixture;
+        use std::cell::RefCell;
+
+        thread_local! {
+            static CAPTURED: RefCell<Option<(Backtrace, Option<crate::UnwindError>)>> =
+                const { RefCell::new(None) };
+        }
+
+        #[inline(never)]
+        extern "C" fn truncated_callback() {
+            let g = crate::GlobalContext::new();
+            let captured = Backtrace::capture(&g);
+            CAPTURED.with(|x| *x.borrow_mut() = Some(captured));
+        }
+
+        // A caller without CFI, which the unwinder cannot step through.
+        let source = "void relay(void (*f)(void)) { f(); }\n";
+        let flags = [
+            "-O0",
+            "-fno-asynchronous-unwind-tables",
+            "-fno-unwind-tables",
+        ];
+        let fixture = match Fixture::load("relay", source, &flags) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let relay: extern "C" fn(extern "C" fn()) =
+            unsafe { std::mem::transmute(fixture.symbol("relay")) };
+        relay(truncated_callback);
+
+        let (backtrace, error) = CAPTURED.with(|x| x.borrow_mut().take()).unwrap();
+        assert!(error.is_some());
+        let name = backtrace.frames[0].symbols[0].name.as_deref().unwrap();
+        assert!(name.contains("truncated_callback"), "{}", name);
+        let last = backtrace.frames.last().unwrap();
+        assert_eq!(last.object.as_deref(), fixture.path.to_str());
+    }
+
+    #[cfg(feature = "line-info")]
+    #[test]
+    fn it_marks_tail_calls() {
+        use crate::tests::Fixture;
+        use std::cell::RefCell;
+
+        thread_local! {
+            static CAPTURED: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+        }
+
+        #[inline(never)]
+        extern "C" fn tail_call_callback() {
+            let g = crate::GlobalContext::new();
+            let (mut backtrace, _) = Backtrace::capture(&g);
+            backtrace.mark_tail_calls(&g);
+            CAPTURED.with(|x| *x.borrow_mut() = Some(backtrace));
+        }
+
+        // `middle` jumps to `leaf`, so `outer` returns from `leaf` without `middle` in between.
+        let source = "volatile int counter;\n\
+            __attribute__((noinline, noclone)) void leaf(void (*f)(void)) { f(); counter++; }\n\
+            __attribute__((noinline, noclone)) void middle(void (*f)(void)) { counter++; leaf(f); }\n\
+            void outer(void (*f)(void)) { middle(f); counter++; }\n";
+        let fixture = match Fixture::load("tail_call", source, &["-O2", "-g"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let outer: extern "C" fn(extern "C" fn()) =
+            unsafe { std::mem::transmute(fixture.symbol("outer")) };
+        outer(tail_call_callback);
+
+        let backtrace = CAPTURED.with(|x| x.borrow_mut().take()).unwrap();
+        let names: Vec<_> = backtrace
+            .frames
+            .iter()
+            .map(|x| x.symbols.last().and_then(|x| x.name.clone()))
+            .collect();
+        let leaf = names
+            .iter()
+            .position(|x| x.as_deref() == Some("leaf"))
+            .unwrap();
+        assert_eq!(names[leaf + 1].as_deref(), Some("outer"), "{:?}", names);
+        let marked: Vec<_> = backtrace.frames.iter().map(|x| x.tail_called).collect();
+        assert!(marked[leaf], "{:?}", names);
+        assert_eq!(marked.iter().filter(|x| **x).count(), 1, "{:?}", names);
+        assert!(backtrace.to_string().contains("[tail call]"));
+    }
+}
@@ -1,8 +1,182 @@
+#[cfg_attr(
+    any(target_arch = "arm", target_arch = "wasm32", target_os = "none"),
+    allow(unused_imports)
+)]
 pub use libc::*;
 
+/// There is no `ucontext_t` on WebAssembly or bare metal. This stand-in keeps the cursor API in
+/// place; no cursor can be created from one.
+#[cfg(any(target_arch = "wasm32", target_os = "none"))]
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug)]
+pub struct ucontext_t {
+    _private: (),
+}
+
 #[cfg(target_os = "macos")]
 extern "C" {
     // XXX: Deprecated on macOS actually, do not expect this to run.  If it
     // doesn't, use some easy assembly to retrieve the registers.
     pub fn getcontext(ucp: *mut libc::ucontext_t) -> libc::c_int;
 }
+
+#[cfg(all(target_os = "linux", target_arch = "arm"))]
+extern "C" {
+    // The libc crate does not declare it for 32-bit ARM, though glibc provides it.
+    pub fn getcontext(ucp: *mut libc::ucontext_t) -> libc::c_int;
+}
+
+/// musl has no `getcontext`, so capture the registers with inline assembly instead. Being
+/// inlined, this captures the frame of the caller at the point of the call. The registers an
+/// unwinder needs are filled in: the program counter, the stack pointer and the callee-saved
+/// registers; the others are left untouched.
+///
+/// # Safety
+///
+/// `ucp` must be valid for writes.
+#[cfg(all(target_os = "linux", target_env = "musl", target_arch = "x86_64"))]
+#[inline(always)]
+pub unsafe fn getcontext(ucp: *mut libc::ucontext_t) -> libc::c_int {
+    // Every register is stored through fixed caller-saved registers, so that none of the
+    // captured ones is clobbered by an operand before it is saved.
+    core::arch::asm!(
+        "lea rax, [rip]",
+        "mov [rdi + {rip}], rax",
+        "mov [rdi + {rsp}], rsp",
+        "mov [rdi + {rbp}], rbp",
+        "mov [rdi + {rbx}], rbx",
+        "mov [rdi + {r12}], r12",
+        "mov [rdi + {r13}], r13",
+        "mov [rdi + {r14}], r14",
+        "mov [rdi + {r15}], r15",
+        in("rdi") (*ucp).uc_mcontext.gregs.as_mut_ptr(),
+        out("rax") _,
+        rip = const 8 * libc::REG_RIP as usize,
+        rsp = const 8 * libc::REG_RSP as usize,
+        rbp = const 8 * libc::REG_RBP as usize,
+        rbx = const 8 * libc::REG_RBX as usize,
+        r12 = const 8 * libc::REG_R12 as usize,
+        r13 = const 8 * libc::REG_R13 as usize,
+        r14 = const 8 * libc::REG_R14 as usize,
+        r15 = const 8 * libc::REG_R15 as usize,
+        options(nostack, preserves_flags),
+    );
+    0
+}
+
+/// Return the address the call to this function returns to, i.e. the program counter of the
+/// caller right after the call instruction.
+#[cfg(target_arch = "x86_64")]
+#[unsafe(naked)]
+pub extern "C" fn return_address() -> usize {
+    core::arch::naked_asm!("mov rax, [rsp]", "ret")
+}
+
+#[cfg(target_arch = "aarch64")]
+#[unsafe(naked)]
+pub extern "C" fn return_address() -> usize {
+    core::arch::naked_asm!("mov x0, x30", "ret")
+}
+
+#[cfg(target_arch = "arm")]
+#[unsafe(naked)]
+pub extern "C" fn return_address() -> usize {
+    core::arch::naked_asm!("mov r0, lr", "bx lr")
+}
+
+/// Return the address range of the calling thread's stack. It is only looked up once per
+/// thread, as `pthread_getattr_np` parses `/proc/self/maps` for the main thread.
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub fn stack_bounds() -> Result<std::ops::Range<usize>, nix::errno::Errno> {
+    type Bounds = Result<std::ops::Range<usize>, nix::errno::Errno>;
+    thread_local! {
+        static STACK_BOUNDS: std::cell::OnceCell<Bounds> = const { std::cell::OnceCell::new() };
+    }
+    STACK_BOUNDS.with(|x| x.get_or_init(read_stack_bounds).clone())
+}
+
+#[cfg(all(feature = "std", target_os = "linux"))]
+fn read_stack_bounds() -> Result<std::ops::Range<usize>, nix::errno::Errno> {
+    let mut attr = std::mem::MaybeUninit::<libc::pthread_attr_t>::uninit();
+    let ret = unsafe { libc::pthread_getattr_np(libc::pthread_self(), attr.as_mut_ptr()) };
+    if ret != 0 {
+        return Err(nix::errno::Errno::from_i32(ret));
+    }
+    let mut addr = std::ptr::null_mut();
+    let mut size = 0;
+    let ret = unsafe { libc::pthread_attr_getstack(attr.as_ptr(), &mut addr, &mut size) };
+    unsafe { libc::pthread_attr_destroy(attr.as_mut_ptr()) };
+    if ret != 0 {
+        return Err(nix::errno::Errno::from_i32(ret));
+    }
+    Ok(addr as usize..addr as usize + size)
+}
+
+/// Return the pid of the process without a syscall per call. It is looked up again in a forked
+/// child, which would otherwise read the memory of its parent.
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub fn pid() -> libc::pid_t {
+    use std::sync::atomic::{AtomicI32, Ordering};
+    static PID: AtomicI32 = AtomicI32::new(0);
+    static AT_FORK: std::sync::Once = std::sync::Once::new();
+    extern "C" fn forget() {
+        PID.store(0, Ordering::Relaxed);
+    }
+    match PID.load(Ordering::Relaxed) {
+        0 => {
+            AT_FORK.call_once(|| unsafe {
+                libc::pthread_atfork(None, None, Some(forget));
+            });
+            let pid = unsafe { libc::getpid() };
+            PID.store(pid, Ordering::Relaxed);
+            pid
+        }
+        pid => pid,
+    }
+}
+
+#[cfg(all(feature = "std", target_os = "macos"))]
+pub fn stack_bounds() -> Result<std::ops::Range<usize>, nix::errno::Errno> {
+    // The stack address is the top of the stack, which grows down from it.
+    let thread = unsafe { libc::pthread_self() };
+    let top = unsafe { libc::pthread_get_stackaddr_np(thread) } as usize;
+    let size = unsafe { libc::pthread_get_stacksize_np(thread) };
+    Ok(top - size..top)
+}
+
+#[cfg(all(feature = "std", target_os = "linux", target_arch = "x86_64"))]
+const ARCH_GET_FS: libc::c_int = 0x1003;
+#[cfg(all(feature = "std", target_os = "linux", target_arch = "x86_64"))]
+const ARCH_GET_GS: libc::c_int = 0x1004;
+
+#[cfg(all(feature = "std", target_os = "linux", target_arch = "x86_64"))]
+fn arch_prctl_get(code: libc::c_int) -> Result<usize, nix::errno::Errno> {
+    let mut base = 0usize;
+    let ret = unsafe { libc::syscall(libc::SYS_arch_prctl, code, &mut base) };
+    nix::errno::Errno::result(ret).map(|_| base)
+}
+
+/// Return the thread pointer of the calling thread, i.e. the base of its TLS block:
+/// `fs_base` on x86_64, `tpidr_el0` on AArch64 and `TPIDRURO` on ARM.
+#[cfg(all(feature = "std", target_os = "linux", target_arch = "x86_64"))]
+pub fn thread_pointer() -> Result<usize, nix::errno::Errno> {
+    arch_prctl_get(ARCH_GET_FS)
+}
+
+/// Return the `gs_base` of the calling thread. User space rarely sets it, so it is usually 0.
+#[cfg(all(feature = "std", target_os = "linux", target_arch = "x86_64"))]
+pub fn gs_base() -> Result<usize, nix::errno::Errno> {
+    arch_prctl_get(ARCH_GET_GS)
+}
+
+#[cfg(target_arch = "aarch64")]
+#[unsafe(naked)]
+pub extern "C" fn thread_pointer() -> usize {
+    core::arch::naked_asm!("mrs x0, tpidr_el0", "ret")
+}
+
+#[cfg(target_arch = "arm")]
+#[unsafe(naked)]
+pub extern "C" fn thread_pointer() -> usize {
+    core::arch::naked_asm!("mrc p15, 0, r0, c13, c0, 3", "bx lr")
+}
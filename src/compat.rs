@@ -0,0 +1,131 @@
+//! Adapters to the types of the `backtrace` crate, so that code written against it can consume
+//! backtraces of this crate.
+use crate::backtrace::{Backtrace, BacktraceFrame};
+use crate::PublicFrame;
+use ::backtrace::{BacktraceFmt, BytesOrWideString, SymbolName};
+use std::ffi::c_void;
+use std::path::Path;
+
+/// A resolved frame with the accessors of `backtrace::Symbol`.
+#[derive(Clone, Copy, Debug)]
+pub struct Symbol<'a> {
+    pc: usize,
+    frame: &'a PublicFrame,
+}
+
+impl<'a> Symbol<'a> {
+    pub fn name(&self) -> Option<SymbolName<'a>> {
+        self.frame
+            .name
+            .as_deref()
+            .map(|x| SymbolName::new(x.as_bytes()))
+    }
+
+    /// The start of the function, if known. Only the outermost frame of an address has one.
+    pub fn addr(&self) -> Option<*mut c_void> {
+        self.frame.function_start.map(|x| x as *mut c_void)
+    }
+
+    pub fn filename_raw(&self) -> Option<BytesOrWideString<'a>> {
+        self.frame
+            .file
+            .as_deref()
+            .map(|x| BytesOrWideString::Bytes(x.as_bytes()))
+    }
+
+    pub fn filename(&self) -> Option<&'a Path> {
+        self.frame.file.as_deref().map(Path::new)
+    }
+
+    pub fn lineno(&self) -> Option<u32> {
+        self.frame.line
+    }
+
+    pub fn colno(&self) -> Option<u32> {
+        self.frame.column
+    }
+
+    /// The program counter of the frame the symbol was resolved at.
+    pub fn ip(&self) -> *mut c_void {
+        self.pc as *mut c_void
+    }
+}
+
+impl BacktraceFrame {
+    /// The symbols of the frame, innermost first, like `backtrace::BacktraceFrame::symbols`.
+    pub fn compat_symbols(&self) -> impl Iterator<Item = Symbol<'_>> {
+        self.symbols
+            .iter()
+            .map(|frame| Symbol { pc: self.pc, frame })
+    }
+}
+
+impl Backtrace {
+    /// Print the backtrace with a formatter of the `backtrace` crate, so that existing output
+    /// styles apply unchanged. The caller adds any preamble and calls `finish`.
+    pub fn fmt_with(&self, fmt: &mut BacktraceFmt<'_, '_>) -> std::fmt::Result {
+        for frame in &self.frames {
+            // Inlined symbols share the frame, and so its index.
+            let mut frame_fmt = fmt.frame();
+            if frame.symbols.is_empty() {
+                frame_fmt.print_raw(frame.pc as *mut c_void, None, None, None)?;
+            }
+            for symbol in frame.compat_symbols() {
+                frame_fmt.print_raw_with_column(
+                    symbol.ip(),
+                    symbol.name(),
+                    symbol.filename_raw(),
+                    symbol.lineno(),
+                    symbol.colno(),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::backtrace::Backtrace;
+    use ::backtrace::{BacktraceFmt, BytesOrWideString, PrintFmt};
+    use std::fmt::{Display, Formatter};
+
+    struct Printed<'a>(&'a Backtrace);
+
+    impl Display for Printed<'_> {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            let mut print_path =
+                |f: &mut Formatter<'_>, path: BytesOrWideString<'_>| Display::fmt(&path, f);
+            let mut fmt = BacktraceFmt::new(f, PrintFmt::Full, &mut print_path);
+            fmt.add_context()?;
+            self.0.fmt_with(&mut fmt)?;
+            fmt.finish()
+        }
+    }
+
+    #[test]
+    fn it_adapts_to_backtrace_crate() {
+        let g = crate::GlobalContext::new();
+        let (backtrace, _) = Backtrace::capture(&g);
+        let frame = &backtrace.frames[0];
+        let symbol = frame.compat_symbols().next().unwrap();
+        let name = symbol.name().unwrap();
+        assert!(name
+            .as_str()
+            .unwrap()
+            .contains("it_adapts_to_backtrace_crate"));
+        assert_eq!(name.as_str(), frame.symbols[0].name.as_deref());
+        assert!(symbol.filename().unwrap().ends_with("src/compat.rs"));
+        assert_eq!(symbol.lineno(), frame.symbols[0].line);
+        assert!(symbol.lineno().is_some());
+        assert_eq!(symbol.ip() as usize, frame.pc);
+
+        let printed = Printed(&backtrace).to_string();
+        assert!(
+            printed.contains("it_adapts_to_backtrace_crate"),
+            "{}",
+            printed
+        );
+        assert!(printed.contains("src/compat.rs"), "{}", printed);
+    }
+}
@@ -1,110 +1,378 @@
 use crate::cursor::state::CursorState;
 use crate::image::ImageReader;
-use crate::{cffi, GlobalContext, SymbolInfo, UnwindError};
+use crate::{cffi, GlobalContext, ModuleAddress, SymbolInfo, UnwindError};
+use alloc::string::String;
+use alloc::vec::Vec;
 use gimli::{
-    Reader, Register, RegisterRule, StoreOnHeap, UnwindContext, UnwindContextStorage,
-    UnwindSection, UnwindTableRow,
+    CfaRule, Reader, Register, RegisterRule, StoreOnHeap, UnwindContext, UnwindContextStorage,
+    UnwindTableRow,
 };
-use libc::ucontext_t;
-use nix::errno::Errno;
-use std::borrow::Borrow;
-use std::mem::MaybeUninit;
 
-mod state;
+pub use target::{LocalTarget, Target, UncheckedLocalTarget};
 
-struct InlineStorage;
+pub mod state;
+mod target;
+
+pub use state::VariableLocation;
+
+pub struct InlineStorage;
 
 impl<R: Reader> UnwindContextStorage<R> for InlineStorage {
     type Rules = [(Register, RegisterRule<R>); 192];
     type Stack = [UnwindTableRow<R, Self>; 32];
 }
 
-struct UnwindCursor<'a, Storage, State>
+/// How the cursor steps from a frame to its caller.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnwindPolicy {
+    /// Only follow the unwind info of the images.
+    #[default]
+    DwarfOnly,
+    /// Follow the unwind info, but walk the frame pointer chain where it is missing or fails.
+    FramePointerFallback,
+    /// Only walk the frame pointer chain. This requires all code on the stack to keep frame
+    /// pointers.
+    FramePointerOnly,
+}
+
+/// What the cursor does when the program counter lies outside all images, e.g. in JIT code
+/// that was never registered or in a module that failed to load.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnknownPcPolicy {
+    /// Fail the step with [`UnwindError::UnknownProgramCounter`].
+    #[default]
+    Error,
+    /// Keep the current frame as an anonymous one, and end the unwind after it with
+    /// [`UnwindError::UnwindEnded`].
+    Stop,
+}
+
+/// Why the cursor stopped stepping, telling complete stacks from truncated ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// The end of the stack was reached: the unwind info marks the outermost frame, the
+    /// caller's program counter is null, or a step leaves the frame unchanged.
+    CleanEnd,
+    /// The program counter lies outside all images.
+    UnknownProgramCounter(usize),
+    /// The image of the frame has no unwind info covering it.
+    MissingUnwindInfo,
+    /// The unwind info could not be followed, e.g. it is malformed or its operations are not
+    /// supported.
+    InvalidUnwindInfo,
+    /// The recovered state is inconsistent, e.g. a frame pointer below the stack pointer.
+    LogicalError(&'static str),
+    /// The state or the target lacks a capability the step needs.
+    NotSupported(&'static str),
+    /// Reading the target or a file failed.
+    SystemError,
+}
+
+impl StopReason {
+    /// Classify the error a step failed with while the cursor is at `pc`.
+    fn new(error: &UnwindError, pc: usize) -> Self {
+        match error {
+            UnwindError::UnwindEnded => StopReason::CleanEnd,
+            _ if pc == 0 => StopReason::CleanEnd,
+            UnwindError::UnknownProgramCounter(pc) => StopReason::UnknownProgramCounter(*pc),
+            UnwindError::GimliError(gimli::Error::NoUnwindInfoForAddress) => {
+                StopReason::MissingUnwindInfo
+            }
+            UnwindError::GimliError(_) | UnwindError::ObjectParsingError(_) => {
+                StopReason::InvalidUnwindInfo
+            }
+            UnwindError::UnwindLogicalError(s) => StopReason::LogicalError(s),
+            UnwindError::NotSupported(s) => StopReason::NotSupported(s),
+            #[cfg(feature = "std")]
+            _ => StopReason::SystemError,
+        }
+    }
+}
+
+/// The unwind info a DWARF step followed, recorded when tracing is enabled with
+/// [`UnwindCursor::with_step_trace`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepTrace<'a> {
+    /// The program counter of the frame stepped out of.
+    pub pc: usize,
+    /// The AVMAs covered by the FDE matching the program counter.
+    pub fde: core::ops::Range<usize>,
+    /// The rule the CFA was computed with.
+    pub cfa: CfaRule<ImageReader<'a>>,
+    /// The rule the return address was recovered with.
+    pub return_address: RegisterRule<ImageReader<'a>>,
+}
+
+/// A variable or parameter in scope at the program counter of a frame, found by
+/// [`Unwinding::locals`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Local {
+    pub name: Option<String>,
+    /// Whether this is a parameter of the function, or of a function inlined into it.
+    pub parameter: bool,
+    /// Where the variable lives. `None` if it has no location at the program counter, e.g. it
+    /// was optimized out, or if its location cannot be evaluated with the registers known in
+    /// the frame.
+    pub location: Option<VariableLocation>,
+}
+
+/// The walk of a cursor at a frame, taken by [`UnwindCursor::snapshot`], to be continued later
+/// with [`GlobalContext::resume`], e.g. by a profiler bounding the time spent per sample.
+///
+/// It holds no reference to the context or the target: resuming needs a context with the same
+/// images loaded, and the stack must not have changed in the meantime.
+#[derive(Clone, Debug)]
+pub struct CursorSnapshot<State> {
+    /// The register file of the current frame.
+    pub state: State,
+    /// See [`Unwinding::cfa`].
+    pub cfa: Option<usize>,
+    pub policy: UnwindPolicy,
+    pub unknown_pc_policy: UnknownPcPolicy,
+    /// See [`UnwindCursor::heuristic`].
+    pub heuristic: bool,
+    /// See [`UnwindCursor::captured`].
+    pub captured: bool,
+    pub stack_bounds: Option<core::ops::Range<usize>>,
+    /// See [`UnwindCursor::stop_reason`].
+    pub stop_reason: Option<StopReason>,
+    pub trace_steps: bool,
+    pub verify_calls: bool,
+}
+
+pub struct UnwindCursor<'a, Storage, State, T = LocalTarget>
 where
     Storage: UnwindContextStorage<ImageReader<'a>>,
     State: CursorState,
+    T: Target,
 {
     global_ctx: &'a GlobalContext<'a>,
     local_ctx: UnwindContext<ImageReader<'a>, Storage>,
     state: State,
+    target: T,
+    cfa: Option<usize>,
+    policy: UnwindPolicy,
+    unknown_pc_policy: UnknownPcPolicy,
+    heuristic: bool,
+    captured: bool,
+    stack_bounds: Option<core::ops::Range<usize>>,
+    stop_reason: Option<StopReason>,
+    trace_steps: bool,
+    last_step_trace: Option<StepTrace<'a>>,
+    verify_calls: bool,
 }
 
-trait Unwinding<'a, Storage, State>: Sized
+/// The stepping API of a cursor: the frame it is at, how it is resolved, and the step to its
+/// caller. How the steps are taken is configured on [`UnwindCursor`].
+pub trait Unwinding<'a, Storage, State>: Sized
 where
     Storage: UnwindContextStorage<ImageReader<'a>>,
     State: CursorState,
 {
+    type Target: Target;
+
     fn local_context_mut(&mut self) -> &mut UnwindContext<ImageReader<'a>, Storage>;
     fn state_mut(&mut self) -> &mut State;
     fn local_context(&self) -> &UnwindContext<ImageReader<'a>, Storage>;
     fn state(&self) -> &State;
     fn global_context(&self) -> &'a GlobalContext<'a>;
+    fn target(&self) -> &Self::Target;
+    /// The CFA of the frame most recently stepped out of, i.e. the stack pointer of the
+    /// current frame right before its call. `None` until the first step.
+    fn cfa(&self) -> Option<usize>;
+
+    fn from_parts(g_ctx: &'a GlobalContext<'a>, target: Self::Target, state: State) -> Self;
 
-    fn new(g_ctx: &'a GlobalContext<'a>) -> Result<Self, UnwindError> {
-        let mut ucp = MaybeUninit::<libc::ucontext_t>::zeroed();
-        let ret = unsafe { cffi::getcontext(ucp.as_mut_ptr()) };
-        let ctx = unsafe { ucp.assume_init() };
-        Errno::result(ret)
-            .map(|_| Self::from_ucontext(g_ctx, ctx))
-            .map_err(Into::into)
+    /// Start unwinding from the registers captured by `target`.
+    fn with_target(g_ctx: &'a GlobalContext<'a>, target: Self::Target)
+        -> Result<Self, UnwindError>;
+
+    /// Resolve the current frame. The registers of a cursor created by [`Unwinding::new`] or
+    /// [`Unwinding::with_target`] are captured within the inlined constructor, so the program
+    /// counter lies in the caller; the inlined frames of the constructor itself are left out,
+    /// and the first frame is the caller's.
+    fn get_sym_info(&self) -> SymbolInfo<'a>;
+
+    /// Step to the caller. When this fails, the cursor stays at the current frame and
+    /// [`UnwindCursor::stop_reason`] tells why.
+    fn next(&mut self) -> Result<(), UnwindError>;
+
+    /// The TLS base of the thread being unwound. It does not change while unwinding.
+    fn tls_base(&self) -> Result<usize, UnwindError> {
+        self.target().tls_base()
     }
 
-    fn from_ucontext(g_ctx: &'a GlobalContext<'a>, u_ctx: libc::ucontext_t) -> Self;
+    /// The register file of the current frame.
+    fn registers(&self) -> &State {
+        self.state()
+    }
 
-    fn get_sym_info(&self) -> SymbolInfo<'a> {
+    #[inline(always)]
+    fn new(g_ctx: &'a GlobalContext<'a>) -> Result<Self, UnwindError>
+    where
+        Self::Target: Default,
+    {
+        Self::with_target(g_ctx, Default::default())
+    }
+
+    fn from_ucontext(g_ctx: &'a GlobalContext<'a>, u_ctx: cffi::ucontext_t) -> Self
+    where
+        Self::Target: Default,
+    {
+        Self::from_parts(g_ctx, Default::default(), State::new(&u_ctx))
+    }
+
+    /// Start unwinding at the given program counter and stack pointer, e.g. from a stack
+    /// captured elsewhere.
+    fn from_pc_sp(g_ctx: &'a GlobalContext<'a>, pc: usize, sp: usize) -> Self
+    where
+        Self::Target: Default,
+    {
+        Self::from_parts(g_ctx, Default::default(), State::from_pc_sp(pc, sp))
+    }
+
+    /// Like [`Unwinding::from_pc_sp`], also given the frame pointer.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn from_pc_sp_fp(g_ctx: &'a GlobalContext<'a>, pc: usize, sp: usize, fp: usize) -> Self
+    where
+        Self::Target: Default,
+    {
+        Self::from_parts(g_ctx, Default::default(), State::from_pc_sp_fp(pc, sp, fp))
+    }
+
+    /// Like [`Unwinding::get_sym_info`], but a frame reached by a step, whose program counter
+    /// is a return address, is resolved at its call with
+    /// [`GlobalContext::resolve_call_site`], so its line is the one of the call.
+    fn get_call_site_info(&self) -> SymbolInfo<'a> {
+        if self.cfa().is_none() {
+            return self.get_sym_info();
+        }
         self.global_context()
-            .resolve_symbol(self.state().get_program_counter())
+            .resolve_call_site(self.state().get_program_counter())
+    }
+    /// The module and SVMA of the current frame, for symbolizing it elsewhere. Nothing is
+    /// resolved, so this is much cheaper than [`Unwinding::get_sym_info`].
+    fn get_module_address(&self) -> Option<ModuleAddress<'a>> {
+        self.global_context()
+            .module_address(self.state().get_program_counter())
     }
 
+    /// Evaluate the unwind info of the current frame, returning the row along with the return
+    /// address column of its CIE.
     fn setup_unwind_info(
         &mut self,
-    ) -> Result<&UnwindTableRow<ImageReader<'a>, Storage>, UnwindError> {
+    ) -> Result<(&UnwindTableRow<ImageReader<'a>, Storage>, Register), UnwindError> {
         let pc = self.state().get_program_counter();
-        if let Some(img) = self.global_context().find_image(pc) {
-            let svma = pc as u64 - img.bias as u64;
-            if let Some(table) = img.eh_frame_hdr_section.as_ref().and_then(|x| x.1.table()) {
-                table
-                    .unwind_info_for_address(
-                        &img.eh_frame_section.1,
-                        &img.base_addresses,
-                        self.local_context_mut(),
-                        svma,
-                        gimli::EhFrame::cie_from_offset,
-                    )
-                    .map_err(Into::into)
-            } else {
-                img.eh_frame_section
-                    .1
-                    .unwind_info_for_address(
-                        &img.base_addresses,
-                        self.local_context_mut(),
-                        svma,
-                        gimli::EhFrame::cie_from_offset,
-                    )
-                    .map_err(Into::into)
-            }
+        // Past the innermost frame, the program counter is a return address. It may lie right
+        // after the function if the call does not return, so look up the call instead.
+        let address = if self.cfa().is_some() {
+            pc.saturating_sub(1)
+        } else {
+            pc
+        };
+        if let Some(img) = self.global_context().find_image(address) {
+            let svma = img.avma_to_svma(address);
+            img.unwind_info_for_address(self.local_context_mut(), svma)
+                .map_err(Into::into)
         } else {
             Result::Err(UnwindError::UnknownProgramCounter(pc))
         }
     }
 
-    fn next(&mut self) -> Result<(), UnwindError> {
-        let mut state = *self.state();
-        {
-            let context = self.global_context().borrow();
-            let unwind_info = self.setup_unwind_info()?;
-            state.step(&unwind_info, context)?;
-        }
-        *self.state_mut() = state;
-        Ok(())
+    /// The AVMAs covered by the FDE of the current frame, looked up like in
+    /// [`Unwinding::setup_unwind_info`].
+    fn fde_range(&self) -> Option<core::ops::Range<usize>> {
+        let pc = self.state().get_program_counter();
+        let address = if self.cfa().is_some() {
+            pc.saturating_sub(1)
+        } else {
+            pc
+        };
+        let img = self.global_context().find_image(address)?;
+        let fde = img.fde_range(img.avma_to_svma(address))?;
+        Some(img.svma_to_avma(fde.start)..img.svma_to_avma(fde.end))
+    }
+
+    /// The variables and parameters in scope at the program counter of the current frame,
+    /// from its debug info, outermost scope first. Locations are evaluated with the registers
+    /// of the frame, so past the innermost frame only variables kept in memory or in
+    /// callee-saved registers are found.
+    fn locals(&mut self) -> Result<Vec<Local>, UnwindError> {
+        let pc = self.state().get_program_counter();
+        let address = if self.cfa().is_some() {
+            pc.saturating_sub(1)
+        } else {
+            pc
+        };
+        let g_ctx = self.global_context();
+        let img = g_ctx
+            .find_image(address)
+            .ok_or(UnwindError::UnknownProgramCounter(pc))?;
+        let svma = img.avma_to_svma(address);
+        let state = *self.state();
+        let target = self.target().clone();
+        // The CFA of this frame, which is only computed when stepping out of it.
+        let cfa = self
+            .setup_unwind_info()
+            .and_then(|(row, _)| state.get_cfa(row, g_ctx, &target))
+            .ok();
+        let dwarf = img.dwarf();
+        let unit = img.find_unit(svma).ok_or(UnwindError::NotSupported(
+            "no debug info covers the program counter",
+        ))?;
+        let scope = img.scope_at(&unit, svma);
+        let mut environment = state::Environment {
+            encoding: unit.encoding(),
+            unit: Some(&unit),
+            dwarf: Some(&dwarf),
+            initial_value: None,
+            cfa: cfa.map(|x| x as u64),
+            frame_base: None,
+            bias: Some(img.bias as u64),
+        };
+        let evaluate = |expr, environment: &state::Environment<_>| {
+            state::evaluate_location(&state, expr, environment, &target).ok()
+        };
+        environment.frame_base = scope
+            .frame_base
+            .as_ref()
+            .and_then(|x| evaluate(x, &environment))
+            .map(|(VariableLocation::Address(x) | VariableLocation::Value(x))| x);
+        Ok(scope
+            .variables
+            .iter()
+            .map(|x| Local {
+                name: x.name.map(String::from),
+                parameter: x.parameter,
+                location: x.location.as_ref().and_then(|x| evaluate(x, &environment)),
+            })
+            .collect())
+    }
+
+    /// Find the EHABI unwind instructions for the current frame. `None` means the image has
+    /// no `.ARM.exidx` and the DWARF path should be taken.
+    #[cfg(target_arch = "arm")]
+    fn setup_exidx_info(&self) -> Result<Option<crate::image::exidx::Instructions>, UnwindError> {
+        let pc = self.state().get_program_counter();
+        let img = self
+            .global_context()
+            .find_image(pc)
+            .ok_or(UnwindError::UnknownProgramCounter(pc))?;
+        img.exidx
+            .as_ref()
+            .map(|x| x.lookup(img.avma_to_svma(pc)))
+            .transpose()
     }
 }
 
-impl<'a, Storage, State> Unwinding<'a, Storage, State> for UnwindCursor<'a, Storage, State>
+impl<'a, Storage, State, T> Unwinding<'a, Storage, State> for UnwindCursor<'a, Storage, State, T>
 where
     Storage: UnwindContextStorage<ImageReader<'a>>,
     State: CursorState,
+    T: Target,
 {
+    type Target = T;
+
     fn local_context_mut(&mut self) -> &mut UnwindContext<ImageReader<'a>, Storage> {
         &mut self.local_ctx
     }
@@ -125,29 +393,1776 @@ where
         self.global_ctx
     }
 
-    fn from_ucontext(g_ctx: &'a GlobalContext<'a>, u_ctx: ucontext_t) -> Self {
+    fn target(&self) -> &T {
+        &self.target
+    }
+
+    fn cfa(&self) -> Option<usize> {
+        self.cfa
+    }
+
+    fn from_parts(g_ctx: &'a GlobalContext<'a>, target: T, state: State) -> Self {
         Self {
             global_ctx: g_ctx,
             local_ctx: Default::default(),
-            state: State::new(&u_ctx),
+            state,
+            target,
+            cfa: None,
+            policy: UnwindPolicy::default(),
+            unknown_pc_policy: UnknownPcPolicy::default(),
+            heuristic: false,
+            captured: false,
+            stack_bounds: None,
+            stop_reason: None,
+            trace_steps: false,
+            last_step_trace: None,
+            verify_calls: false,
+        }
+    }
+
+    #[inline(always)]
+    fn with_target(g_ctx: &'a GlobalContext<'a>, target: T) -> Result<Self, UnwindError> {
+        let u_ctx = target.initial_context()?;
+        let mut cursor = Self::from_parts(g_ctx, target, State::new(&u_ctx));
+        cursor.captured = true;
+        cursor.stack_bounds = cursor.target.stack_bounds().ok();
+        Ok(cursor)
+    }
+
+    fn get_sym_info(&self) -> SymbolInfo<'a> {
+        let mut info = self
+            .global_ctx
+            .resolve_symbol(self.state.get_program_counter());
+        if self.captured && self.cfa.is_none() {
+            hide_capture_frames(&mut info);
+        }
+        info
+    }
+
+    fn next(&mut self) -> Result<(), UnwindError> {
+        let result = self.step_within_stack();
+        if let Err(e) = &result {
+            let reason = StopReason::new(e, self.state.get_program_counter());
+            self.stop_reason = Some(reason);
+        }
+        result
+    }
+}
+
+impl<'a, Storage, State, T> UnwindCursor<'a, Storage, State, T>
+where
+    Storage: UnwindContextStorage<ImageReader<'a>>,
+    State: CursorState,
+    T: Target,
+{
+    pub fn policy(&self) -> UnwindPolicy {
+        self.policy
+    }
+
+    pub fn unknown_pc_policy(&self) -> UnknownPcPolicy {
+        self.unknown_pc_policy
+    }
+
+    /// Whether the last step walked the frame pointer chain rather than the unwind info, so
+    /// the current frame may be wrong.
+    pub fn heuristic(&self) -> bool {
+        self.heuristic
+    }
+
+    /// Whether the registers of the innermost frame were captured through the target, within
+    /// the inlined constructor.
+    pub fn captured(&self) -> bool {
+        self.captured
+    }
+
+    /// The stack of the unwound thread. A step leaving it ends the unwind.
+    pub fn stack_bounds(&self) -> Option<&core::ops::Range<usize>> {
+        self.stack_bounds.as_ref()
+    }
+
+    /// Why the last step failed. `None` until a step fails.
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.stop_reason
+    }
+
+    /// Whether steps record the unwind info they follow.
+    pub fn trace_steps(&self) -> bool {
+        self.trace_steps
+    }
+
+    /// The unwind info followed by the last step, if tracing is enabled and the step took the
+    /// DWARF path. It is kept when the step fails after finding the rules, so a wrong or
+    /// failed step can be told apart from missing unwind info.
+    pub fn last_step_trace(&self) -> Option<&StepTrace<'a>> {
+        self.last_step_trace.as_ref()
+    }
+
+    /// Whether frame pointer steps check that the return address follows a call instruction.
+    pub fn verify_calls(&self) -> bool {
+        self.verify_calls
+    }
+
+    /// Use `policy` for the following steps.
+    pub fn with_policy(mut self, policy: UnwindPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Use `policy` for program counters outside all images.
+    pub fn with_unknown_pc_policy(mut self, policy: UnknownPcPolicy) -> Self {
+        self.unknown_pc_policy = policy;
+        self
+    }
+
+    /// End the unwind when a step takes the stack pointer from within `bounds` to outside of
+    /// them, e.g. off the end of the thread's stack. Steps from outside, e.g. from a signal
+    /// stack onto the thread's stack, are not affected.
+    pub fn with_stack_bounds(mut self, bounds: core::ops::Range<usize>) -> Self {
+        self.stack_bounds = Some(bounds);
+        self
+    }
+
+    /// Record the unwind info every step follows, see [`UnwindCursor::last_step_trace`].
+    pub fn with_step_trace(mut self, enabled: bool) -> Self {
+        self.trace_steps = enabled;
+        self
+    }
+
+    /// Reject frame pointer steps to a return address that does not follow a call instruction,
+    /// which is how a broken chain usually shows. This reads the code before every such return
+    /// address through the target.
+    pub fn with_call_verification(mut self, enabled: bool) -> Self {
+        self.verify_calls = enabled;
+        self
+    }
+
+    /// Start unwinding at a caller frame in the middle of the stack, e.g. the last one a
+    /// profiler reached through frame pointers or LBR, given its registers. Its program counter
+    /// is a return address, so the unwind info of the call right before it is followed.
+    pub fn from_caller_state(g_ctx: &'a GlobalContext<'a>, state: State) -> Self
+    where
+        T: Default,
+    {
+        let mut cursor = Self::from_parts(g_ctx, Default::default(), state);
+        cursor.continue_from_caller_state(state);
+        cursor
+    }
+
+    /// Continue the walk saved in `snapshot` on `target`. See [`GlobalContext::resume`].
+    pub fn from_snapshot(
+        g_ctx: &'a GlobalContext<'a>,
+        target: T,
+        snapshot: CursorSnapshot<State>,
+    ) -> Self {
+        let mut cursor = Self::from_parts(g_ctx, target, snapshot.state);
+        cursor.cfa = snapshot.cfa;
+        cursor.policy = snapshot.policy;
+        cursor.unknown_pc_policy = snapshot.unknown_pc_policy;
+        cursor.heuristic = snapshot.heuristic;
+        cursor.captured = snapshot.captured;
+        cursor.stack_bounds = snapshot.stack_bounds;
+        cursor.stop_reason = snapshot.stop_reason;
+        cursor.trace_steps = snapshot.trace_steps;
+        cursor.verify_calls = snapshot.verify_calls;
+        cursor
+    }
+
+    /// Save the walk at the current frame, so it can be continued later without capturing the
+    /// registers again. The trace of the last step is not kept.
+    pub fn snapshot(&self) -> CursorSnapshot<State> {
+        CursorSnapshot {
+            state: self.state,
+            cfa: self.cfa,
+            policy: self.policy,
+            unknown_pc_policy: self.unknown_pc_policy,
+            heuristic: self.heuristic,
+            captured: self.captured,
+            stack_bounds: self.stack_bounds.clone(),
+            stop_reason: self.stop_reason,
+            trace_steps: self.trace_steps,
+            verify_calls: self.verify_calls,
         }
     }
+
+    /// Continue the walk on another stack, e.g. the one an async task was suspended on, as if
+    /// the cursor had been created there with [`Unwinding::from_pc_sp`]. The target and the
+    /// policy are kept, so logical stacks can be stitched from several physical ones.
+    pub fn continue_from(&mut self, pc: usize, sp: usize) {
+        self.reset_state(State::from_pc_sp(pc, sp));
+    }
+
+    /// Like [`UnwindCursor::continue_from`], but from a saved register context.
+    pub fn continue_from_ucontext(&mut self, u_ctx: &cffi::ucontext_t) {
+        self.reset_state(State::new(u_ctx));
+    }
+
+    /// Like [`UnwindCursor::from_caller_state`], but keeping the target and the policy.
+    pub fn continue_from_caller_state(&mut self, state: State) {
+        self.reset_state(state);
+        // The caller's stack pointer is the CFA of the frame it called.
+        self.cfa = Some(state.get_stack_pointer());
+    }
+
+    /// Restart the walk at `state`, which is treated as the innermost frame.
+    fn reset_state(&mut self, state: State) {
+        self.state = state;
+        self.cfa = None;
+        self.heuristic = false;
+        self.captured = false;
+        self.stop_reason = None;
+        self.last_step_trace = None;
+    }
+
+    /// Step to the caller, ending the unwind if the step leaves the stack. A step that keeps
+    /// both the program counter and the stack pointer would repeat forever, so it ends the
+    /// unwind too. This is how the stack ends at thread entry points whose unwind info does not
+    /// mark the outermost frame, e.g. musl's `__clone`.
+    fn step_within_stack(&mut self) -> Result<(), UnwindError> {
+        let (state, cfa, heuristic) = (self.state, self.cfa, self.heuristic);
+        self.step()?;
+        let (pc, sp) = (
+            self.state().get_program_counter(),
+            self.state().get_stack_pointer(),
+        );
+        let left_stack = self
+            .stack_bounds
+            .as_ref()
+            .is_some_and(|x| x.contains(&state.get_stack_pointer()) && !x.contains(&sp));
+        let stuck = pc == state.get_program_counter() && sp == state.get_stack_pointer();
+        if left_stack || stuck {
+            self.state = state;
+            self.cfa = cfa;
+            self.heuristic = heuristic;
+        }
+        if left_stack || stuck {
+            Err(UnwindError::UnwindEnded)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Step to the caller following the policy, without checking the result.
+    fn step(&mut self) -> Result<(), UnwindError> {
+        self.last_step_trace = None;
+        let dwarf = match self.policy {
+            UnwindPolicy::FramePointerOnly => None,
+            _ => Some(self.step_dwarf()),
+        };
+        let result = match dwarf {
+            Some(Ok(())) => {
+                self.heuristic = false;
+                Ok(())
+            }
+            // The unwind info marks the end of the stack, so there is nothing to fall back on.
+            Some(Err(UnwindError::UnwindEnded)) => Err(UnwindError::UnwindEnded),
+            Some(Err(e)) if self.policy == UnwindPolicy::DwarfOnly => Err(e),
+            dwarf => match self.step_frame_pointer() {
+                Ok(()) => {
+                    self.heuristic = true;
+                    Ok(())
+                }
+                Err(e) => Err(dwarf.and_then(Result::err).unwrap_or(e)),
+            },
+        };
+        match result {
+            Err(UnwindError::UnknownProgramCounter(_))
+                if self.unknown_pc_policy == UnknownPcPolicy::Stop =>
+            {
+                Err(UnwindError::UnwindEnded)
+            }
+            result => result,
+        }
+    }
+
+    /// Step to the caller following the unwind info of the image.
+    fn step_dwarf(&mut self) -> Result<(), UnwindError> {
+        let mut state = *self.state();
+        let target = self.target().clone();
+        #[cfg(target_arch = "arm")]
+        if let Some(insns) = self.setup_exidx_info()? {
+            state.step_exidx(&insns, &target)?;
+            self.cfa = state.get_register(Register(13)).ok();
+            self.state = state;
+            return Ok(());
+        }
+        let fde = self.trace_steps.then(|| self.fde_range()).flatten();
+        let (cfa, trace) = {
+            let context = self.global_context();
+            let (unwind_info, return_address) = self.setup_unwind_info()?;
+            let trace = fde.map(|fde| StepTrace {
+                pc: state.get_program_counter(),
+                fde,
+                cfa: unwind_info.cfa().clone(),
+                return_address: unwind_info.register(return_address),
+            });
+            let cfa = state
+                .get_cfa(unwind_info, context, &target)
+                .and_then(|cfa| {
+                    state.step(unwind_info, return_address, context, &target)?;
+                    Ok(cfa)
+                });
+            (cfa, trace)
+        };
+        self.last_step_trace = trace;
+        let cfa = cfa?;
+        self.state = state;
+        self.cfa = Some(cfa);
+        Ok(())
+    }
+
+    /// Step to the caller following the frame pointer chain.
+    fn step_frame_pointer(&mut self) -> Result<(), UnwindError> {
+        let mut state = *self.state();
+        let target = self.target().clone();
+        let cfa = state.step_frame_pointer(&target)?;
+        let pc = state.get_program_counter();
+        // A null return address ends the stack rather than following a call.
+        if self.verify_calls && pc != 0 && !State::follows_call(pc, &target) {
+            return Err(UnwindError::UnwindLogicalError(
+                "return address does not follow a call",
+            ));
+        }
+        self.state = state;
+        self.cfa = Some(cfa);
+        Ok(())
+    }
+}
+
+/// The functions inlined into the caller while capturing its registers or program
+/// counter, named by their full path in this crate as [`trait_path`] gives it.
+#[cfg(feature = "line-info")]
+const CAPTURE_FRAMES: &[&str] = &[
+    "unwinder::cffi::getcontext",
+    "unwinder::cursor::target::Target::initial_context",
+    "unwinder::cursor::Unwinding::with_target",
+    "unwinder::cursor::Unwinding::new",
+    "unwinder::backtrace::Backtrace::capture",
+    "unwinder::GlobalContext::current_location",
+];
+
+/// Whether the demangled function `name` is one of [`CAPTURE_FRAMES`]. Functions of other
+/// crates with the same name, e.g. a `Backtrace::capture` of the caller, are kept.
+#[cfg(feature = "line-info")]
+fn is_capture_frame(name: &str) -> bool {
+    CAPTURE_FRAMES.contains(&trait_path(name).as_str())
+}
+
+/// Drop the leading inlined frames of the register capture from `info`.
+#[cfg_attr(not(feature = "line-info"), allow(unused_variables))]
+pub(crate) fn hide_capture_frames(info: &mut SymbolInfo) {
+    #[cfg(feature = "line-info")]
+    {
+        let frames = &mut info.associated_frames;
+        let count = frames
+            .iter()
+            .take_while(|frame| match frame {
+                crate::Frame::Dwarf(frame) => frame
+                    .function
+                    .as_ref()
+                    .and_then(|x| x.raw_name().ok())
+                    .is_some_and(|x| {
+                        is_capture_frame(&format!("{:#}", rustc_demangle::demangle(&x)))
+                    }),
+                _ => false,
+            })
+            .count()
+            .min(frames.len().saturating_sub(1));
+        if count > 0 {
+            frames.drain(..count);
+            // The remaining frames are located at call sites, which carry no discriminator.
+            info.discriminator = None;
+        }
+    }
+}
+
+/// The path of a demangled function name without generic arguments, where a method of a
+/// trait implementation is named through the trait. Legacy and v0 manglings then agree, e.g.
+/// `<A<T> as B<T>>::f` becomes `B::f`.
+#[cfg(feature = "line-info")]
+fn trait_path(name: &str) -> String {
+    let mut path = String::with_capacity(name.len());
+    let mut depth = 0usize;
+    let mut last = ' ';
+    for c in name.chars() {
+        match c {
+            '<' if depth > 0 || last.is_alphanumeric() || last == '_' || last == ':' => depth += 1,
+            '>' if depth > 0 => depth -= 1,
+            _ if depth == 0 => path.push(c),
+            _ => {}
+        }
+        last = c;
+    }
+    let path = match path.rfind(" as ") {
+        Some(i) => &path[i + 4..],
+        None => path.trim_start_matches('<'),
+    };
+    path.trim_end_matches("::").replace('>', "")
 }
 
-type DynamicCursor<'a, State> = UnwindCursor<'a, StoreOnHeap, State>;
-type StaticCursor<'a, State> = UnwindCursor<'a, InlineStorage, State>;
+pub type DynamicCursor<'a, State, T = LocalTarget> = UnwindCursor<'a, StoreOnHeap, State, T>;
+pub type StaticCursor<'a, State, T = LocalTarget> = UnwindCursor<'a, InlineStorage, State, T>;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std", not(target_arch = "wasm32")))]
 mod test {
     use crate::cursor::state::FramePointerBasedState;
-    use crate::cursor::{DynamicCursor, Unwinding};
-    use crate::{Frame, GlobalContext};
+    use crate::cursor::{DynamicCursor, LocalTarget, Target, Unwinding};
+    use crate::tests::Fixture;
+    use crate::{Frame, GlobalContext, UnwindError};
+    use object::{Object, ObjectSection, ObjectSymbol};
+    use std::mem::MaybeUninit;
+
+    /// A stack copied out of its thread, mapped at a fake base address.
+    #[derive(Clone)]
+    struct CopiedStack {
+        base: usize,
+        words: Vec<usize>,
+    }
+
+    impl Target for CopiedStack {
+        fn read_memory(&self, address: usize, buf: &mut [u8]) -> Result<(), UnwindError> {
+            let bytes = unsafe {
+                std::slice::from_raw_parts(
+                    self.words.as_ptr() as *const u8,
+                    self.words.len() * std::mem::size_of::<usize>(),
+                )
+            };
+            let start = address.wrapping_sub(self.base);
+            let data = bytes.get(start..start.saturating_add(buf.len())).ok_or(
+                UnwindError::UnwindLogicalError("read outside of copied stack"),
+            )?;
+            buf.copy_from_slice(data);
+            Ok(())
+        }
+
+        fn initial_context(&self) -> Result<libc::ucontext_t, UnwindError> {
+            Err(UnwindError::NotSupported("registers of a copied stack"))
+        }
+    }
+
+    fn cursor_at<'a>(
+        g: &'a GlobalContext<'a>,
+        pc: usize,
+    ) -> DynamicCursor<'a, FramePointerBasedState> {
+        let mut ucp = unsafe { MaybeUninit::<libc::ucontext_t>::zeroed().assume_init() };
+        ucp.uc_mcontext.gregs[libc::REG_RIP as usize] = pc as _;
+        DynamicCursor::from_ucontext(g, ucp)
+    }
+
+    #[test]
+    fn it_survives_inconsistent_eh_frame_hdr() {
+        let source = "int hdr_a(int x) { return x + 1; }\nint hdr_b(int x) { return x * 2; }\n";
+        let fixture = match Fixture::build("hdr", source, &["-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let path = &fixture.path;
+        // Point every entry of the binary search table at the first FDE.
+        let mut data = std::fs::read(path).unwrap();
+        let (offset, size) = object::File::parse(&*data)
+            .unwrap()
+            .section_by_name(".eh_frame_hdr")
+            .and_then(|x| x.file_range())
+            .unwrap();
+        let hdr = &mut data[offset as usize..(offset + size) as usize];
+        assert_eq!(&hdr[..4], &[1, 0x1b, 0x03, 0x3b]);
+        let first_fde = hdr[16..20].to_vec();
+        for entry in hdr[12..].chunks_exact_mut(8).skip(1) {
+            entry[4..].copy_from_slice(&first_fde);
+        }
+        std::fs::write(path, data).unwrap();
+
+        let fixture = fixture.open();
+        let g = GlobalContext::new();
+        for name in ["hdr_a", "hdr_b"] {
+            let mut cursor = cursor_at(&g, fixture.symbol(name));
+            assert!(cursor.setup_unwind_info().is_ok());
+        }
+    }
+
+    #[test]
+    fn it_recovers_vector_registers() {
+        use crate::cursor::state::CursorState;
+        use gimli::write::{
+            Address, CallFrameInstruction, CommonInformationEntry, EhFrame, EndianVec,
+            FrameDescriptionEntry, FrameTable,
+        };
+        use gimli::{Register, UnwindSection};
+
+        // Synthetic CFI: CFA = rsp + 24, return address at CFA - 8, xmm0 saved at CFA - 24 and
+        // xmm1 given by a rule that vector registers cannot follow.
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 1,
+            address_size: 8,
+        };
+        let mut table = FrameTable::default();
+        let cie = table.add_cie(CommonInformationEntry::new(encoding, 1, -8, Register(16)));
+        let mut fde = FrameDescriptionEntry::new(Address::Constant(0x1000), 0x100);
+        fde.add_instruction(0, CallFrameInstruction::Cfa(Register(7), 24));
+        fde.add_instruction(0, CallFrameInstruction::Offset(Register(16), -8));
+        fde.add_instruction(0, CallFrameInstruction::Offset(Register(17), -24));
+        fde.add_instruction(0, CallFrameInstruction::ValOffset(Register(18), 0));
+        table.add_fde(cie, fde);
+        let mut eh_frame = EhFrame(EndianVec::new(gimli::LittleEndian));
+        table.write_eh_frame(&mut eh_frame).unwrap();
+        let data = eh_frame.0.into_vec();
+
+        let eh_frame = gimli::EhFrame::new(&data, gimli::LittleEndian);
+        let bases = gimli::BaseAddresses::default();
+        let mut ctx = gimli::UnwindContext::new();
+        let row = eh_frame
+            .fde_for_address(&bases, 0x1010, gimli::EhFrame::cie_from_offset)
+            .and_then(|fde| fde.unwind_info_for_address(&eh_frame, &bases, &mut ctx, 0x1010))
+            .unwrap();
+
+        let value: u128 = 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210;
+        let mut stack = [0u64; 3];
+        stack[0] = value as u64;
+        stack[1] = (value >> 64) as u64;
+        stack[2] = 0x2000;
+        let mut ucp = unsafe { MaybeUninit::<libc::ucontext_t>::zeroed().assume_init() };
+        ucp.uc_mcontext.gregs[libc::REG_RIP as usize] = 0x1010;
+        ucp.uc_mcontext.gregs[libc::REG_RSP as usize] = stack.as_ptr() as _;
+
+        let g = GlobalContext::new();
+        let mut state = FramePointerBasedState::new(&ucp);
+        assert!(state.get_fp_register(Register(17)).is_err());
+        assert_eq!(
+            state
+                .recover_fp_register(Register(17), row, &g, &LocalTarget)
+                .unwrap(),
+            value
+        );
+        state
+            .step(row, gimli::X86_64::RA, &g, &LocalTarget)
+            .unwrap();
+        assert_eq!(state.get_program_counter(), 0x2000);
+        assert_eq!(state.get_fp_register(Register(17)).unwrap(), value);
+        assert!(state.get_fp_register(Register(18)).is_err());
+        assert!(state.get_fp_register(Register(19)).is_err());
+    }
+
+    #[test]
+    fn it_tracks_registers_by_dwarf_number() {
+        use crate::cursor::state::CursorState;
+        use gimli::write::{
+            Address, CallFrameInstruction, CommonInformationEntry, EhFrame, EndianVec,
+            FrameDescriptionEntry, FrameTable,
+        };
+        use gimli::{Register, UnwindSection, X86_64};
+
+        // Synthetic CFI of a frame pointer based function: CFA = rbp + 16, the return address
+        // at CFA - 8 and the caller's rbp at CFA - 16.
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 1,
+            address_size: 8,
+        };
+        let mut table = FrameTable::default();
+        let cie = table.add_cie(CommonInformationEntry::new(encoding, 1, -8, X86_64::RA));
+        let mut fde = FrameDescriptionEntry::new(Address::Constant(0x1000), 0x100);
+        fde.add_instruction(0, CallFrameInstruction::Cfa(X86_64::RBP, 16));
+        fde.add_instruction(0, CallFrameInstruction::Offset(X86_64::RA, -8));
+        fde.add_instruction(0, CallFrameInstruction::Offset(X86_64::RBP, -16));
+        table.add_fde(cie, fde);
+        let mut eh_frame = EhFrame(EndianVec::new(gimli::LittleEndian));
+        table.write_eh_frame(&mut eh_frame).unwrap();
+        let data = eh_frame.0.into_vec();
+
+        let eh_frame = gimli::EhFrame::new(&data, gimli::LittleEndian);
+        let bases = gimli::BaseAddresses::default();
+        let mut ctx = gimli::UnwindContext::new();
+        let row = eh_frame
+            .fde_for_address(&bases, 0x1010, gimli::EhFrame::cie_from_offset)
+            .and_then(|fde| fde.unwind_info_for_address(&eh_frame, &bases, &mut ctx, 0x1010))
+            .unwrap();
+
+        let stack = [0x7777usize, 0x2000];
+        let rbp = stack.as_ptr() as usize;
+        let mut ucp = unsafe { MaybeUninit::<libc::ucontext_t>::zeroed().assume_init() };
+        let gregs = &mut ucp.uc_mcontext.gregs;
+        gregs[libc::REG_RIP as usize] = 0x1010;
+        gregs[libc::REG_RSP as usize] = 0x10;
+        gregs[libc::REG_RBP as usize] = rbp as _;
+        gregs[libc::REG_RBX as usize] = 0x1111;
+        gregs[libc::REG_R12 as usize] = 0x2222;
+        gregs[libc::REG_RAX as usize] = 0x3333;
+
+        let g = GlobalContext::new();
+        let mut state = FramePointerBasedState::new(&ucp);
+        assert_eq!(state.get_register(X86_64::RBX).unwrap(), 0x1111);
+        assert_eq!(state.get_register(X86_64::R12).unwrap(), 0x2222);
+        assert_eq!(state.get_register(X86_64::RBP).unwrap(), rbp);
+        assert_eq!(state.get_register(X86_64::RA).unwrap(), 0x1010);
+        assert!(state.get_register(Register(40)).is_err());
+
+        state.step(row, X86_64::RA, &g, &LocalTarget).unwrap();
+        assert_eq!(state.get_program_counter(), 0x2000);
+        assert_eq!(state.get_register(X86_64::RSP).unwrap(), rbp + 16);
+        assert_eq!(state.get_register(X86_64::RBP).unwrap(), 0x7777);
+        assert_eq!(state.get_register(X86_64::RBX).unwrap(), 0x1111);
+        assert_eq!(state.get_register(X86_64::R12).unwrap(), 0x2222);
+        // The call may have clobbered the caller-saved registers.
+        assert!(state.get_register(X86_64::RAX).is_err());
+
+        let state = FramePointerBasedState::from_pc_sp(0x1010, 0x10);
+        assert!(state.get_register(X86_64::RBP).is_err());
+    }
+
+    #[test]
+    fn it_reads_return_address_column_of_cie() {
+        use crate::cursor::state::CursorState;
+        use gimli::write::{
+            Address, CallFrameInstruction, CommonInformationEntry, EhFrame, EndianVec,
+            FrameDescriptionEntry, FrameTable,
+        };
+        use gimli::{Register, UnwindSection, X86_64};
+
+        // The CIE keeps the return address in a column other than RIP's.
+        let column = Register(100);
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 1,
+            address_size: 8,
+        };
+        let mut table = FrameTable::default();
+        let cie = table.add_cie(CommonInformationEntry::new(encoding, 1, -8, column));
+        let mut fde = FrameDescriptionEntry::new(Address::Constant(0x1000), 0x100);
+        fde.add_instruction(0, CallFrameInstruction::Cfa(X86_64::RSP, 16));
+        fde.add_instruction(0, CallFrameInstruction::Offset(column, -16));
+        table.add_fde(cie, fde);
+        let mut eh_frame = EhFrame(EndianVec::new(gimli::LittleEndian));
+        table.write_eh_frame(&mut eh_frame).unwrap();
+        let data = eh_frame.0.into_vec();
+
+        let eh_frame = gimli::EhFrame::new(&data, gimli::LittleEndian);
+        let bases = gimli::BaseAddresses::default();
+        let mut ctx = gimli::UnwindContext::new();
+        let fde = eh_frame
+            .fde_for_address(&bases, 0x1010, gimli::EhFrame::cie_from_offset)
+            .unwrap();
+        let return_address = fde.cie().return_address_register();
+        assert_eq!(return_address, column);
+        let row = fde
+            .unwind_info_for_address(&eh_frame, &bases, &mut ctx, 0x1010)
+            .unwrap();
+
+        let stack = [0x2000usize, 0x3000];
+        let sp = stack.as_ptr() as usize;
+        let g = GlobalContext::new();
+        let mut state = FramePointerBasedState::from_pc_sp(0x1010, sp);
+        state.step(row, return_address, &g, &LocalTarget).unwrap();
+        assert_eq!(state.get_program_counter(), 0x2000);
+        assert_eq!(state.get_stack_pointer(), sp + 16);
+    }
+
+    #[test]
+    fn it_retrieves_rip_and_rbp() {
+        use crate::cursor::state::CursorState;
+        use gimli::X86_64;
+        let g = GlobalContext::new();
+        let mut cursor = DynamicCursor::<FramePointerBasedState>::new(&g).unwrap();
+        let mut frames = 0;
+        loop {
+            let state = cursor.state();
+            assert_eq!(
+                state.get_register(X86_64::RA).unwrap(),
+                state.get_program_counter()
+            );
+            // Common CFA rules are based on RBP, so it must stay known for CFI to be followed.
+            assert!(state.get_register(X86_64::RBP).is_ok(), "frame {}", frames);
+            frames += 1;
+            if cursor.next().is_err() {
+                break;
+            }
+        }
+        assert!(frames > 1);
+    }
+
+    #[test]
+    #[cfg(target_env = "musl")]
+    fn it_unwinds_one_frame_on_musl() {
+        use crate::cursor::state::CursorState;
+        let g = GlobalContext::new();
+        let mut cursor = DynamicCursor::<FramePointerBasedState>::new(&g).unwrap();
+        let info = cursor.get_sym_info();
+        assert!(info.svma.is_some());
+        cursor.next().unwrap();
+        let pc = cursor.state().get_program_counter();
+        assert!(g.find_image(pc).is_some());
+        // The thread entry point of musl does not end the stack; the walk must still stop, and
+        // the stack is complete.
+        let mut frames = 1;
+        while cursor.next().is_ok() {
+            frames += 1;
+            assert!(frames < 1000);
+        }
+        assert_eq!(
+            cursor.stop_reason(),
+            Some(crate::cursor::StopReason::CleanEnd)
+        );
+    }
+
+    #[test]
+    fn it_reads_registers_from_gregs() {
+        use crate::cursor::state::CursorState;
+        use gimli::X86_64;
+        let mut ucp: libc::ucontext_t = unsafe { MaybeUninit::zeroed().assume_init() };
+        let gregs = &mut ucp.uc_mcontext.gregs;
+        gregs[libc::REG_RIP as usize] = 0x1234;
+        gregs[libc::REG_RSP as usize] = 0x7ff0;
+        gregs[libc::REG_RBP as usize] = 0x8000;
+        let state = FramePointerBasedState::new(&ucp);
+        assert_eq!(state.get_program_counter(), 0x1234);
+        assert_eq!(state.get_stack_pointer(), 0x7ff0);
+        assert_eq!(state.get_register(X86_64::RBP).unwrap(), 0x8000);
+        assert_eq!(state.get_register(X86_64::RAX).unwrap(), 0);
+    }
+
+    #[test]
+    fn it_recovers_constant_registers() {
+        use crate::cursor::state::CursorState;
+        use gimli::write::{
+            Address, CallFrameInstruction, CommonInformationEntry, EhFrame, EndianVec,
+            FrameDescriptionEntry, FrameTable,
+        };
+        use gimli::{Register, UnwindSection};
+
+        // Only the AArch64 `DW_CFA_AARCH64_negate_ra_state` yields a constant rule: it toggles
+        // the RA_SIGN_STATE pseudo-register between 0 and 1.
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 1,
+            address_size: 8,
+        };
+        let mut table = FrameTable::default();
+        let cie = table.add_cie(CommonInformationEntry::new(encoding, 1, -8, Register(16)));
+        let mut fde = FrameDescriptionEntry::new(Address::Constant(0x1000), 0x100);
+        fde.add_instruction(0, CallFrameInstruction::Cfa(Register(7), 8));
+        fde.add_instruction(0, CallFrameInstruction::NegateRaState);
+        table.add_fde(cie, fde);
+        let mut eh_frame = EhFrame(EndianVec::new(gimli::LittleEndian));
+        table.write_eh_frame(&mut eh_frame).unwrap();
+        let data = eh_frame.0.into_vec();
+
+        let mut eh_frame = gimli::EhFrame::new(&data, gimli::LittleEndian);
+        eh_frame.set_vendor(gimli::Vendor::AArch64);
+        let bases = gimli::BaseAddresses::default();
+        let mut ctx = gimli::UnwindContext::new();
+        let row = eh_frame
+            .fde_for_address(&bases, 0x1010, gimli::EhFrame::cie_from_offset)
+            .and_then(|fde| fde.unwind_info_for_address(&eh_frame, &bases, &mut ctx, 0x1010))
+            .unwrap();
+        let ra_sign_state = gimli::AArch64::RA_SIGN_STATE;
+        assert_eq!(
+            row.register(ra_sign_state),
+            gimli::RegisterRule::Constant(1)
+        );
+
+        let stack = [0u64; 1];
+        let mut ucp = unsafe { MaybeUninit::<libc::ucontext_t>::zeroed().assume_init() };
+        ucp.uc_mcontext.gregs[libc::REG_RIP as usize] = 0x1010;
+        ucp.uc_mcontext.gregs[libc::REG_RSP as usize] = stack.as_ptr() as _;
+        let g = GlobalContext::new();
+        let state = FramePointerBasedState::new(&ucp);
+        assert_eq!(
+            state
+                .recover_register(ra_sign_state, row, &g, &LocalTarget)
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn it_evaluates_implicit_values() {
+        use crate::cursor::state::CursorState;
+        use gimli::{BigEndian, EndianSlice, Expression, LittleEndian};
+
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 4,
+            address_size: 8,
+        };
+        let ucp = unsafe { MaybeUninit::<libc::ucontext_t>::zeroed().assume_init() };
+        let state = FramePointerBasedState::new(&ucp);
+        // DW_OP_implicit_value 4 bytes
+        let bytes = [gimli::DW_OP_implicit_value.0, 4, 0x78, 0x56, 0x34, 0x12];
+        let le = Expression(EndianSlice::new(&bytes, LittleEndian));
+        assert_eq!(
+            state.eval(&le, encoding, 0, &LocalTarget).unwrap(),
+            0x1234_5678
+        );
+        let be = Expression(EndianSlice::new(&bytes, BigEndian));
+        assert_eq!(
+            state.eval(&be, encoding, 0, &LocalTarget).unwrap(),
+            0x7856_3412
+        );
+
+        let bytes = [gimli::DW_OP_implicit_value.0, 9, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let wide = Expression(EndianSlice::new(&bytes, LittleEndian));
+        assert!(state.eval(&wide, encoding, 0, &LocalTarget).is_err());
+    }
+
+    #[test]
+    fn it_evaluates_stack_values() {
+        use crate::cursor::state::CursorState;
+        use gimli::{EndianSlice, Expression, LittleEndian};
+
+        let encoding = |address_size| gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 4,
+            address_size,
+        };
+        let ucp = unsafe { MaybeUninit::<libc::ucontext_t>::zeroed().assume_init() };
+        let state = FramePointerBasedState::new(&ucp);
+
+        // DW_OP_constu 0x1234_5678_9abc; DW_OP_stack_value
+        let mut bytes = vec![gimli::DW_OP_constu.0];
+        gimli::leb128::write::unsigned(&mut bytes, 0x1234_5678_9abc).unwrap();
+        bytes.push(gimli::DW_OP_stack_value.0);
+        let expr = Expression(EndianSlice::new(&bytes, LittleEndian));
+        assert_eq!(
+            state.eval(&expr, encoding(8), 0, &LocalTarget).unwrap(),
+            0x1234_5678_9abc
+        );
+        assert_eq!(
+            state.eval(&expr, encoding(4), 0, &LocalTarget).unwrap(),
+            0x5678_9abc
+        );
+
+        // DW_OP_lit5; DW_OP_neg; DW_OP_stack_value
+        let bytes = [
+            gimli::DW_OP_lit5.0,
+            gimli::DW_OP_neg.0,
+            gimli::DW_OP_stack_value.0,
+        ];
+        let expr = Expression(EndianSlice::new(&bytes, LittleEndian));
+        assert_eq!(
+            state.eval(&expr, encoding(8), 0, &LocalTarget).unwrap(),
+            -5i64 as u64
+        );
+        assert_eq!(
+            state.eval(&expr, encoding(4), 0, &LocalTarget).unwrap(),
+            0xffff_fffb
+        );
+    }
+
+    #[test]
+    fn it_rejects_unmapped_expression_addresses() {
+        use crate::cursor::state::CursorState;
+        use gimli::write::{
+            Address, CallFrameInstruction, CommonInformationEntry, EhFrame, EndianVec, Expression,
+            FrameDescriptionEntry, FrameTable,
+        };
+        use gimli::{EndianSlice, LittleEndian, UnwindSection, X86_64};
+
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 1,
+            address_size: 8,
+        };
+        // RBP is saved at the null page: DW_OP_lit16
+        let mut table = FrameTable::default();
+        let cie = table.add_cie(CommonInformationEntry::new(encoding, 1, -8, X86_64::RA));
+        let mut fde = FrameDescriptionEntry::new(Address::Constant(0x1000), 0x100);
+        fde.add_instruction(0, CallFrameInstruction::Cfa(X86_64::RSP, 8));
+        let expr = Expression::raw(vec![gimli::DW_OP_lit16.0]);
+        fde.add_instruction(0, CallFrameInstruction::Expression(X86_64::RBP, expr));
+        table.add_fde(cie, fde);
+        let mut eh_frame = EhFrame(EndianVec::new(LittleEndian));
+        table.write_eh_frame(&mut eh_frame).unwrap();
+        let data = eh_frame.0.into_vec();
+
+        let eh_frame = gimli::EhFrame::new(&data, LittleEndian);
+        let bases = gimli::BaseAddresses::default();
+        let mut ctx = gimli::UnwindContext::new();
+        let row = eh_frame
+            .fde_for_address(&bases, 0x1010, gimli::EhFrame::cie_from_offset)
+            .and_then(|fde| fde.unwind_info_for_address(&eh_frame, &bases, &mut ctx, 0x1010))
+            .unwrap();
+        let stack = [0u64; 1];
+        let g = GlobalContext::new();
+        let state = FramePointerBasedState::from_pc_sp(0x1010, stack.as_ptr() as usize);
+        assert!(state
+            .recover_register(X86_64::RBP, row, &g, &LocalTarget)
+            .is_err());
+
+        // DW_OP_breg7 0; DW_OP_bit_piece 64 <offset>
+        let eval_encoding = gimli::Encoding {
+            version: 4,
+            ..encoding
+        };
+        let bit_piece = |offset| {
+            let mut bytes = vec![gimli::DW_OP_breg7.0, 0, gimli::DW_OP_bit_piece.0, 64];
+            gimli::leb128::write::unsigned(&mut bytes, offset).unwrap();
+            bytes
+        };
+        let sp = state.get_register(X86_64::RSP).unwrap() as u64;
+        let bytes = bit_piece(8);
+        let expr = gimli::Expression(EndianSlice::new(&bytes, LittleEndian));
+        assert_eq!(
+            state.eval(&expr, eval_encoding, 0, &LocalTarget).unwrap(),
+            sp + 1
+        );
+        let bytes = bit_piece(3);
+        let expr = gimli::Expression(EndianSlice::new(&bytes, LittleEndian));
+        assert!(matches!(
+            state.eval(&expr, eval_encoding, 0, &LocalTarget),
+            Err(UnwindError::NotSupported(_))
+        ));
+    }
+
+    fn written_sections(
+        dwarf: &mut gimli::write::DwarfUnit,
+    ) -> std::collections::HashMap<gimli::SectionId, Vec<u8>> {
+        use gimli::write::{EndianVec, Sections};
+        let mut sections = Sections::new(EndianVec::new(gimli::LittleEndian));
+        dwarf.write(&mut sections).unwrap();
+        let mut data = std::collections::HashMap::new();
+        sections
+            .for_each(|id, section| {
+                data.insert(id, section.slice().to_vec());
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn it_evaluates_typed_values() {
+        use crate::cursor::state::CursorState;
+        use gimli::write::{AttributeValue, DwarfUnit};
+        use gimli::{EndianSlice, Expression, LittleEndian};
+
+        // A unit with a signed 4-byte base type.
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 5,
+            address_size: 8,
+        };
+        let mut dwarf = DwarfUnit::new(encoding);
+        let root = dwarf.unit.root();
+        let id = dwarf.unit.add(root, gimli::DW_TAG_base_type);
+        let entry = dwarf.unit.get_mut(id);
+        entry.set(gimli::DW_AT_byte_size, AttributeValue::Data1(4));
+        entry.set(
+            gimli::DW_AT_encoding,
+            AttributeValue::Encoding(gimli::DW_ATE_signed),
+        );
+        let data = written_sections(&mut dwarf);
+        let dwarf = gimli::Dwarf::load(|id| {
+            Ok::<_, ()>(EndianSlice::new(
+                data.get(&id).map(Vec::as_slice).unwrap_or_default(),
+                LittleEndian,
+            ))
+        })
+        .unwrap();
+        let header = dwarf.units().next().unwrap().unwrap();
+        let unit = dwarf.unit(header).unwrap();
+        let mut entries = unit.entries();
+        let offset = loop {
+            let (_, entry) = entries.next_dfs().unwrap().unwrap();
+            if entry.tag() == gimli::DW_TAG_base_type {
+                break entry.offset();
+            }
+        };
+
+        let ucp = unsafe { MaybeUninit::<libc::ucontext_t>::zeroed().assume_init() };
+        let state = FramePointerBasedState::new(&ucp);
+        // DW_OP_constu 0xffff_fffe; DW_OP_convert <int>; DW_OP_stack_value
+        let mut bytes = vec![gimli::DW_OP_constu.0];
+        gimli::leb128::write::unsigned(&mut bytes, 0xffff_fffe).unwrap();
+        bytes.push(gimli::DW_OP_convert.0);
+        gimli::leb128::write::unsigned(&mut bytes, offset.0 as u64).unwrap();
+        bytes.push(gimli::DW_OP_stack_value.0);
+        let expr = Expression(EndianSlice::new(&bytes, LittleEndian));
+        assert_eq!(
+            state.eval_in_unit(&expr, &unit, 0, &LocalTarget).unwrap(),
+            -2i64 as u64
+        );
+        assert!(state.eval(&expr, encoding, 0, &LocalTarget).is_err());
+    }
+
+    #[test]
+    fn it_evaluates_calls_to_dies() {
+        use crate::cursor::state::CursorState;
+        use gimli::write::{AttributeValue, DwarfUnit};
+        use gimli::{EndianSlice, Expression, LittleEndian};
+
+        // A unit with a variable located by DW_OP_lit7.
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 5,
+            address_size: 8,
+        };
+        let mut dwarf = DwarfUnit::new(encoding);
+        let root = dwarf.unit.root();
+        let id = dwarf.unit.add(root, gimli::DW_TAG_variable);
+        dwarf.unit.get_mut(id).set(
+            gimli::DW_AT_location,
+            AttributeValue::Exprloc(gimli::write::Expression::raw(vec![gimli::DW_OP_lit7.0])),
+        );
+        // And one located by calling itself.
+        let recursive_id = dwarf.unit.add(root, gimli::DW_TAG_variable);
+        let mut location = gimli::write::Expression::new();
+        location.op_call(recursive_id);
+        dwarf
+            .unit
+            .get_mut(recursive_id)
+            .set(gimli::DW_AT_location, AttributeValue::Exprloc(location));
+        let data = written_sections(&mut dwarf);
+        let dwarf = gimli::Dwarf::load(|id| {
+            Ok::<_, ()>(EndianSlice::new(
+                data.get(&id).map(Vec::as_slice).unwrap_or_default(),
+                LittleEndian,
+            ))
+        })
+        .unwrap();
+        let header = dwarf.units().next().unwrap().unwrap();
+        let unit = dwarf.unit(header).unwrap();
+        let mut entries = unit.entries();
+        let mut variables = std::iter::from_fn(|| loop {
+            let (_, entry) = entries.next_dfs().unwrap()?;
+            if entry.tag() == gimli::DW_TAG_variable {
+                return Some(entry.offset());
+            }
+        });
+        let offset = variables.next().unwrap();
+        let recursive = variables.next().unwrap();
+
+        let ucp = unsafe { MaybeUninit::<libc::ucontext_t>::zeroed().assume_init() };
+        let state = FramePointerBasedState::new(&ucp);
+        // DW_OP_call4 <variable>; DW_OP_stack_value
+        let mut bytes = vec![gimli::DW_OP_call4.0];
+        bytes.extend((offset.0 as u32).to_le_bytes());
+        bytes.push(gimli::DW_OP_stack_value.0);
+        let expr = Expression(EndianSlice::new(&bytes, LittleEndian));
+        assert_eq!(
+            state.eval_in_unit(&expr, &unit, 0, &LocalTarget).unwrap(),
+            7
+        );
+        assert!(matches!(
+            state.eval(&expr, encoding, 0, &LocalTarget),
+            Err(UnwindError::NotSupported(_))
+        ));
+
+        // DW_OP_call_ref <.debug_info offset>; DW_OP_stack_value
+        let mut bytes = vec![gimli::DW_OP_call_ref.0];
+        bytes.extend(0u32.to_le_bytes());
+        bytes.push(gimli::DW_OP_stack_value.0);
+        let expr = Expression(EndianSlice::new(&bytes, LittleEndian));
+        assert!(matches!(
+            state.eval_in_unit(&expr, &unit, 0, &LocalTarget),
+            Err(UnwindError::NotSupported(_))
+        ));
+
+        // DW_OP_call4 <recursive>, where the recursive DIE is located by calling itself.
+        let mut bytes = vec![gimli::DW_OP_call4.0];
+        bytes.extend((recursive.0 as u32).to_le_bytes());
+        let expr = Expression(EndianSlice::new(&bytes, LittleEndian));
+        assert!(matches!(
+            state.eval_in_unit(&expr, &unit, 0, &LocalTarget),
+            Err(UnwindError::UnwindLogicalError(_))
+        ));
+
+        // DW_OP_skip -3, jumping back onto itself.
+        let bytes = [gimli::DW_OP_skip.0, 0xfd, 0xff];
+        let expr = Expression(EndianSlice::new(&bytes, LittleEndian));
+        assert!(matches!(
+            state.eval_in_unit(&expr, &unit, 0, &LocalTarget),
+            Err(UnwindError::GimliError(gimli::Error::TooManyIterations))
+        ));
+    }
+
+    #[test]
+    fn it_walks_synthetic_stack() {
+        use crate::cursor::state::CursorState;
+        let source = "int pc_sp_leaf(int x) { return x + 1; }\n\
+                      int pc_sp_outer(int x) { return pc_sp_leaf(x) * 2; }\n";
+        let fixture = match Fixture::load("pc_sp", source, &["-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let g = GlobalContext::new();
+        let leaf = fixture.symbol("pc_sp_leaf");
+        let outer = fixture.symbol("pc_sp_outer");
+        // At function entry the return address sits right at the stack pointer.
+        let stack = [outer, 0];
+        let sp = stack.as_ptr() as usize;
+        let mut cursor = DynamicCursor::<FramePointerBasedState>::from_pc_sp(&g, leaf, sp);
+        assert_eq!(cursor.state().get_program_counter(), leaf);
+        cursor.next().unwrap();
+        assert_eq!(cursor.state().get_program_counter(), outer);
+        assert_eq!(cursor.cfa(), Some(sp + 8));
+        cursor.next().unwrap();
+        assert_eq!(cursor.state().get_program_counter(), 0);
+        assert_eq!(cursor.cfa(), Some(sp + 16));
+        assert!(cursor.next().is_err());
+    }
+
+    #[test]
+    fn it_walks_with_debug_frame() {
+        use crate::cursor::state::CursorState;
+        // Without unwind tables, the compiler emits `.debug_frame` (`__debug_frame` on macOS)
+        // for the debug info instead of `.eh_frame`.
+        let source = "int df_leaf(int x) { return x + 1; }\n\
+                      int df_outer(int x) { return df_leaf(x) * 2; }\n";
+        let flags = [
+            "-g",
+            "-O1",
+            "-fno-asynchronous-unwind-tables",
+            "-fno-unwind-tables",
+        ];
+        let fixture = match Fixture::load("debug_frame", source, &flags) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let g = GlobalContext::new();
+        let leaf = fixture.symbol("df_leaf");
+        let outer = fixture.symbol("df_outer");
+        let image = fixture.image(&g);
+        if image.debug_frame_section.is_none() {
+            return;
+        }
+        let svma = (leaf - image.bias) as u64;
+        assert!(image.find_fde(svma).is_err());
+        assert!(g.has_unwind_info(leaf));
+
+        let stack = [outer, 0];
+        let sp = stack.as_ptr() as usize;
+        let mut cursor = DynamicCursor::<FramePointerBasedState>::from_pc_sp(&g, leaf, sp);
+        cursor.next().unwrap();
+        assert_eq!(cursor.state().get_program_counter(), outer);
+        assert_eq!(cursor.cfa(), Some(sp + 8));
+    }
+
+    #[test]
+    fn it_hides_capture_frames() {
+        let g = GlobalContext::new();
+        let cursor = DynamicCursor::<FramePointerBasedState>::new(&g).unwrap();
+        let frames = cursor.get_sym_info().public_frames();
+        let name = frames[0].name.as_deref().unwrap();
+        assert!(name.contains("it_hides_capture_frames"), "{}", name);
+    }
+
+    #[cfg(feature = "line-info")]
+    #[test]
+    fn it_names_capture_frames_by_trait() {
+        use super::trait_path;
+        assert_eq!(
+            trait_path("<a::Cursor<b::S, a::T> as a::Unwinding<b::S, a::T>>::new"),
+            "a::Unwinding::new"
+        );
+        assert_eq!(trait_path("a::Unwinding::new"), "a::Unwinding::new");
+        assert_eq!(trait_path("<a::Local as a::Target>::f"), "a::Target::f");
+        assert_eq!(trait_path("a::f::<u8>"), "a::f");
+    }
+
+    #[cfg(feature = "line-info")]
+    #[test]
+    fn it_only_hides_capture_frames_of_this_crate() {
+        use super::is_capture_frame;
+        assert!(is_capture_frame(
+            "<unwinder::cursor::UnwindCursor<S, T> as unwinder::cursor::Unwinding>::new"
+        ));
+        assert!(is_capture_frame(
+            "<app::Board as unwinder::cursor::target::Target>::initial_context"
+        ));
+        assert!(is_capture_frame("unwinder::backtrace::Backtrace::capture"));
+        // Functions of the caller named like the capture path stay in the trace.
+        assert!(!is_capture_frame("mycrate::Backtrace::capture"));
+        assert!(!is_capture_frame("<app::Walker as app::Unwinding>::new"));
+        assert!(!is_capture_frame("app::unwinder::Backtrace::capture"));
+        assert!(!is_capture_frame(
+            "unwinder::backtrace::Backtrace::capture::{{closure}}"
+        ));
+    }
+
+    #[test]
+    fn it_stops_at_guard_pages() {
+        use crate::cursor::state::CursorState;
+        let source = "int guard_leaf(int x) { return x + 1; }\n";
+        let fixture = match Fixture::load("guard", source, &["-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let guard = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                page,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(guard, libc::MAP_FAILED);
+        let sp = guard as usize;
+        assert!(matches!(
+            LocalTarget.read_usize(sp),
+            Err(UnwindError::UnwindEnded)
+        ));
+
+        // The return address of the leaf lies at the stack pointer, inside the guard page.
+        let g = GlobalContext::new();
+        let leaf = fixture.symbol("guard_leaf");
+        let mut cursor = DynamicCursor::<FramePointerBasedState>::from_pc_sp(&g, leaf, sp);
+        assert!(matches!(cursor.next(), Err(UnwindError::UnwindEnded)));
+        assert_eq!(cursor.state().get_program_counter(), leaf);
+        unsafe { libc::munmap(guard, page) };
+    }
+
+    #[test]
+    fn it_continues_on_another_stack() {
+        use crate::cursor::state::CursorState;
+        let source = "int switch_leaf(int x) { return x + 1; }\n\
+                      int switch_outer(int x) { return switch_leaf(x) * 2; }\n";
+        let fixture = match Fixture::load("switch", source, &["-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let g = GlobalContext::new();
+        let leaf = fixture.symbol("switch_leaf");
+        let outer = fixture.symbol("switch_outer");
+        // The physical stack ends in a "poll" frame; the task's own stack was saved apart.
+        let poll_stack = [0usize, 0];
+        let task_stack = [outer, 0];
+        let mut cursor =
+            DynamicCursor::<FramePointerBasedState>::from_pc_sp(&g, leaf, poll_stack.as_ptr() as _);
+        cursor.next().unwrap();
+        assert_eq!(cursor.state().get_program_counter(), 0);
+
+        let sp = task_stack.as_ptr() as usize;
+        cursor.continue_from(leaf, sp);
+        assert_eq!(cursor.state().get_program_counter(), leaf);
+        assert_eq!(cursor.cfa(), None);
+        cursor.next().unwrap();
+        assert_eq!(cursor.state().get_program_counter(), outer);
+        assert_eq!(cursor.cfa(), Some(sp + 8));
+        cursor.next().unwrap();
+        assert_eq!(cursor.state().get_program_counter(), 0);
+    }
+
+    #[test]
+    fn it_walks_stack_of_custom_target() {
+        use crate::cursor::state::CursorState;
+        let source = "int target_leaf(int x) { return x + 1; }\n\
+                      int target_outer(int x) { return target_leaf(x) * 2; }\n";
+        let fixture = match Fixture::load("target", source, &["-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let g = GlobalContext::new();
+        let leaf = fixture.symbol("target_leaf");
+        let outer = fixture.symbol("target_outer");
+        let target = CopiedStack {
+            base: 0x7000_0000,
+            words: vec![outer, 0],
+        };
+        assert!(
+            DynamicCursor::<FramePointerBasedState, _>::with_target(&g, target.clone()).is_err()
+        );
+
+        let mut ucp = unsafe { MaybeUninit::<libc::ucontext_t>::zeroed().assume_init() };
+        ucp.uc_mcontext.gregs[libc::REG_RIP as usize] = leaf as _;
+        ucp.uc_mcontext.gregs[libc::REG_RSP as usize] = target.base as _;
+        let mut cursor = DynamicCursor::from_parts(&g, target, FramePointerBasedState::new(&ucp));
+        cursor.next().unwrap();
+        assert_eq!(cursor.state().get_program_counter(), outer);
+        cursor.next().unwrap();
+        assert_eq!(cursor.state().get_program_counter(), 0);
+        assert!(cursor.next().is_err());
+    }
+
+    #[test]
+    fn it_walks_stack_of_image_loaded_from_memory() {
+        use crate::cursor::state::CursorState;
+
+        let source = "int memory_leaf(int x) { return x + 1; }\n\
+                      int memory_outer(int x) { return memory_leaf(x) * 2; }\n";
+        let fixture = match Fixture::build("memory", source, &["-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let path = &fixture.path;
+        // Neither loaded by the dynamic linker nor read through a mapping.
+        let data: &'static [u8] = std::fs::read(path).unwrap().leak();
+        let address = |name| {
+            let object = object::File::parse(data).unwrap();
+            let symbol = object.symbols().find(|x| x.name() == Ok(name)).unwrap();
+            symbol.address() as usize
+        };
+        let bias = 0x4000_0000;
+        let (leaf, outer) = (
+            bias + address("memory_leaf"),
+            bias + address("memory_outer"),
+        );
+        let image = crate::image::load_bytes("memory", data, bias).unwrap();
+        let g = GlobalContext::with_images(vec![image]);
+        assert_eq!(g.module_address(leaf).unwrap().object_name, "memory");
+
+        let target = CopiedStack {
+            base: 0x7000_0000,
+            words: vec![outer, 0],
+        };
+        let state = FramePointerBasedState::from_pc_sp(leaf, target.base);
+        let mut cursor = DynamicCursor::from_parts(&g, target, state);
+        cursor.next().unwrap();
+        assert_eq!(cursor.state().get_program_counter(), outer);
+        cursor.next().unwrap();
+        assert_eq!(cursor.state().get_program_counter(), 0);
+        assert!(cursor.next().is_err());
+    }
+
+    #[test]
+    fn it_unwinds_through_vdso() {
+        use crate::cursor::state::CursorState;
+
+        let base = unsafe { libc::getauxval(libc::AT_SYSINFO_EHDR) } as usize;
+        if base == 0 {
+            return;
+        }
+        let g = GlobalContext::new();
+        let image = g.find_image(base).expect("vDSO image is loaded");
+        let svma = match image
+            .symbol_map
+            .symbols()
+            .iter()
+            .find(|x| x.name().ends_with("clock_gettime"))
+        {
+            Some(symbol) => symbol.address() as usize,
+            None => return,
+        };
+        let pc = svma + image.bias;
+
+        // A stack as seen on entry of `clock_gettime`, returning into this test.
+        let ret = it_unwinds_through_vdso as *const () as usize;
+        let stack = [ret, 0];
+        let mut cursor =
+            DynamicCursor::<FramePointerBasedState>::from_pc_sp(&g, pc, stack.as_ptr() as usize);
+        let names = cursor.get_sym_info().public_frames();
+        assert!(names.iter().any(|x| x
+            .name
+            .as_deref()
+            .is_some_and(|x| x.ends_with("clock_gettime"))));
+        cursor.next().unwrap();
+        assert_eq!(cursor.state().get_program_counter(), ret);
+    }
+
+    #[test]
+    fn it_follows_unwind_policies() {
+        use crate::cursor::state::CursorState;
+        use crate::cursor::UnwindPolicy;
+
+        #[inline(never)]
+        fn landing() {}
+
+        // A function without CFI, which keeps a frame pointer.
+        let source = "int fp_gap(int x) { return x * 3; }\n";
+        let flags = [
+            "-O0",
+            "-fno-omit-frame-pointer",
+            "-fno-asynchronous-unwind-tables",
+            "-fno-unwind-tables",
+        ];
+        let fixture = match Fixture::load("fp_gap", source, &flags) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let g = GlobalContext::new();
+        let pc = fixture.symbol("fp_gap") + 4;
+        // The caller resumes at the entry of `landing`, where the CFA is RSP + 8.
+        let ret = landing as *const () as usize + 1;
+        // Frame record of `fp_gap`: the caller's RBP, then the return address.
+        let stack = [0usize, ret, 0];
+        let rbp = stack.as_ptr() as usize;
+        let cursor = |policy| {
+            let mut ucp = unsafe { MaybeUninit::<libc::ucontext_t>::zeroed().assume_init() };
+            ucp.uc_mcontext.gregs[libc::REG_RIP as usize] = pc as _;
+            ucp.uc_mcontext.gregs[libc::REG_RSP as usize] = (rbp - 16) as _;
+            ucp.uc_mcontext.gregs[libc::REG_RBP as usize] = rbp as _;
+            DynamicCursor::<FramePointerBasedState>::from_ucontext(&g, ucp).with_policy(policy)
+        };
+
+        let mut dwarf = cursor(UnwindPolicy::DwarfOnly);
+        assert!(dwarf.next().is_err());
+        assert_eq!(dwarf.state().get_program_counter(), pc);
+
+        let mut fallback = cursor(UnwindPolicy::FramePointerFallback);
+        fallback.next().unwrap();
+        assert!(fallback.heuristic());
+        assert_eq!(fallback.state().get_program_counter(), ret);
+        assert_eq!(fallback.cfa(), Some(rbp + 16));
+        // Back in code with CFI, the unwind info is followed again.
+        fallback.next().unwrap();
+        assert!(!fallback.heuristic());
+        assert_eq!(fallback.state().get_program_counter(), 0);
+        assert_eq!(fallback.cfa(), Some(rbp + 24));
+
+        let mut frame_pointer = cursor(UnwindPolicy::FramePointerOnly);
+        frame_pointer.next().unwrap();
+        assert!(frame_pointer.heuristic());
+        assert_eq!(frame_pointer.state().get_program_counter(), ret);
+        // The caller's RBP is null, which ends the chain.
+        assert!(matches!(
+            frame_pointer.next(),
+            Err(UnwindError::UnwindEnded)
+        ));
+
+        // The frame record is only found if the frame pointer is given.
+        let mut pc_sp = DynamicCursor::<FramePointerBasedState>::from_pc_sp(&g, pc, rbp - 16)
+            .with_policy(UnwindPolicy::FramePointerOnly);
+        assert!(pc_sp.next().is_err());
+        let mut pc_sp_fp =
+            DynamicCursor::<FramePointerBasedState>::from_pc_sp_fp(&g, pc, rbp - 16, rbp)
+                .with_policy(UnwindPolicy::FramePointerOnly);
+        pc_sp_fp.next().unwrap();
+        assert_eq!(pc_sp_fp.state().get_program_counter(), ret);
+        assert_eq!(pc_sp_fp.cfa(), Some(rbp + 16));
+    }
+
+    #[test]
+    fn it_verifies_return_addresses_follow_calls() {
+        use crate::cursor::state::CursorState;
+        use crate::cursor::UnwindPolicy;
+
+        // A function without CFI, and code where a return address either follows a call or is
+        // made up, preceded by nothing but `nop`s.
+        let source = "int vc_gap(int x) { return x * 3; }\n\
+                      __asm__(\".globl vc_after_call\\n.globl vc_no_call\\n\
+                      call vc_gap\\nvc_after_call: nop\\n.fill 8, 1, 0x90\\n\
+                      vc_no_call: ret\\n\");\n";
+        let flags = [
+            "-O0",
+            "-fno-omit-frame-pointer",
+            "-fno-asynchronous-unwind-tables",
+            "-fno-unwind-tables",
+        ];
+        let fixture = match Fixture::load("verify_call", source, &flags) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let g = GlobalContext::new();
+        let pc = fixture.symbol("vc_gap") + 4;
+        let cursor = |stack: &[usize; 3], policy| {
+            let rbp = stack.as_ptr() as usize;
+            let mut ucp = unsafe { MaybeUninit::<libc::ucontext_t>::zeroed().assume_init() };
+            ucp.uc_mcontext.gregs[libc::REG_RIP as usize] = pc as _;
+            ucp.uc_mcontext.gregs[libc::REG_RSP as usize] = (rbp - 16) as _;
+            ucp.uc_mcontext.gregs[libc::REG_RBP as usize] = rbp as _;
+            DynamicCursor::<FramePointerBasedState>::from_ucontext(&g, ucp)
+                .with_policy(policy)
+                .with_call_verification(true)
+        };
+
+        // Frame records of `vc_gap`: the caller's RBP, then the return address.
+        let after_call = fixture.symbol("vc_after_call");
+        let no_call = fixture.symbol("vc_no_call");
+        let (genuine, made_up) = ([0, after_call, 0], [0, no_call, 0]);
+        let mut cursor_after_call = cursor(&genuine, UnwindPolicy::FramePointerFallback);
+        cursor_after_call.next().unwrap();
+        assert_eq!(cursor_after_call.state().get_program_counter(), after_call);
+
+        let mut fallback = cursor(&made_up, UnwindPolicy::FramePointerFallback);
+        assert!(fallback.next().is_err());
+        assert_eq!(fallback.state().get_program_counter(), pc);
+        let mut frame_pointer = cursor(&made_up, UnwindPolicy::FramePointerOnly);
+        assert!(matches!(
+            frame_pointer.next(),
+            Err(UnwindError::UnwindLogicalError(_))
+        ));
+        let unverified = cursor(&made_up, UnwindPolicy::FramePointerOnly);
+        let mut unverified = unverified.with_call_verification(false);
+        unverified.next().unwrap();
+        assert_eq!(unverified.state().get_program_counter(), no_call);
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn it_unwinds_through_dynamic_cfa() {
+        use crate::cursor::state::CursorState;
+        use std::cell::RefCell;
+
+        thread_local! {
+            static PCS: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+        }
+
+        #[inline(never)]
+        extern "C" fn dynamic_cfa_callback() {
+            let g = GlobalContext::new();
+            let mut cursor = DynamicCursor::<FramePointerBasedState>::new(&g).unwrap();
+            let mut pcs = vec![cursor.state().get_program_counter()];
+            while cursor.next().is_ok() && pcs.len() < 64 {
+                pcs.push(cursor.state().get_program_counter());
+            }
+            PCS.with(|x| *x.borrow_mut() = pcs);
+        }
+
+        // `alloca_loop` grows its frame in a loop, so its CFA follows RBP, and it calls back
+        // through `cfa_expr`, whose CFA is given by the expression `DW_OP_breg6 16`.
+        let source = "void cfa_expr(void (*f)(void));
+            __asm__(\".globl cfa_expr\\ncfa_expr:\\n.cfi_startproc\\n\
+            push %rbp\\n.cfi_def_cfa_offset 16\\n.cfi_offset 6, -16\\n\
+            mov %rsp, %rbp\\n.cfi_escape 0x0f, 0x02, 0x76, 0x10\\n\
+            call *%rdi\\npop %rbp\\n.cfi_def_cfa 7, 8\\nret\\n.cfi_endproc\\n\");\n\
+            void alloca_loop(void (*f)(void), int n) {\n\
+                volatile char *p = 0;\n\
+                for (int i = 0; i < n; i++) { p = __builtin_alloca(32 * i + 1); p[0] = i; }\n\
+                cfa_expr(f);\n\
+                p[0]++;\n\
+            }\n";
+        let fixture = match Fixture::load("dynamic_cfa", source, &["-O2"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let alloca_loop: extern "C" fn(extern "C" fn(), i32) =
+            unsafe { std::mem::transmute(fixture.symbol("alloca_loop")) };
+        alloca_loop(dynamic_cfa_callback, 8);
+
+        let pcs = PCS.with(|x| x.borrow_mut().split_off(0));
+        let g = GlobalContext::new();
+        let image = fixture.image(&g);
+        let function = |pc: usize| image.find_function_range(image.avma_to_svma(pc - 1));
+        let cfa_expr = fixture.symbol("cfa_expr");
+        let alloca_loop = image
+            .find_function_range(image.avma_to_svma(fixture.symbol("alloca_loop")))
+            .unwrap();
+        let i = pcs
+            .iter()
+            .position(|&pc| (cfa_expr..cfa_expr + 16).contains(&pc))
+            .unwrap();
+        assert_eq!(function(pcs[i + 1]), Some(alloca_loop.clone()));
+        let test = it_unwinds_through_dynamic_cfa as fn() as usize;
+        let caller = g.resolve_symbol(pcs[i + 2] - 1).function;
+        assert!(caller.is_some_and(|x| x.start == test), "{:x?}", pcs);
+    }
+
+    #[test]
+    fn it_reads_locals_of_frame() {
+        use crate::cursor::VariableLocation;
+
+        let known = std::hint::black_box(0x1234_5678_9abc_def0u64);
+        let g = GlobalContext::new();
+        let mut cursor = DynamicCursor::<FramePointerBasedState>::new(&g).unwrap();
+        let locals = cursor.locals().unwrap();
+        let local = locals
+            .iter()
+            .find(|x| x.name.as_deref() == Some("known"))
+            .unwrap();
+        assert!(!local.parameter);
+        let value = match local.location.unwrap() {
+            VariableLocation::Address(address) => unsafe { *(address as *const u64) },
+            VariableLocation::Value(value) => value,
+        };
+        assert_eq!(value, known);
+    }
+
+    #[test]
+    fn it_stops_softly_at_unknown_pc() {
+        use crate::cursor::state::CursorState;
+        use crate::cursor::UnknownPcPolicy;
+        let g = GlobalContext::new();
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let code = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                page,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(code, libc::MAP_FAILED);
+        // An anonymous mapping, like unregistered JIT code, belongs to no image.
+        let pc = code as usize + 16;
+        let stack = [0usize; 2];
+        let sp = stack.as_ptr() as usize;
+
+        let mut strict = DynamicCursor::<FramePointerBasedState>::from_pc_sp(&g, pc, sp);
+        assert!(matches!(
+            strict.next(),
+            Err(UnwindError::UnknownProgramCounter(x)) if x == pc
+        ));
+
+        let mut soft = DynamicCursor::<FramePointerBasedState>::from_pc_sp(&g, pc, sp)
+            .with_unknown_pc_policy(UnknownPcPolicy::Stop);
+        let info = soft.get_sym_info();
+        assert_eq!(info.avma, pc);
+        assert!(info.object_name.is_none());
+        assert!(info.public_frames().is_empty());
+        assert!(matches!(soft.next(), Err(UnwindError::UnwindEnded)));
+        assert_eq!(soft.state().get_program_counter(), pc);
+        unsafe { libc::munmap(code, page) };
+    }
+
+    #[test]
+    fn it_continues_from_caller_state() {
+        use crate::cursor::state::CursorState;
+        let g = GlobalContext::new();
+        let mut cursor = DynamicCursor::<FramePointerBasedState>::new(&g).unwrap();
+        let mut states = vec![*cursor.state()];
+        while cursor.next().is_ok() {
+            states.push(*cursor.state());
+        }
+        assert!(states.len() > 3);
+
+        let mut seeded = DynamicCursor::<FramePointerBasedState>::from_caller_state(&g, states[2]);
+        let mut pcs = vec![seeded.state().get_program_counter()];
+        while seeded.next().is_ok() {
+            pcs.push(seeded.state().get_program_counter());
+        }
+        let tail: Vec<_> = states[2..]
+            .iter()
+            .map(|x| x.get_program_counter())
+            .collect();
+        assert_eq!(pcs, tail);
+    }
+
+    #[test]
+    fn it_reports_stop_reason() {
+        use crate::cursor::StopReason;
+        let g = GlobalContext::new();
+        let mut cursor = DynamicCursor::<FramePointerBasedState>::new(&g).unwrap();
+        assert_eq!(cursor.stop_reason(), None);
+        cursor.next().unwrap();
+        assert_eq!(cursor.stop_reason(), None);
+        while cursor.next().is_ok() {}
+        assert_eq!(cursor.stop_reason(), Some(StopReason::CleanEnd));
+    }
+
+    #[test]
+    fn it_traces_the_rules_of_a_step() {
+        let source = "int tr_leaf(int x) { return x + 1; }\n\
+                      int tr_outer(int x) { return tr_leaf(x) * 2; }\n";
+        let fixture = match Fixture::load("step_trace", source, &["-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let g = GlobalContext::new();
+        let leaf = fixture.symbol("tr_leaf");
+        let outer = fixture.symbol("tr_outer");
+        let stack = [outer, 0];
+        let sp = stack.as_ptr() as usize;
+        let mut cursor =
+            DynamicCursor::<FramePointerBasedState>::from_pc_sp(&g, leaf, sp).with_step_trace(true);
+        cursor.next().unwrap();
+        // At the entry of a function, the CFA lies right above the return address.
+        let trace = cursor.last_step_trace().unwrap();
+        assert_eq!(trace.pc, leaf);
+        assert_eq!(trace.fde.start, leaf);
+        assert_eq!(
+            trace.cfa,
+            gimli::CfaRule::RegisterAndOffset {
+                register: gimli::X86_64::RSP,
+                offset: 8
+            }
+        );
+        assert_eq!(trace.return_address, gimli::RegisterRule::Offset(-8));
+
+        let mut cursor = DynamicCursor::<FramePointerBasedState>::from_pc_sp(&g, leaf, sp);
+        cursor.next().unwrap();
+        assert!(cursor.last_step_trace().is_none());
+    }
+
+    #[test]
+    fn it_reports_module_addresses() {
+        let g = GlobalContext::new();
+        let mut cursor = DynamicCursor::<FramePointerBasedState>::new(&g).unwrap();
+        let mut frames = 0;
+        loop {
+            let info = cursor.get_sym_info();
+            let address = cursor.get_module_address().unwrap();
+            assert_eq!(Some(address.svma), info.svma);
+            assert_eq!(Some(address.object_name), info.object_name);
+            frames += 1;
+            if cursor.next().is_err() {
+                break;
+            }
+        }
+        assert!(frames > 1);
+        let image = g
+            .find_image(it_reports_module_addresses as *const () as usize)
+            .unwrap();
+        let address = g.module_address(image.start_avma).unwrap();
+        assert_eq!(address.svma, image.start_avma - image.bias);
+        assert_eq!(address.build_id, image.build_id());
+        assert!(g.module_address(0).is_none());
+    }
+
+    #[test]
+    fn it_reads_stack_bounds() {
+        let local = 0usize;
+        let bounds = LocalTarget.stack_bounds().unwrap();
+        assert!(bounds.contains(&(&local as *const usize as usize)));
+        let g = GlobalContext::new();
+        let cursor = DynamicCursor::<FramePointerBasedState>::new(&g).unwrap();
+        assert_eq!(cursor.stack_bounds(), Some(&bounds));
+        // The bounds are remembered per thread.
+        let other = std::thread::spawn(|| LocalTarget.stack_bounds().unwrap())
+            .join()
+            .unwrap();
+        assert_ne!(other, bounds);
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn it_stops_when_leaving_stack() {
+        use crate::cursor::state::CursorState;
+        use crate::cursor::UnwindPolicy;
+        // Two frame records, each holding the caller's frame pointer and return address.
+        let mut records = [0usize; 6];
+        let base = records.as_ptr() as usize;
+        records[..4].copy_from_slice(&[base + 16, 0x1000, base + 32, 0x2000]);
+        let mut u_ctx: libc::ucontext_t = unsafe { std::mem::zeroed() };
+        u_ctx.uc_mcontext.gregs[libc::REG_RIP as usize] = 0x10;
+        u_ctx.uc_mcontext.gregs[libc::REG_RSP as usize] = base as i64;
+        u_ctx.uc_mcontext.gregs[libc::REG_RBP as usize] = base as i64;
+        let g = GlobalContext::new();
+        let cursor = || {
+            DynamicCursor::<FramePointerBasedState>::from_ucontext(&g, u_ctx)
+                .with_policy(UnwindPolicy::FramePointerOnly)
+        };
+
+        let mut unbounded = cursor();
+        unbounded.next().unwrap();
+        unbounded.next().unwrap();
+        assert_eq!(unbounded.state().get_stack_pointer(), base + 32);
+
+        // The second record lets the stack pointer leave the bounds.
+        let mut bounded = cursor().with_stack_bounds(base..base + 32);
+        bounded.next().unwrap();
+        assert_eq!(bounded.state().get_program_counter(), 0x1000);
+        assert!(matches!(bounded.next(), Err(UnwindError::UnwindEnded)));
+        assert_eq!(bounded.state().get_program_counter(), 0x1000);
+        assert_eq!(bounded.cfa(), Some(base + 16));
+    }
+
+    #[test]
+    fn it_exposes_cfa() {
+        use crate::cursor::state::CursorState;
+        let g = GlobalContext::new();
+        let mut cursor = DynamicCursor::<FramePointerBasedState>::new(&g).unwrap();
+        assert!(cursor.cfa().is_none());
+        let mut cfas = Vec::new();
+        while cursor.next().is_ok() {
+            let cfa = cursor.cfa().unwrap();
+            assert_eq!(
+                cursor.registers().get_register(gimli::X86_64::RSP).unwrap(),
+                cfa
+            );
+            cfas.push(cfa);
+        }
+        assert!(cfas.len() > 1);
+        assert!(cfas.windows(2).all(|x| x[0] < x[1]));
+    }
 
     #[test]
     fn it_inits_cursor() {
         let g = GlobalContext::new();
         if let Ok(mut cursor) = DynamicCursor::<FramePointerBasedState>::new(&g) {
-            while let Ok(_) = cursor.next() {
+            while cursor.next().is_ok() {
                 let sym = cursor.get_sym_info();
                 println!("AVMA: {:?}", sym.avma);
                 println!("SVMA: {:?}", sym.svma);
@@ -157,6 +2172,7 @@ mod test {
                     sym.associated_frames
                         .iter()
                         .filter_map(|x| match x {
+                            #[cfg(feature = "line-info")]
                             Frame::Dwarf(d) => {
                                 d.function
                                     .as_ref()
@@ -165,6 +2181,8 @@ mod test {
                             Frame::SymbolMap(map) => {
                                 Some(map.to_string())
                             }
+                            Frame::Section(name) => Some(name.to_string()),
+                            Frame::ModuleOffset { .. } | Frame::Elided(_) => None,
                         })
                         .map(|x| rustc_demangle::demangle(&x).to_string())
                         .collect::<Vec<_>>()
@@ -173,4 +2191,146 @@ mod test {
             }
         };
     }
+
+    #[test]
+    fn it_reads_tls_base() {
+        let g = GlobalContext::new();
+        let base = DynamicCursor::<FramePointerBasedState>::new(&g)
+            .unwrap()
+            .tls_base()
+            .unwrap();
+        assert_ne!(base, 0);
+        assert_eq!(LocalTarget.tls_base().unwrap(), base);
+        let other = std::thread::spawn(|| LocalTarget.tls_base().unwrap())
+            .join()
+            .unwrap();
+        assert_ne!(other, base);
+    }
+
+    #[test]
+    fn it_reads_without_checks_on_request() {
+        use crate::cursor::UncheckedLocalTarget;
+
+        let value = 0x1234_5678usize;
+        let address = &value as *const usize as usize;
+        let target = unsafe { UncheckedLocalTarget::new() };
+        assert_eq!(target.read_usize(address).unwrap(), value);
+        let g = GlobalContext::new();
+        let mut cursor =
+            DynamicCursor::<FramePointerBasedState, _>::with_target(&g, target).unwrap();
+        while cursor.next().is_ok() {}
+        assert_eq!(
+            cursor.stop_reason(),
+            Some(crate::cursor::StopReason::CleanEnd)
+        );
+    }
+
+    #[test]
+    fn it_evaluates_thread_relative_expressions() {
+        use crate::cursor::state::CursorState;
+        use gimli::{EndianSlice, Expression, LittleEndian, X86_64};
+
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 4,
+            address_size: 8,
+        };
+        let base = LocalTarget.tls_base().unwrap();
+        let ucp = unsafe { MaybeUninit::<libc::ucontext_t>::zeroed().assume_init() };
+        let state = FramePointerBasedState::new(&ucp);
+        // The segment bases come from the target, not from the frame.
+        assert!(state.get_register(X86_64::FS_BASE).is_err());
+        assert_eq!(LocalTarget.thread_register(X86_64::FS_BASE).unwrap(), base);
+        assert!(LocalTarget.thread_register(X86_64::GS_BASE).is_ok());
+
+        // The stack protector canary lives at %fs:0x28: DW_OP_bregx fs.base 0x28
+        let mut bytes = vec![gimli::DW_OP_bregx.0];
+        gimli::leb128::write::unsigned(&mut bytes, X86_64::FS_BASE.0.into()).unwrap();
+        gimli::leb128::write::signed(&mut bytes, 0x28).unwrap();
+        let expr = Expression(EndianSlice::new(&bytes, LittleEndian));
+        assert_eq!(
+            state.eval(&expr, encoding, 0, &LocalTarget).unwrap(),
+            base as u64 + 0x28
+        );
+        // ...; DW_OP_deref; DW_OP_stack_value
+        bytes.extend([gimli::DW_OP_deref.0, gimli::DW_OP_stack_value.0]);
+        let expr = Expression(EndianSlice::new(&bytes, LittleEndian));
+        assert_eq!(
+            state.eval(&expr, encoding, 0, &LocalTarget).unwrap(),
+            LocalTarget.read_usize(base + 0x28).unwrap() as u64
+        );
+
+        // DW_OP_lit16; DW_OP_form_tls_address
+        let bytes = [gimli::DW_OP_lit16.0, gimli::DW_OP_form_tls_address.0];
+        let expr = Expression(EndianSlice::new(&bytes, LittleEndian));
+        assert_eq!(
+            state.eval(&expr, encoding, 0, &LocalTarget).unwrap(),
+            base as u64 + 16
+        );
+
+        // A target that cannot tell the bases of its thread fails the expression.
+        let stack = CopiedStack {
+            base: 0x1000,
+            words: vec![],
+        };
+        let mut bytes = vec![gimli::DW_OP_bregx.0];
+        gimli::leb128::write::unsigned(&mut bytes, X86_64::FS_BASE.0.into()).unwrap();
+        bytes.push(0);
+        let expr = Expression(EndianSlice::new(&bytes, LittleEndian));
+        assert!(state.eval(&expr, encoding, 0, &stack).is_err());
+    }
+
+    #[test]
+    fn it_resumes_from_snapshots() {
+        use crate::cursor::state::CursorState;
+
+        type Cursor<'a> = DynamicCursor<'a, FramePointerBasedState>;
+        let walk = |mut cursor: Cursor| {
+            let mut pcs = vec![cursor.state().get_program_counter()];
+            while cursor.next().is_ok() {
+                pcs.push(cursor.state().get_program_counter());
+            }
+            (pcs, cursor.stop_reason())
+        };
+        let g = GlobalContext::new();
+        let (full, reason) = walk(Cursor::new(&g).unwrap());
+        assert!(full.len() > 3);
+
+        let mut cursor = Cursor::new(&g).unwrap().with_call_verification(true);
+        let mut head = vec![cursor.state().get_program_counter()];
+        for _ in 0..2 {
+            cursor.next().unwrap();
+            head.push(cursor.state().get_program_counter());
+        }
+        let snapshot = cursor.snapshot();
+        drop(cursor);
+        let resumed: Cursor = g.resume(snapshot.clone());
+        assert_eq!(resumed.state().get_program_counter(), head[2]);
+        assert_eq!(resumed.cfa(), snapshot.cfa);
+        assert!(resumed.verify_calls());
+        assert!(resumed.captured());
+        let (tail, resumed_reason) = walk(resumed);
+        assert_eq!(resumed_reason, reason);
+        // The cursors are created at different program counters of this function, but share
+        // its callers.
+        assert_eq!(head[1..], full[1..3]);
+        assert_eq!(tail, full[2..]);
+    }
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod wasm32_test {
+    use crate::cursor::state::NativeState;
+    use crate::cursor::{DynamicCursor, Unwinding};
+    use crate::{GlobalContext, UnwindError};
+
+    #[test]
+    fn it_reports_unsupported_platform() {
+        let g = GlobalContext::new();
+        assert!(g.images.is_empty());
+        assert!(matches!(
+            DynamicCursor::<NativeState>::new(&g),
+            Err(UnwindError::NotSupported(_))
+        ));
+    }
 }
@@ -0,0 +1,128 @@
+use crate::cursor::state::CursorState;
+use crate::cursor::Target;
+use crate::image::exidx::Instructions;
+use crate::{GlobalContext, UnwindError};
+use gimli::{CfaRule, Reader, Register, RegisterRule, UnwindContextStorage, UnwindTableRow};
+
+/// Core register state of 32-bit ARM. Frames are unwound with EHABI tables where available
+/// and with DWARF CFI otherwise.
+#[derive(Copy, Clone)]
+pub struct EhabiState {
+    regs: [u32; 16],
+}
+
+const FRAME_POINTER_IDX: u16 = 11;
+const STACK_POINTER_IDX: u16 = 13;
+const PROGRAM_COUNTER_IDX: u16 = 15;
+
+impl CursorState for EhabiState {
+    #[cfg(not(target_os = "none"))]
+    fn new(uctx: &libc::ucontext_t) -> Self {
+        let m = &uctx.uc_mcontext;
+        Self {
+            regs: [
+                m.arm_r0, m.arm_r1, m.arm_r2, m.arm_r3, m.arm_r4, m.arm_r5, m.arm_r6, m.arm_r7,
+                m.arm_r8, m.arm_r9, m.arm_r10, m.arm_fp, m.arm_ip, m.arm_sp, m.arm_lr, m.arm_pc,
+            ],
+        }
+    }
+
+    /// Bare metal has no `ucontext_t`, and the stand-in holds no registers.
+    #[cfg(target_os = "none")]
+    fn new(_: &crate::cffi::ucontext_t) -> Self {
+        Self::from_pc_sp(0, 0)
+    }
+
+    fn from_pc_sp(pc: usize, sp: usize) -> Self {
+        let mut regs = [0; 16];
+        regs[PROGRAM_COUNTER_IDX as usize] = pc as u32;
+        regs[STACK_POINTER_IDX as usize] = sp as u32;
+        Self { regs }
+    }
+
+    /// The frame pointer is R11, as in ARM code.
+    fn from_pc_sp_fp(pc: usize, sp: usize, fp: usize) -> Self {
+        let mut state = Self::from_pc_sp(pc, sp);
+        state.regs[FRAME_POINTER_IDX as usize] = fp as u32;
+        state
+    }
+
+    fn get_program_counter(&self) -> usize {
+        self.regs[PROGRAM_COUNTER_IDX as usize] as usize
+    }
+
+    fn get_stack_pointer(&self) -> usize {
+        self.regs[STACK_POINTER_IDX as usize] as usize
+    }
+
+    fn get_register(&self, reg: Register) -> Result<usize, UnwindError> {
+        self.regs
+            .get(reg.0 as usize)
+            .map(|x| *x as usize)
+            .ok_or(UnwindError::NotSupported(
+                "only core registers can be retrieved in EHABI state",
+            ))
+    }
+
+    fn step_exidx<T: Target>(
+        &mut self,
+        insns: &Instructions,
+        target: &T,
+    ) -> Result<(), UnwindError> {
+        let mut regs = self.regs;
+        insns.execute(&mut regs, |addr| {
+            let mut buf = [0; 4];
+            target.read_memory(addr as usize, &mut buf)?;
+            Ok(u32::from_ne_bytes(buf))
+        })?;
+        if regs[PROGRAM_COUNTER_IDX as usize] == 0 {
+            return Err(UnwindError::UnwindEnded);
+        }
+        self.regs = regs;
+        Ok(())
+    }
+
+    fn get_cfa<R, S, T>(
+        &self,
+        row: &UnwindTableRow<R, S>,
+        _: &GlobalContext,
+        target: &T,
+    ) -> Result<usize, UnwindError>
+    where
+        R: Reader,
+        S: UnwindContextStorage<R>,
+        T: Target,
+    {
+        match row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => self
+                .get_register(*register)
+                .map(|x| (x as i64 + offset) as usize),
+            CfaRule::Expression(expr) => super::evaluate_cfa(self, expr, target),
+        }
+    }
+
+    fn step<R, S, T>(
+        &mut self,
+        row: &UnwindTableRow<R, S>,
+        return_address: Register,
+        g_ctx: &GlobalContext,
+        target: &T,
+    ) -> Result<(), UnwindError>
+    where
+        R: Reader,
+        S: UnwindContextStorage<R>,
+        T: Target,
+    {
+        let mut regs = self.regs;
+        for (reg, rule) in row.registers() {
+            if reg.0 < STACK_POINTER_IDX && !matches!(rule, RegisterRule::Undefined) {
+                regs[reg.0 as usize] = self.recover_register(*reg, row, g_ctx, target)? as u32;
+            }
+        }
+        regs[PROGRAM_COUNTER_IDX as usize] =
+            self.recover_register(return_address, row, g_ctx, target)? as u32;
+        regs[STACK_POINTER_IDX as usize] = self.get_cfa(row, g_ctx, target)? as u32;
+        self.regs = regs;
+        Ok(())
+    }
+}
@@ -1,5 +1,17 @@
-use crate::{GlobalContext, UnwindError};
-use gimli::{Register, RegisterRule, UnwindContextStorage};
+use crate::cursor::Target;
+use crate::{cffi, GlobalContext, UnwindError};
+use gimli::{
+    Endianity, EvaluationResult, Location, ReaderOffset, Register, RegisterRule,
+    UnwindContextStorage, Value, ValueType,
+};
+
+/// The operations a DWARF expression may execute, counting those of the DIEs it calls, before
+/// it is taken to loop forever.
+const MAX_EXPRESSION_ITERATIONS: u32 = 0x10000;
+
+/// The `DW_OP_call2` and `DW_OP_call4` a DWARF expression may make in all, which bounds how
+/// deep they nest when a DIE ends up calling itself.
+const MAX_EXPRESSION_CALLS: usize = 64;
 
 #[cfg(target_arch = "x86_64")]
 mod x86_64;
@@ -7,57 +19,547 @@ mod x86_64;
 #[cfg(target_arch = "x86_64")]
 pub use x86_64::*;
 
+#[cfg(target_arch = "arm")]
+mod arm;
+
+#[cfg(target_arch = "arm")]
+pub use arm::*;
+
+#[cfg(target_arch = "wasm32")]
+mod wasm32;
+
+#[cfg(target_arch = "wasm32")]
+pub use wasm32::*;
+
+/// The state covering the registers of the running architecture.
+#[cfg(target_arch = "x86_64")]
+pub type NativeState = FramePointerBasedState;
+#[cfg(target_arch = "arm")]
+pub type NativeState = EhabiState;
+#[cfg(target_arch = "wasm32")]
+pub type NativeState = UnsupportedState;
+
 pub trait CursorState: Sized + Copy + Clone {
-    fn new(u_ctx: &libc::ucontext_t) -> Self;
+    fn new(u_ctx: &cffi::ucontext_t) -> Self;
+    /// Create a state knowing only the program counter and the stack pointer.
+    fn from_pc_sp(pc: usize, sp: usize) -> Self;
+    /// Create a state knowing the program counter, the stack pointer and the frame pointer, so
+    /// that the first frame can be unwound through its frame record or CFI based on the frame
+    /// pointer.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn from_pc_sp_fp(pc: usize, sp: usize, fp: usize) -> Self;
     fn get_program_counter(&self) -> usize;
+    fn get_stack_pointer(&self) -> usize;
+    /// Retrieve a register by its DWARF number. All core registers are known in a state
+    /// created from a `ucontext_t`; the program counter and the stack pointer stay known across
+    /// steps, and other registers as long as the unwind info keeps track of them.
     fn get_register(&self, reg: Register) -> Result<usize, UnwindError>;
 
-    fn get_cfa<R, S>(
+    /// Retrieve a floating point or vector register by its DWARF number.
+    fn get_fp_register(&self, _reg: Register) -> Result<u128, UnwindError> {
+        Err(UnwindError::NotSupported(
+            "floating point registers are not tracked in this state",
+        ))
+    }
+
+    /// Step to the caller's frame following ARM EHABI unwind instructions.
+    #[cfg(target_arch = "arm")]
+    fn step_exidx<T: Target>(
+        &mut self,
+        _insns: &crate::image::exidx::Instructions,
+        _target: &T,
+    ) -> Result<(), UnwindError> {
+        Err(UnwindError::NotSupported(
+            "EHABI unwinding is not supported in this state",
+        ))
+    }
+
+    /// Step to the caller's frame following the frame pointer chain, returning the CFA.
+    fn step_frame_pointer<T: Target>(&mut self, _target: &T) -> Result<usize, UnwindError> {
+        Err(UnwindError::NotSupported(
+            "frame pointer unwinding is not supported in this state",
+        ))
+    }
+
+    /// Whether the code right before `return_address` decodes as a call instruction, as it
+    /// does for a genuine return address. States that cannot decode the instructions of their
+    /// architecture accept every address.
+    fn follows_call<T: Target>(_return_address: usize, _target: &T) -> bool {
+        true
+    }
+
+    /// The CFA of the frame following `row`, which may be given by an expression, e.g. in
+    /// functions that call `alloca` and so find their frame from the frame pointer.
+    fn get_cfa<R, S, T>(
         &self,
         row: &gimli::UnwindTableRow<R, S>,
         g_ctx: &GlobalContext,
+        target: &T,
     ) -> Result<usize, UnwindError>
     where
         R: gimli::Reader,
-        S: UnwindContextStorage<R>;
+        S: UnwindContextStorage<R>,
+        T: Target;
 
-    fn step<R, S>(
+    /// Step to the caller's frame following `row`. The caller's program counter is recovered
+    /// from `return_address`, the return address column of the CIE.
+    fn step<R, S, T>(
         &mut self,
         row: &gimli::UnwindTableRow<R, S>,
+        return_address: Register,
         g_ctx: &GlobalContext,
+        target: &T,
     ) -> Result<(), UnwindError>
     where
         R: gimli::Reader,
-        S: UnwindContextStorage<R>;
+        S: UnwindContextStorage<R>,
+        T: Target;
 
-    fn recover_register<R, S>(
+    fn recover_register<R, S, T>(
         &self,
         reg: Register,
         row: &gimli::UnwindTableRow<R, S>,
         g_ctx: &GlobalContext,
+        target: &T,
     ) -> Result<usize, UnwindError>
     where
         R: gimli::Reader,
         S: UnwindContextStorage<R>,
+        T: Target,
     {
-        self.get_cfa(row, g_ctx)
+        self.get_cfa(row, g_ctx, target)
             .and_then(|cfa| match row.register(reg) {
                 RegisterRule::Undefined => Err(UnwindError::UnwindEnded),
                 RegisterRule::SameValue => self.get_register(reg),
-                RegisterRule::Offset(offset) => unsafe {
-                    Ok(*((cfa as i64 + offset) as usize as *mut usize))
-                },
+                RegisterRule::Offset(offset) => target.read_usize((cfa as i64 + offset) as usize),
                 RegisterRule::ValOffset(offset) => Ok((cfa as i64 + offset) as usize),
-                RegisterRule::Register(target) => self.get_register(target),
+                RegisterRule::Register(other) => self.get_register(other),
                 RegisterRule::Expression(expr) => {
-                    todo!()
-                }
-                RegisterRule::ValExpression(_) => {
-                    todo!()
+                    let address = self.eval(&expr, cfi_encoding(), cfa, target)?;
+                    target.read_usize(address as usize)
                 }
+                RegisterRule::ValExpression(expr) => self
+                    .eval(&expr, cfi_encoding(), cfa, target)
+                    .map(|x| x as usize),
                 RegisterRule::Architectural => Err(UnwindError::NotSupported(
                     "target register recovery is architectural",
                 )),
+                RegisterRule::Constant(value) => Ok(value as usize),
+                // `RegisterRule` is non-exhaustive; rules added by newer gimli versions are
+                // reported rather than breaking the build.
+                _ => Err(UnwindError::NotSupported("unknown register rule")),
+            })
+    }
+
+    /// Evaluate a DWARF expression against this state. `cfa` is pushed onto the stack first,
+    /// as CFI expressions expect.
+    fn eval<R, T>(
+        &self,
+        expr: &gimli::Expression<R>,
+        encoding: gimli::Encoding,
+        cfa: usize,
+        target: &T,
+    ) -> Result<u64, UnwindError>
+    where
+        R: gimli::Reader,
+        T: Target,
+    {
+        evaluate(self, expr, &Environment::cfi(encoding, None, cfa), target)
+    }
+
+    /// Like [`CursorState::eval`], but typed operations resolve their base types in `unit`.
+    fn eval_in_unit<R, T>(
+        &self,
+        expr: &gimli::Expression<R>,
+        unit: &gimli::Unit<R>,
+        cfa: usize,
+        target: &T,
+    ) -> Result<u64, UnwindError>
+    where
+        R: gimli::Reader,
+        T: Target,
+    {
+        let environment = Environment::cfi(unit.encoding(), Some(unit), cfa);
+        evaluate(self, expr, &environment, target)
+    }
+
+    fn recover_fp_register<R, S, T>(
+        &self,
+        reg: Register,
+        row: &gimli::UnwindTableRow<R, S>,
+        g_ctx: &GlobalContext,
+        target: &T,
+    ) -> Result<u128, UnwindError>
+    where
+        R: gimli::Reader,
+        S: UnwindContextStorage<R>,
+        T: Target,
+    {
+        self.get_cfa(row, g_ctx, target)
+            .and_then(|cfa| match row.register(reg) {
+                RegisterRule::Undefined => Err(UnwindError::UnwindEnded),
+                RegisterRule::SameValue => self.get_fp_register(reg),
+                RegisterRule::Offset(offset) => {
+                    let mut buf = [0; core::mem::size_of::<u128>()];
+                    target.read_memory((cfa as i64 + offset) as usize, &mut buf)?;
+                    Ok(u128::from_ne_bytes(buf))
+                }
+                RegisterRule::Register(other) => self.get_fp_register(other),
+                RegisterRule::Constant(value) => Ok(value.into()),
+                _ => Err(UnwindError::NotSupported(
+                    "unsupported rule for floating point register recovery",
+                )),
             })
     }
 }
+
+/// Where the value of a variable lives in a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VariableLocation {
+    /// The variable is stored in memory at this address.
+    Address(u64),
+    /// The variable is not stored in memory, e.g. it is kept in a register or computed by its
+    /// location expression; this is its value.
+    Value(u64),
+}
+
+/// What an expression may refer to besides the registers and the memory of the frame.
+pub(crate) struct Environment<'u, R: gimli::Reader> {
+    pub encoding: gimli::Encoding,
+    /// The unit typed operations and DIE calls are resolved in.
+    pub unit: Option<&'u gimli::Unit<R>>,
+    /// The sections `DW_OP_addrx` reads from.
+    pub dwarf: Option<&'u gimli::Dwarf<R>>,
+    /// Pushed onto the stack first, as CFI expressions expect.
+    pub initial_value: Option<u64>,
+    /// The CFA of the frame, for `DW_OP_call_frame_cfa`.
+    pub cfa: Option<u64>,
+    /// The value of `DW_AT_frame_base` of the function, for `DW_OP_fbreg`.
+    pub frame_base: Option<u64>,
+    /// The bias of the image, added to the SVMAs the expression names, e.g. with `DW_OP_addr`.
+    pub bias: Option<u64>,
+}
+
+impl<'u, R: gimli::Reader> Environment<'u, R> {
+    /// The environment of a CFI expression, which starts with `cfa` on the stack.
+    fn cfi(encoding: gimli::Encoding, unit: Option<&'u gimli::Unit<R>>, cfa: usize) -> Self {
+        Self {
+            encoding,
+            unit,
+            dwarf: None,
+            initial_value: Some(cfa as u64),
+            cfa: Some(cfa as u64),
+            frame_base: None,
+            bias: None,
+        }
+    }
+}
+
+/// Evaluate the CFA of a frame whose CFI gives it by the expression `expr`, which starts with
+/// an empty stack.
+#[cfg(not(target_arch = "wasm32"))]
+fn evaluate_cfa<S, R, T>(
+    state: &S,
+    expr: &gimli::Expression<R>,
+    target: &T,
+) -> Result<usize, UnwindError>
+where
+    S: CursorState,
+    R: gimli::Reader,
+    T: Target,
+{
+    let environment = Environment {
+        initial_value: None,
+        cfa: None,
+        ..Environment::cfi(cfi_encoding(), None, 0)
+    };
+    evaluate(state, expr, &environment, target).map(|x| x as usize)
+}
+
+/// Drive the evaluation of `expr`, answering its requests from `state`. An address is returned
+/// as is, so the caller decides whether to read memory there.
+fn evaluate<S, R, T>(
+    state: &S,
+    expr: &gimli::Expression<R>,
+    environment: &Environment<R>,
+    target: &T,
+) -> Result<u64, UnwindError>
+where
+    S: CursorState,
+    R: gimli::Reader,
+    T: Target,
+{
+    evaluate_location(state, expr, environment, target).map(|x| match x {
+        VariableLocation::Address(x) | VariableLocation::Value(x) => x,
+    })
+}
+
+/// Like [`evaluate`], but telling an address from a value.
+pub(crate) fn evaluate_location<S, R, T>(
+    state: &S,
+    expr: &gimli::Expression<R>,
+    environment: &Environment<R>,
+    target: &T,
+) -> Result<VariableLocation, UnwindError>
+where
+    S: CursorState,
+    R: gimli::Reader,
+    T: Target,
+{
+    let Environment { encoding, unit, .. } = *environment;
+    let mut evaluation = expr.clone().evaluation(encoding);
+    evaluation.set_max_iterations(MAX_EXPRESSION_ITERATIONS);
+    if let Some(value) = environment.initial_value {
+        evaluation.set_initial_value(value);
+    }
+    let relocate = |address: u64| {
+        environment
+            .bias
+            .map(|bias| address.wrapping_add(bias))
+            .ok_or(UnwindError::NotSupported(
+                "DWARF expression names an address without its image",
+            ))
+    };
+    let mut calls = 0;
+    let mut result = evaluation.evaluate()?;
+    loop {
+        result = match result {
+            EvaluationResult::Complete => break,
+            EvaluationResult::RequiresMemory {
+                address,
+                size,
+                space: None,
+                base_type: offset,
+            } => {
+                let value = read_memory(target, address, size)?;
+                let value_type = base_type(unit, offset)?;
+                evaluation.resume_with_memory(Value::from_u64(value_type, value)?)?
+            }
+            EvaluationResult::RequiresRegister {
+                register,
+                base_type: offset,
+            } => {
+                let value = read_register(state, target, register)? as u64;
+                let value_type = base_type(unit, offset)?;
+                evaluation.resume_with_register(Value::from_u64(value_type, value)?)?
+            }
+            EvaluationResult::RequiresBaseType(offset) => {
+                evaluation.resume_with_base_type(base_type(unit, offset)?)?
+            }
+            EvaluationResult::RequiresCallFrameCfa => match environment.cfa {
+                Some(cfa) => evaluation.resume_with_call_frame_cfa(cfa)?,
+                None => return Err(UnwindError::NotSupported("the CFA of the frame is unknown")),
+            },
+            EvaluationResult::RequiresFrameBase => match environment.frame_base {
+                Some(frame_base) => evaluation.resume_with_frame_base(frame_base)?,
+                None => {
+                    return Err(UnwindError::NotSupported(
+                        "the frame base of the function is unknown",
+                    ))
+                }
+            },
+            EvaluationResult::RequiresRelocatedAddress(address) => {
+                evaluation.resume_with_relocated_address(relocate(address)?)?
+            }
+            EvaluationResult::RequiresIndexedAddress { index, relocate: r } => {
+                let (dwarf, unit) =
+                    environment
+                        .dwarf
+                        .zip(unit)
+                        .ok_or(UnwindError::NotSupported(
+                            "indexed address without a compilation unit",
+                        ))?;
+                let address = dwarf.address(unit, index)?;
+                let address = if r { relocate(address)? } else { address };
+                evaluation.resume_with_indexed_address(address)?
+            }
+            EvaluationResult::RequiresAtLocation(reference) => {
+                calls += 1;
+                if calls > MAX_EXPRESSION_CALLS {
+                    return Err(UnwindError::UnwindLogicalError(
+                        "DWARF expression calls too many DIEs",
+                    ));
+                }
+                evaluation.resume_with_at_location(at_location(unit, reference, &expr.0)?)?
+            }
+            // `DW_OP_form_tls_address` names an offset into the TLS block of the thread.
+            EvaluationResult::RequiresTls(offset) => {
+                let base = target.tls_base()? as u64;
+                evaluation.resume_with_tls(base.wrapping_add(offset))?
+            }
+            EvaluationResult::RequiresParameterRef(_) => {
+                return Err(UnwindError::NotSupported(
+                    "DW_OP_GNU_parameter_ref needs the values at the call site",
+                ))
+            }
+            _ => {
+                return Err(UnwindError::NotSupported(
+                    "unsupported operation in DWARF expression",
+                ))
+            }
+        }
+    }
+    let address_bits = 8 * u64::from(encoding.address_size);
+    match evaluation.result().as_slice() {
+        // A single `DW_OP_bit_piece` covering a whole address selects where in the location the
+        // value starts.
+        [piece] if piece.size_in_bits.is_none_or(|x| x == address_bits) => {
+            let offset = piece_offset(piece.bit_offset)?;
+            match &piece.location {
+                Location::Address { address } => address
+                    .checked_add(offset)
+                    .map(VariableLocation::Address)
+                    .ok_or(UnwindError::UnwindLogicalError(
+                        "DWARF expression yields an invalid address",
+                    )),
+                _ if offset != 0 => Err(UnwindError::NotSupported(
+                    "offset piece of a DWARF expression result not in memory",
+                )),
+                Location::Register { register } => read_register(state, target, *register)
+                    .map(|x| VariableLocation::Value(x as u64)),
+                Location::Value { value } => {
+                    value_to_u64(*value, encoding).map(VariableLocation::Value)
+                }
+                Location::Bytes { value } => bytes_to_u64(value).map(VariableLocation::Value),
+                _ => Err(UnwindError::NotSupported(
+                    "unsupported location in DWARF expression result",
+                )),
+            }
+        }
+        _ => Err(UnwindError::NotSupported(
+            "composite location in DWARF expression result",
+        )),
+    }
+}
+
+/// Read `register` from the frame in `state`, or from `target` if it is a register of the whole
+/// thread that the state does not track.
+fn read_register<S: CursorState, T: Target>(
+    state: &S,
+    target: &T,
+    register: Register,
+) -> Result<usize, UnwindError> {
+    state
+        .get_register(register)
+        .or_else(|error| target.thread_register(register).map_err(|_| error))
+}
+
+/// The location expression of the DIE `DW_OP_call2` or `DW_OP_call4` refers to, which is
+/// empty if the DIE has none. Only DIEs in `unit` can be followed.
+fn at_location<R: gimli::Reader>(
+    unit: Option<&gimli::Unit<R>>,
+    reference: gimli::DieReference<R::Offset>,
+    expr: &R,
+) -> Result<R, UnwindError> {
+    let (unit, offset) = match (unit, reference) {
+        (Some(unit), gimli::DieReference::UnitRef(offset)) => (unit, offset),
+        _ => {
+            return Err(UnwindError::NotSupported(
+                "DWARF expression calls a DIE outside the unit",
+            ))
+        }
+    };
+    match unit.entry(offset)?.attr_value(gimli::DW_AT_location)? {
+        Some(gimli::AttributeValue::Exprloc(location)) => Ok(location.0),
+        Some(_) => Err(UnwindError::NotSupported(
+            "DWARF expression calls a DIE with a location list",
+        )),
+        None => {
+            let mut empty = expr.clone();
+            empty.empty();
+            Ok(empty)
+        }
+    }
+}
+
+/// The byte offset of a piece into its location. Pieces starting within a byte cannot be
+/// addressed.
+fn piece_offset(bit_offset: Option<u64>) -> Result<u64, UnwindError> {
+    match bit_offset.unwrap_or(0) {
+        x if x % 8 == 0 => Ok(x / 8),
+        _ => Err(UnwindError::NotSupported(
+            "DWARF expression result starts within a byte",
+        )),
+    }
+}
+
+/// Resolve the base type DIE at `offset` in `unit`. The zero offset denotes the generic type.
+fn base_type<R: gimli::Reader>(
+    unit: Option<&gimli::Unit<R>>,
+    offset: gimli::UnitOffset<R::Offset>,
+) -> Result<ValueType, UnwindError> {
+    if offset.0.into_u64() == 0 {
+        return Ok(ValueType::Generic);
+    }
+    let unit = unit.ok_or(UnwindError::NotSupported(
+        "typed DWARF expressions need a compilation unit",
+    ))?;
+    let entry = unit.entry(offset)?;
+    let byte_size = entry
+        .attr_value(gimli::DW_AT_byte_size)?
+        .and_then(|x| x.udata_value());
+    let encoding = match entry.attr_value(gimli::DW_AT_encoding)? {
+        Some(gimli::AttributeValue::Encoding(x)) => Some(x),
+        _ => None,
+    };
+    encoding
+        .zip(byte_size)
+        .and_then(|(encoding, byte_size)| ValueType::from_encoding(encoding, byte_size))
+        .ok_or(UnwindError::NotSupported(
+            "unsupported base type in DWARF expression",
+        ))
+}
+
+/// The encoding CFI expressions are evaluated with. They may not refer to other DIEs, so only
+/// the address size matters.
+fn cfi_encoding() -> gimli::Encoding {
+    gimli::Encoding {
+        format: gimli::Format::Dwarf32,
+        version: 4,
+        address_size: core::mem::size_of::<usize>() as u8,
+    }
+}
+
+fn address_mask(encoding: gimli::Encoding) -> u64 {
+    !0 >> (64 - 8 * u32::from(encoding.address_size))
+}
+
+/// Convert a stack value to a 64-bit integer. Generic values are truncated to the address
+/// size, while values of signed base types are sign-extended.
+fn value_to_u64(value: Value, encoding: gimli::Encoding) -> Result<u64, UnwindError> {
+    value.to_u64(address_mask(encoding)).map_err(|_| {
+        UnwindError::UnwindLogicalError("DWARF expression yields a non-integral value")
+    })
+}
+
+/// Read `size` bytes at `address` as a native endian integer.
+fn read_memory<T: Target>(target: &T, address: u64, size: u8) -> Result<u64, UnwindError> {
+    if size as usize > core::mem::size_of::<u64>() {
+        return Err(UnwindError::NotSupported("memory read wider than 64 bits"));
+    }
+    let mut buf = [0u8; 8];
+    #[cfg(target_endian = "little")]
+    let start = 0;
+    #[cfg(target_endian = "big")]
+    let start = 8 - size as usize;
+    target.read_memory(address as usize, &mut buf[start..start + size as usize])?;
+    Ok(u64::from_ne_bytes(buf))
+}
+
+/// Zero-extend the bytes of an implicit value to 64 bits, honoring the reader's endianness.
+fn bytes_to_u64<R: gimli::Reader>(value: &R) -> Result<u64, UnwindError> {
+    let bytes = value.to_slice()?;
+    if bytes.len() > core::mem::size_of::<u64>() {
+        return Err(UnwindError::NotSupported(
+            "implicit value wider than 64 bits",
+        ));
+    }
+    let mut buf = [0u8; 8];
+    if value.endian().is_big_endian() {
+        buf[8 - bytes.len()..].copy_from_slice(&bytes);
+        Ok(u64::from_be_bytes(buf))
+    } else {
+        buf[..bytes.len()].copy_from_slice(&bytes);
+        Ok(u64::from_le_bytes(buf))
+    }
+}
@@ -0,0 +1,69 @@
+use crate::cursor::state::CursorState;
+use crate::cursor::Target;
+use crate::{cffi, GlobalContext, UnwindError};
+use gimli::{Reader, Register, UnwindContextStorage, UnwindTableRow};
+
+/// Placeholder state for WebAssembly, where the call stack lives outside linear memory and
+/// cannot be walked. It only keeps the values it is created with; every step fails.
+#[derive(Copy, Clone)]
+pub struct UnsupportedState {
+    pc: usize,
+    sp: usize,
+}
+
+impl CursorState for UnsupportedState {
+    fn new(_: &cffi::ucontext_t) -> Self {
+        Self { pc: 0, sp: 0 }
+    }
+
+    fn from_pc_sp(pc: usize, sp: usize) -> Self {
+        Self { pc, sp }
+    }
+
+    fn get_program_counter(&self) -> usize {
+        self.pc
+    }
+
+    fn get_stack_pointer(&self) -> usize {
+        self.sp
+    }
+
+    fn get_register(&self, _: Register) -> Result<usize, UnwindError> {
+        Err(UnwindError::NotSupported(
+            "registers are not available on WebAssembly",
+        ))
+    }
+
+    fn get_cfa<R, S, T>(
+        &self,
+        _: &UnwindTableRow<R, S>,
+        _: &GlobalContext,
+        _: &T,
+    ) -> Result<usize, UnwindError>
+    where
+        R: Reader,
+        S: UnwindContextStorage<R>,
+        T: Target,
+    {
+        Err(UnwindError::NotSupported(
+            "unwinding is not supported on WebAssembly",
+        ))
+    }
+
+    fn step<R, S, T>(
+        &mut self,
+        _: &UnwindTableRow<R, S>,
+        _: Register,
+        _: &GlobalContext,
+        _: &T,
+    ) -> Result<(), UnwindError>
+    where
+        R: Reader,
+        S: UnwindContextStorage<R>,
+        T: Target,
+    {
+        Err(UnwindError::NotSupported(
+            "unwinding is not supported on WebAssembly",
+        ))
+    }
+}
@@ -1,84 +1,309 @@
 use crate::cursor::state::CursorState;
+use crate::cursor::Target;
 use crate::{GlobalContext, UnwindError};
-use gimli::{CfaRule, Reader, Register, UnwindContextStorage, UnwindTableRow};
+use gimli::{
+    CfaRule, Reader, Register, RegisterRule, UnwindContextStorage, UnwindTableRow, X86_64,
+};
 
 #[derive(Copy, Clone)]
 pub struct FramePointerBasedState {
-    rip: usize,
-    rsp: usize,
+    /// General purpose registers and the return address, indexed by DWARF register number.
+    regs: [usize; REGISTER_COUNT],
+    /// Bit `i` is set if `regs[i]` holds a known value.
+    regs_valid: u32,
+    xmm: [u128; XMM_COUNT],
+    /// Bit `i` is set if `xmm[i]` holds a known value.
+    xmm_valid: u16,
 }
 
 const STACK_POINTER_IDX: u16 = 7;
 const RETURN_ADDRESS_IDX: u16 = 16;
+const REGISTER_COUNT: usize = 17;
+const XMM_BASE_IDX: u16 = 17;
+const XMM_COUNT: usize = 16;
+/// The registers a callee preserves in the System V ABI: RBX, RBP, RSP and R12-R15. The
+/// others, the vector registers included, may be clobbered by any call.
+const CALLEE_SAVED: u32 = 1 << 3 | 1 << 6 | 1 << 7 | 0xf << 12;
+
+/// The `gregs` slots of the registers in DWARF order: RAX, RDX, RCX, RBX, RSI, RDI, RBP, RSP,
+/// R8-R15 and RIP.
+#[cfg(target_os = "linux")]
+const GREGS: [libc::c_int; REGISTER_COUNT] = [
+    libc::REG_RAX,
+    libc::REG_RDX,
+    libc::REG_RCX,
+    libc::REG_RBX,
+    libc::REG_RSI,
+    libc::REG_RDI,
+    libc::REG_RBP,
+    libc::REG_RSP,
+    libc::REG_R8,
+    libc::REG_R9,
+    libc::REG_R10,
+    libc::REG_R11,
+    libc::REG_R12,
+    libc::REG_R13,
+    libc::REG_R14,
+    libc::REG_R15,
+    libc::REG_RIP,
+];
+
+impl FramePointerBasedState {
+    fn register_index(reg: Register) -> Option<usize> {
+        Some(usize::from(reg.0)).filter(|x| *x < REGISTER_COUNT)
+    }
+
+    fn xmm_index(reg: Register) -> Option<usize> {
+        reg.0
+            .checked_sub(XMM_BASE_IDX)
+            .map(usize::from)
+            .filter(|x| *x < XMM_COUNT)
+    }
+
+    /// Read the general purpose registers from `gregs`, whose layout is up to the C library.
+    /// A register without a slot in the array is left unknown rather than read out of bounds.
+    #[cfg(target_os = "linux")]
+    fn read_gregs(gregs: &[libc::greg_t]) -> ([usize; REGISTER_COUNT], u32) {
+        let mut regs = [0; REGISTER_COUNT];
+        let mut valid = 0;
+        for (i, slot) in GREGS.iter().enumerate() {
+            if let Some(value) = usize::try_from(*slot).ok().and_then(|x| gregs.get(x)) {
+                regs[i] = *value as usize;
+                valid |= 1 << i;
+            }
+        }
+        (regs, valid)
+    }
+
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    fn read_xmm(uctx: &libc::ucontext_t) -> ([u128; XMM_COUNT], u16) {
+        let mut xmm = [0; XMM_COUNT];
+        if uctx.uc_mcontext.fpregs.is_null() {
+            return (xmm, 0);
+        }
+        let fpregs = unsafe { &*uctx.uc_mcontext.fpregs };
+        for (value, reg) in xmm.iter_mut().zip(fpregs._xmm.iter()) {
+            *value = reg
+                .element
+                .iter()
+                .rev()
+                .fold(0, |acc, x| (acc << 32) | *x as u128);
+        }
+        (xmm, u16::MAX)
+    }
+
+    /// The libc crate keeps the pointer to the floating point state private on other C
+    /// libraries, e.g. musl, so vector registers are unknown there.
+    #[cfg(all(target_os = "linux", not(target_env = "gnu")))]
+    fn read_xmm(_: &libc::ucontext_t) -> ([u128; XMM_COUNT], u16) {
+        ([0; XMM_COUNT], 0)
+    }
+}
 
 impl CursorState for FramePointerBasedState {
     #[cfg(target_os = "linux")]
     fn new(uctx: &libc::ucontext_t) -> Self {
+        let (regs, regs_valid) = Self::read_gregs(&uctx.uc_mcontext.gregs);
+        let (xmm, xmm_valid) = Self::read_xmm(uctx);
         Self {
-            rip: uctx.uc_mcontext.gregs[libc::REG_RIP as usize] as _,
-            rsp: uctx.uc_mcontext.gregs[libc::REG_RSP as usize] as _,
+            regs,
+            regs_valid,
+            xmm,
+            xmm_valid,
         }
     }
 
     #[cfg(target_os = "macos")]
     fn new(uctx: &libc::ucontext_t) -> Self {
-        unsafe {
-            Self {
-                rip: (*uctx.uc_mcontext).__ss.__rip as _,
-                rsp: (*uctx.uc_mcontext).__ss.__rsp as _,
-            }
+        let ss = unsafe { &(*uctx.uc_mcontext).__ss };
+        Self {
+            regs: [
+                ss.__rax, ss.__rdx, ss.__rcx, ss.__rbx, ss.__rsi, ss.__rdi, ss.__rbp, ss.__rsp,
+                ss.__r8, ss.__r9, ss.__r10, ss.__r11, ss.__r12, ss.__r13, ss.__r14, ss.__r15,
+                ss.__rip,
+            ]
+            .map(|x| x as usize),
+            regs_valid: (1 << REGISTER_COUNT) - 1,
+            xmm: [0; XMM_COUNT],
+            xmm_valid: 0,
         }
     }
 
+    /// Bare metal has no `ucontext_t`, and the stand-in holds no registers.
+    #[cfg(target_os = "none")]
+    fn new(_: &crate::cffi::ucontext_t) -> Self {
+        Self::from_pc_sp(0, 0)
+    }
+
+    fn from_pc_sp(pc: usize, sp: usize) -> Self {
+        let mut regs = [0; REGISTER_COUNT];
+        regs[RETURN_ADDRESS_IDX as usize] = pc;
+        regs[STACK_POINTER_IDX as usize] = sp;
+        Self {
+            regs,
+            regs_valid: 1 << RETURN_ADDRESS_IDX | 1 << STACK_POINTER_IDX,
+            xmm: [0; XMM_COUNT],
+            xmm_valid: 0,
+        }
+    }
+
+    fn from_pc_sp_fp(pc: usize, sp: usize, fp: usize) -> Self {
+        let mut state = Self::from_pc_sp(pc, sp);
+        state.regs[X86_64::RBP.0 as usize] = fp;
+        state.regs_valid |= 1 << X86_64::RBP.0;
+        state
+    }
+
     fn get_program_counter(&self) -> usize {
-        self.rip
+        self.regs[RETURN_ADDRESS_IDX as usize]
+    }
+
+    fn get_stack_pointer(&self) -> usize {
+        self.regs[STACK_POINTER_IDX as usize]
     }
 
     fn get_register(&self, reg: Register) -> Result<usize, UnwindError> {
-        match reg.0 {
-            STACK_POINTER_IDX => Ok(self.rsp),
-            _ => Err(UnwindError::NotSupported(
-                "only RSP can be retrieved in frame pointer based state",
+        match Self::register_index(reg) {
+            Some(i) if self.regs_valid & (1 << i) != 0 => Ok(self.regs[i]),
+            Some(_) => Err(UnwindError::NotSupported("register value is unavailable")),
+            None => Err(UnwindError::NotSupported(
+                "only general purpose registers and RIP can be retrieved in frame pointer based \
+                 state",
             )),
         }
     }
 
-    fn get_cfa<R, S>(
+    fn get_fp_register(&self, reg: Register) -> Result<u128, UnwindError> {
+        match Self::xmm_index(reg) {
+            Some(i) if self.xmm_valid & (1 << i) != 0 => Ok(self.xmm[i]),
+            Some(_) => Err(UnwindError::NotSupported(
+                "vector register value is unavailable",
+            )),
+            None => Err(UnwindError::NotSupported(
+                "only XMM0-XMM15 can be retrieved in frame pointer based state",
+            )),
+        }
+    }
+
+    fn step_frame_pointer<T: Target>(&mut self, target: &T) -> Result<usize, UnwindError> {
+        let rbp = self.get_register(X86_64::RBP)?;
+        if rbp == 0 {
+            return Err(UnwindError::UnwindEnded);
+        }
+        // The frame record lives in the frame, above the stack pointer.
+        if rbp < self.get_register(X86_64::RSP)? {
+            return Err(UnwindError::UnwindLogicalError(
+                "frame pointer lies below the stack pointer",
+            ));
+        }
+        let caller_rbp = target.read_usize(rbp)?;
+        let return_address = target.read_usize(rbp + 8)?;
+        let cfa = rbp + 16;
+        // Nothing is known about the other callee-saved registers.
+        self.regs[X86_64::RBP.0 as usize] = caller_rbp;
+        self.regs[RETURN_ADDRESS_IDX as usize] = return_address;
+        self.regs[STACK_POINTER_IDX as usize] = cfa;
+        self.regs_valid = 1 << X86_64::RBP.0 | 1 << RETURN_ADDRESS_IDX | 1 << STACK_POINTER_IDX;
+        self.xmm_valid = 0;
+        Ok(cfa)
+    }
+
+    fn follows_call<T: Target>(return_address: usize, target: &T) -> bool {
+        let mut code = [0; 8];
+        let start = match return_address.checked_sub(code.len()) {
+            Some(start) => start,
+            None => return false,
+        };
+        target.read_memory(start, &mut code).is_ok() && ends_with_call(&code)
+    }
+
+    fn get_cfa<R, S, T>(
         &self,
         row: &UnwindTableRow<R, S>,
         _: &GlobalContext,
+        target: &T,
     ) -> Result<usize, UnwindError>
     where
         R: Reader,
         S: UnwindContextStorage<R>,
+        T: Target,
     {
         match row.cfa() {
             CfaRule::RegisterAndOffset { register, offset } => {
-                if register.0 == STACK_POINTER_IDX {
-                    Ok((self.rsp as i64 + offset) as usize)
-                } else {
-                    Err(UnwindError::NotSupported(
-                        "CFA value is only derivable from RSP in frame pointer based state",
-                    ))
-                }
+                Ok((self.get_register(*register)? as i64 + offset) as usize)
             }
-            CfaRule::Expression(_) => Err(UnwindError::NotSupported(
-                "CFA expression is not supported in frame pointer based state",
-            )),
+            CfaRule::Expression(expr) => super::evaluate_cfa(self, expr, target),
         }
     }
 
-    fn step<R, S>(
+    fn step<R, S, T>(
         &mut self,
         row: &UnwindTableRow<R, S>,
+        return_address: Register,
         g_ctx: &GlobalContext,
+        target: &T,
     ) -> Result<(), UnwindError>
     where
         R: Reader,
         S: UnwindContextStorage<R>,
+        T: Target,
     {
-        self.rip = self.recover_register(Register(RETURN_ADDRESS_IDX), row, g_ctx)?;
-        self.rsp = self.get_cfa(&row, g_ctx)?;
+        // Callee-saved registers without a rule keep their value, while the others were
+        // clobbered by the call and are only known if a rule recovers them. All rules are
+        // applied to the callee's registers, so recover into a copy.
+        let mut next = *self;
+        next.regs_valid &= CALLEE_SAVED;
+        next.xmm_valid = 0;
+        for (reg, rule) in row.registers() {
+            if let Some(i) = Self::xmm_index(*reg) {
+                // Vector registers are not needed to go on unwinding, so one whose rule cannot
+                // be followed is left unknown rather than failing the step.
+                match self.recover_fp_register(*reg, row, g_ctx, target) {
+                    Ok(value) => {
+                        next.xmm[i] = value;
+                        next.xmm_valid |= 1 << i;
+                    }
+                    Err(UnwindError::NotSupported(_) | UnwindError::UnwindEnded) => {}
+                    Err(e) => return Err(e),
+                }
+            } else if let Some(i) = Self::register_index(*reg) {
+                if matches!(rule, RegisterRule::Undefined) {
+                    next.regs_valid &= !(1 << i);
+                } else {
+                    next.regs[i] = self.recover_register(*reg, row, g_ctx, target)?;
+                    next.regs_valid |= 1 << i;
+                }
+            }
+        }
+        next.regs[RETURN_ADDRESS_IDX as usize] =
+            self.recover_register(return_address, row, g_ctx, target)?;
+        next.regs[STACK_POINTER_IDX as usize] = self.get_cfa(row, g_ctx, target)?;
+        next.regs_valid |= 1 << RETURN_ADDRESS_IDX | 1 << STACK_POINTER_IDX;
+        *self = next;
         Ok(())
     }
 }
+
+/// Whether `code` ends with a `call`, either direct (`E8 rel32`) or indirect (`FF /2`) through
+/// a register or memory operand. Prefixes before the opcode do not change where it ends.
+fn ends_with_call(code: &[u8; 8]) -> bool {
+    if code[3] == 0xe8 {
+        return true;
+    }
+    (2..=7).any(|len| {
+        let (opcode, modrm) = (code[8 - len], code[9 - len]);
+        // The length of the instruction follows from its ModRM byte and the SIB base.
+        let encoded = match (modrm >> 6, modrm & 7) {
+            (3, _) => 2,
+            (0, 4) if len >= 3 && code[10 - len] & 7 == 5 => 7,
+            (0, 4) => 3,
+            (0, 5) => 6,
+            (0, _) => 2,
+            (1, 4) => 4,
+            (1, _) => 3,
+            (_, 4) => 7,
+            _ => 6,
+        };
+        opcode == 0xff && (modrm >> 3) & 7 == 2 && encoded == len
+    })
+}
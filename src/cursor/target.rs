@@ -0,0 +1,277 @@
+use crate::{cffi, UnwindError};
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+use {core::mem::MaybeUninit, nix::errno::Errno};
+
+/// The thread being unwound: where its memory is read from and how its registers are
+/// captured. The stepping logic of the cursor is shared by all targets.
+///
+/// Contract:
+/// - `read_memory` either fills the whole buffer or returns an error. Data is interpreted in
+///   the native byte order of the unwinder.
+/// - `initial_context` captures the registers of the first frame to unwind. It is only called
+///   when a cursor is created from the target itself; cursors built from an explicit
+///   `ucontext_t` or PC/SP pair never call it, so targets that cannot capture registers, e.g. a
+///   stack dumped on bare metal, may leave it out.
+/// - Cursors clone the target for each step, so cloning should be cheap, e.g. a handle or a
+///   reference.
+pub trait Target: Clone {
+    /// Read `buf.len()` bytes at `address`.
+    fn read_memory(&self, address: usize, buf: &mut [u8]) -> Result<(), UnwindError>;
+
+    /// Capture the registers to start unwinding from.
+    fn initial_context(&self) -> Result<cffi::ucontext_t, UnwindError> {
+        Err(UnwindError::NotSupported(
+            "registers cannot be captured from this target",
+        ))
+    }
+
+    /// The thread pointer of the target thread, i.e. the base its thread-local storage is
+    /// addressed from.
+    fn tls_base(&self) -> Result<usize, UnwindError> {
+        Err(UnwindError::NotSupported(
+            "TLS base is not available for this target",
+        ))
+    }
+
+    /// A register of the target thread that is the same in every frame, so the cursor state
+    /// does not track it, e.g. a segment base on x86_64.
+    fn thread_register(&self, register: gimli::Register) -> Result<usize, UnwindError> {
+        let _ = register;
+        Err(UnwindError::NotSupported(
+            "thread registers are not available for this target",
+        ))
+    }
+
+    /// The address range of the target thread's stack.
+    fn stack_bounds(&self) -> Result<core::ops::Range<usize>, UnwindError> {
+        Err(UnwindError::NotSupported(
+            "stack bounds are not available for this target",
+        ))
+    }
+
+    /// Read a pointer-sized word at `address`.
+    fn read_usize(&self, address: usize) -> Result<usize, UnwindError> {
+        let mut buf = [0; core::mem::size_of::<usize>()];
+        self.read_memory(address, &mut buf)?;
+        Ok(usize::from_ne_bytes(buf))
+    }
+}
+
+/// The current thread of the current process.
+///
+/// On Linux, memory inside the stack of the thread is read directly, and any other address is
+/// read through `process_vm_readv`, so an unreadable address, e.g. in the guard page below an
+/// overflowed stack, ends the unwind with [`UnwindError::UnwindEnded`] instead of faulting. If
+/// the call itself is refused, e.g. by a seccomp filter, every read outside the stack fails;
+/// [`UncheckedLocalTarget`] reads directly instead. Elsewhere, and without the `std` feature,
+/// memory is read directly, so every address derived from the unwind info must be mapped.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LocalTarget;
+
+impl LocalTarget {
+    /// Whether the range lies inside the stack of the calling thread, which is mapped, so it
+    /// can be read without asking the kernel. The bounds are looked up once per thread.
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    fn in_stack(address: usize, len: usize) -> bool {
+        let end = address.checked_add(len);
+        matches!(
+            (cffi::stack_bounds(), end),
+            (Ok(bounds), Some(end)) if bounds.start <= address && end <= bounds.end
+        )
+    }
+
+    #[cfg(not(all(feature = "std", target_os = "linux")))]
+    fn in_stack(_: usize, _: usize) -> bool {
+        false
+    }
+
+    /// Read through the kernel, which checks the mapping. `None` if there is no such call.
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    fn read_checked(address: usize, buf: &mut [u8]) -> Option<Result<(), UnwindError>> {
+        #[cfg(test)]
+        test::CHECKED_READS.with(|x| x.set(x.get() + 1));
+        let local = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let remote = libc::iovec {
+            iov_base: address as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let read = unsafe { libc::process_vm_readv(cffi::pid(), &local, 1, &remote, 1, 0) };
+        match Errno::result(read) {
+            Ok(read) if read as usize == buf.len() => Some(Ok(())),
+            Ok(_) | Err(Errno::EFAULT) => Some(Err(UnwindError::UnwindEnded)),
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+
+    #[cfg(not(all(feature = "std", target_os = "linux")))]
+    fn read_checked(_: usize, _: &mut [u8]) -> Option<Result<(), UnwindError>> {
+        None
+    }
+}
+
+/// Copy `buf.len()` bytes at `address` without checking the mapping.
+///
+/// # Safety
+/// The whole range must be mapped and readable.
+unsafe fn read_unchecked(address: usize, buf: &mut [u8]) {
+    core::ptr::copy_nonoverlapping(address as *const u8, buf.as_mut_ptr(), buf.len());
+}
+
+impl Target for LocalTarget {
+    fn read_memory(&self, address: usize, buf: &mut [u8]) -> Result<(), UnwindError> {
+        if !Self::in_stack(address, buf.len()) {
+            if let Some(result) = Self::read_checked(address, buf) {
+                return result;
+            }
+        }
+        unsafe { read_unchecked(address, buf) };
+        Ok(())
+    }
+
+    // Inlined so that the captured frame is the one of the caller, which stays alive while
+    // unwinding.
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+    #[inline(always)]
+    fn initial_context(&self) -> Result<cffi::ucontext_t, UnwindError> {
+        let mut ucp = MaybeUninit::<libc::ucontext_t>::zeroed();
+        let ret = unsafe { cffi::getcontext(ucp.as_mut_ptr()) };
+        Errno::result(ret)
+            .map(|_| unsafe { ucp.assume_init() })
+            .map_err(Into::into)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn initial_context(&self) -> Result<cffi::ucontext_t, UnwindError> {
+        Err(UnwindError::NotSupported(
+            "registers cannot be captured on WebAssembly",
+        ))
+    }
+
+    #[cfg(all(not(feature = "std"), not(target_arch = "wasm32")))]
+    fn initial_context(&self) -> Result<cffi::ucontext_t, UnwindError> {
+        Err(UnwindError::NotSupported(
+            "registers are only captured with the std feature",
+        ))
+    }
+
+    #[cfg(all(feature = "std", any(target_os = "linux", target_os = "macos")))]
+    fn stack_bounds(&self) -> Result<core::ops::Range<usize>, UnwindError> {
+        cffi::stack_bounds().map_err(Into::into)
+    }
+
+    #[cfg(all(feature = "std", target_os = "linux", target_arch = "x86_64"))]
+    fn tls_base(&self) -> Result<usize, UnwindError> {
+        cffi::thread_pointer().map_err(Into::into)
+    }
+
+    /// The segment bases live in model specific registers rather than in the context, so they
+    /// are read from the calling thread when an expression needs them.
+    #[cfg(all(feature = "std", target_os = "linux", target_arch = "x86_64"))]
+    fn thread_register(&self, register: gimli::Register) -> Result<usize, UnwindError> {
+        match register {
+            gimli::X86_64::FS_BASE => self.tls_base(),
+            gimli::X86_64::GS_BASE => cffi::gs_base().map_err(Into::into),
+            _ => Err(UnwindError::NotSupported(
+                "only the segment bases are thread registers",
+            )),
+        }
+    }
+
+    #[cfg(any(target_arch = "aarch64", target_arch = "arm"))]
+    fn tls_base(&self) -> Result<usize, UnwindError> {
+        Ok(cffi::thread_pointer())
+    }
+}
+
+/// The current thread of the current process, read directly rather than through the kernel,
+/// e.g. where `process_vm_readv` is refused by a seccomp filter. An address derived from
+/// malformed unwind info faults instead of ending the unwind.
+#[derive(Copy, Clone, Debug)]
+pub struct UncheckedLocalTarget(());
+
+impl UncheckedLocalTarget {
+    /// # Safety
+    /// Every address the unwind info of the stack leads to must be mapped and readable, i.e.
+    /// the unwind info of all code on the stack must be correct.
+    pub unsafe fn new() -> Self {
+        Self(())
+    }
+}
+
+impl Target for UncheckedLocalTarget {
+    fn read_memory(&self, address: usize, buf: &mut [u8]) -> Result<(), UnwindError> {
+        unsafe { read_unchecked(address, buf) };
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn initial_context(&self) -> Result<cffi::ucontext_t, UnwindError> {
+        LocalTarget.initial_context()
+    }
+
+    fn tls_base(&self) -> Result<usize, UnwindError> {
+        LocalTarget.tls_base()
+    }
+
+    fn thread_register(&self, register: gimli::Register) -> Result<usize, UnwindError> {
+        LocalTarget.thread_register(register)
+    }
+
+    fn stack_bounds(&self) -> Result<core::ops::Range<usize>, UnwindError> {
+        LocalTarget.stack_bounds()
+    }
+}
+
+#[cfg(all(test, feature = "std", target_os = "linux"))]
+mod test {
+    use super::{LocalTarget, Target};
+    use std::cell::Cell;
+
+    thread_local! {
+        /// The reads of the current thread that went through the kernel.
+        pub(super) static CHECKED_READS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    #[test]
+    fn it_reads_the_stack_directly() {
+        let word = std::hint::black_box(0x1234usize);
+        let address = &word as *const usize as usize;
+        let bounds = LocalTarget.stack_bounds().unwrap();
+        assert!(bounds.contains(&address));
+        let before = CHECKED_READS.with(Cell::get);
+        assert_eq!(LocalTarget.read_usize(address).unwrap(), 0x1234);
+        assert_eq!(CHECKED_READS.with(Cell::get), before);
+
+        // Memory off the stack is still read through the kernel.
+        let heap = Box::new(0x5678usize);
+        assert_eq!(
+            LocalTarget
+                .read_usize(&*heap as *const usize as usize)
+                .unwrap(),
+            0x5678
+        );
+        assert_eq!(CHECKED_READS.with(Cell::get), before + 1);
+        // A read running past the top of the stack is checked as well.
+        let mut buf = [0; 16];
+        let _ = LocalTarget.read_memory(bounds.end - 8, &mut buf);
+        assert_eq!(CHECKED_READS.with(Cell::get), before + 2);
+    }
+
+    #[test]
+    fn it_looks_the_pid_up_once() {
+        assert_eq!(crate::cffi::pid(), std::process::id() as libc::pid_t);
+        assert_eq!(crate::cffi::pid(), crate::cffi::pid());
+        // A forked child does not keep the pid of its parent.
+        match unsafe { libc::fork() } {
+            0 => unsafe { libc::_exit((crate::cffi::pid() == libc::getpid()) as i32) },
+            child => {
+                let mut status = 0;
+                assert_eq!(unsafe { libc::waitpid(child, &mut status, 0) }, child);
+                assert_eq!(libc::WEXITSTATUS(status), 1);
+            }
+        }
+    }
+}
@@ -6,24 +6,34 @@ pub type SectionMapper = &'static [(
     fn(gimli::BaseAddresses, u64) -> gimli::BaseAddresses,
 )];
 
-const BASE_SEC_MAPPERS: SectionMapper = &[
-    (".text", BaseAddresses::set_text),
+// The unwind tables come first, as they may be attached from another file. The vDSO, among
+// others, has no `.got`. It only serves `datarel` pointers, as `.text` only serves `textrel`
+// ones, so objects keeping their code elsewhere are unwound all the same.
+const SEC_MAPPERS: SectionMapper = &[
     (".eh_frame", BaseAddresses::set_eh_frame),
+    (".eh_frame_hdr", BaseAddresses::set_eh_frame_hdr),
+    (".text", BaseAddresses::set_text),
     (".got", BaseAddresses::set_got),
 ];
-const EXTRA_SEC_MAPPERS: SectionMapper = &[(".eh_frame_hdr", BaseAddresses::set_eh_frame_hdr)];
 
-pub fn load(f: &File) -> Option<gimli::BaseAddresses> {
-    let ba = BASE_SEC_MAPPERS.iter().fold(
-        Some(gimli::BaseAddresses::default()),
-        |acc, (name, setter)| {
-            acc.and_then(|a| f.section_by_name(name).map(|s| setter(a, s.address())))
-        },
-    );
-    EXTRA_SEC_MAPPERS.iter().fold(ba, |acc, (name, setter)| {
-        if let Some(sec) = f.section_by_name(name) {
-            return acc.map(|a| setter(a, sec.address()));
-        }
-        acc
-    })
+/// The base addresses of the sections of `f`. Sections it lacks, even `.eh_frame`, are left
+/// unset.
+pub fn load(f: &File) -> gimli::BaseAddresses {
+    set_sections(gimli::BaseAddresses::default(), f, SEC_MAPPERS)
+}
+
+/// Set the addresses of the unwind tables of `f` in `ba`, for a file holding the unwind tables
+/// of another image.
+#[cfg(feature = "std")]
+pub fn set_unwind_tables(ba: gimli::BaseAddresses, f: &File) -> gimli::BaseAddresses {
+    set_sections(ba, f, &SEC_MAPPERS[..2])
+}
+
+fn set_sections(ba: gimli::BaseAddresses, f: &File, mappers: SectionMapper) -> BaseAddresses {
+    mappers
+        .iter()
+        .fold(ba, |acc, (name, setter)| match f.section_by_name(name) {
+            Some(sec) => setter(acc, sec.address()),
+            None => acc,
+        })
 }
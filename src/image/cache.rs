@@ -0,0 +1,114 @@
+//! Function indexes of images saved across runs, keyed by build ID.
+//!
+//! The file is line based: a header, then for every image an `image <build id>` line followed
+//! by one `<start> <size> <name>` line per function, with hexadecimal SVMAs. Names come last,
+//! so they may contain spaces. Reading and writing the file takes the `std` feature; indexes
+//! built otherwise are looked up the same way.
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::io::{BufRead, Error, ErrorKind, Write};
+
+#[cfg(feature = "std")]
+const HEADER: &str = "unwinder symbol cache v1";
+
+/// The functions of an image as `(name, start, size)`, sorted by start.
+pub type FunctionIndex = Vec<(String, u64, u64)>;
+
+#[cfg(feature = "std")]
+pub fn write_header<W: Write>(w: &mut W) -> std::io::Result<()> {
+    writeln!(w, "{}", HEADER)
+}
+
+#[cfg(feature = "std")]
+pub fn write_image<'s, W, I>(w: &mut W, build_id: &[u8], functions: I) -> std::io::Result<()>
+where
+    W: Write,
+    I: Iterator<Item = (&'s str, u64, u64)>,
+{
+    writeln!(w, "image {}", hex(build_id))?;
+    for (name, start, size) in functions {
+        writeln!(w, "{:x} {:x} {}", start, size, name)?;
+    }
+    Ok(())
+}
+
+/// Read the function indexes of a cache file by build ID.
+#[cfg(feature = "std")]
+pub fn read<R: BufRead>(r: R) -> std::io::Result<HashMap<Vec<u8>, FunctionIndex>> {
+    let malformed = || Error::new(ErrorKind::InvalidData, "malformed symbol cache");
+    let mut lines = r.lines();
+    if lines.next().transpose()?.as_deref() != Some(HEADER) {
+        return Err(malformed());
+    }
+    let mut indexes: Vec<(Vec<u8>, FunctionIndex)> = Vec::new();
+    for line in lines {
+        let line = line?;
+        if let Some(id) = line.strip_prefix("image ") {
+            indexes.push((unhex(id).ok_or_else(malformed)?, Vec::new()));
+            continue;
+        }
+        let mut fields = line.splitn(3, ' ');
+        let mut number = || u64::from_str_radix(fields.next()?, 16).ok();
+        let (start, size) = number().zip(number()).ok_or_else(malformed)?;
+        // A function must not run past the end of the address space.
+        start.checked_add(size).ok_or_else(malformed)?;
+        let name = fields.next().ok_or_else(malformed)?;
+        let (_, index) = indexes.last_mut().ok_or_else(malformed)?;
+        index.push((name.to_string(), start, size));
+    }
+    for (_, index) in indexes.iter_mut() {
+        index.sort_by_key(|x| x.1);
+    }
+    Ok(indexes.into_iter().collect())
+}
+
+/// Find the function covering `svma` in a sorted index. A function running past the end of
+/// the address space covers nothing.
+pub fn lookup(index: &FunctionIndex, svma: u64) -> Option<(&str, Range<u64>)> {
+    let i = index.partition_point(|x| x.1 <= svma).checked_sub(1)?;
+    let (name, start, size) = &index[i];
+    let end = start.checked_add(*size)?;
+    (svma < end).then_some((name.as_str(), *start..end))
+}
+
+#[cfg(feature = "std")]
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(feature = "std")]
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    let digits = s.as_bytes().chunks_exact(2);
+    (!s.is_empty() && digits.remainder().is_empty())
+        .then(|| {
+            digits
+                .map(|x| u8::from_str_radix(std::str::from_utf8(x).ok()?, 16).ok())
+                .collect()
+        })
+        .flatten()
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::{lookup, read, HEADER};
+    use std::io::ErrorKind;
+
+    #[test]
+    fn it_rejects_functions_past_address_space() {
+        let cache = format!("{}\nimage 01\n10 4 f\nffffffffffffffff 2 g\n", HEADER);
+        let error = read(cache.as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        let cache = format!("{}\nimage 01\n10 4 f\n", HEADER);
+        let indexes = read(cache.as_bytes()).unwrap();
+        assert_eq!(lookup(&indexes[&vec![1]], 0x12), Some(("f", 0x10..0x14)));
+
+        // Indexes built without reading a file are looked up without overflowing.
+        let index = vec![("g".to_string(), u64::MAX, 2)];
+        assert_eq!(lookup(&index, u64::MAX), None);
+    }
+}
@@ -1,22 +1,65 @@
-use crate::image::raw_image;
+use crate::image::raw_image::{self, SectionData};
+use crate::image::ImageReader;
+use alloc::vec::Vec;
 use gimli::Dwarf;
-use object::{File, Object, ObjectSection};
-use std::mem::ManuallyDrop;
-use std::path::Path;
+#[cfg(feature = "line-info")]
+use gimli::{Endianity, RunTimeEndian, Section, SectionId};
+use object::{File, Object};
+#[cfg(feature = "std")]
+use {
+    crate::image::raw_image::Mmap,
+    object::ObjectSection,
+    std::mem::ManuallyDrop,
+    std::path::{Path, PathBuf},
+};
 
-pub type RawDebugInfo = Dwarf<Vec<u8>>;
+/// The DWARF sections of an image, borrowed from the mapped file they were found in unless
+/// compressed. The mappings of other files, e.g. a separate debug file, are pushed to the
+/// `mappings` of the loader, which must outlive the sections.
+pub type RawDebugInfo = Dwarf<SectionData>;
 
-pub fn load<T: AsRef<Path>>(p: T, f: &File) -> RawDebugInfo {
+/// Load the debug info of the image `f` at `p`, from the image itself or a separate debug file.
+/// Debug files found for the image but built from another binary, as told by their build ID or
+/// UUID, are skipped and pushed to `mismatched`.
+#[cfg(feature = "std")]
+pub fn load<T: AsRef<Path>>(
+    p: T,
+    f: &File<'static>,
+    mappings: &mut Vec<Mmap>,
+    mismatched: &mut Vec<PathBuf>,
+) -> RawDebugInfo {
     if f.has_debug_symbols() {
-        return load_dwarf(f);
+        let mut info = load_dwarf(f);
+        if let Some(sup) = sup_path(p.as_ref(), f, mismatched) {
+            load_sup(&mut info, sup, mappings);
+        }
+        return info;
     }
 
+    if let Some(path) = separate_path(p.as_ref(), f, mismatched) {
+        if let Ok(raw) = raw_image::load(&path) {
+            let (obj, m) = raw_image::keep_mapped(raw);
+            let mut info = load_dwarf(&obj);
+            if let Some(sup) = sup_path(&path, &obj, mismatched) {
+                load_sup(&mut info, sup, mappings);
+            }
+            mappings.push(m);
+            return info;
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
     if let Ok(Some(uuid)) = f.mach_uuid() {
-        if let Ok(f) = locate_dwarf::locate_dsym(p, uuid) {
-            if let Ok((obj, m, f)) = raw_image::load(f) {
+        if let Ok(path) = locate_dwarf::locate_dsym(p, uuid) {
+            if let Ok(raw) = raw_image::load_matching(&path, Some(uuid)) {
+                let (obj, m) = raw_image::keep_mapped(raw);
+                // Only the slices of universal binaries are matched while loading.
+                if obj.mach_uuid().ok().flatten() != Some(uuid) {
+                    mismatched.push(path);
+                    return Default::default();
+                }
                 let info = load_dwarf(&obj);
-                ManuallyDrop::into_inner(m);
-                ManuallyDrop::into_inner(f);
+                mappings.push(m);
                 return info;
             }
         }
@@ -25,13 +68,518 @@ pub fn load<T: AsRef<Path>>(p: T, f: &File) -> RawDebugInfo {
     Default::default()
 }
 
-fn load_dwarf(f: &File) -> RawDebugInfo {
-    Dwarf::load(|id| -> Result<Vec<u8>, gimli::Error> {
-        Ok(f.section_by_name(id.name())
-            .and_then(|x| x.uncompressed_data().ok())
-            .map(|x| x.to_vec())
+/// Load the debug info of the image `f` from the image itself. Without the `std` feature there
+/// are no separate debug files to look for.
+#[cfg(not(feature = "std"))]
+pub fn load(f: &File<'static>) -> RawDebugInfo {
+    load_dwarf(f)
+}
+
+/// The identity of an object file: its GNU build ID, or its UUID in Mach-O.
+pub fn identity(f: &File) -> Option<Vec<u8>> {
+    f.build_id()
+        .ok()
+        .flatten()
+        .map(<[u8]>::to_vec)
+        .or_else(|| f.mach_uuid().ok().flatten().map(|x| x.to_vec()))
+        .filter(|x| !x.is_empty())
+}
+
+/// Borrow the owned sections of a debug info as readers. The readers are only valid as long as
+/// `dbg_info` is alive and not modified.
+pub fn borrow<'a>(dbg_info: &RawDebugInfo, endian: gimli::RunTimeEndian) -> Dwarf<ImageReader<'a>> {
+    dbg_info.borrow(|data| unsafe {
+        gimli::EndianSlice::new(
+            core::slice::from_raw_parts(data.as_ptr(), data.len()),
+            endian,
+        )
+    })
+}
+
+/// Replacements for the malformed sections of a debug info, found by [`repair`].
+#[cfg(feature = "line-info")]
+pub struct Repair {
+    debug_info: Option<Vec<u8>>,
+    debug_line: Option<Vec<u8>>,
+    /// The sections found malformed.
+    pub invalid: Vec<SectionId>,
+}
+
+#[cfg(feature = "line-info")]
+impl Repair {
+    /// Borrow the sections of `info` as readers like [`borrow`], with the malformed ones
+    /// replaced. The readers are only valid as long as both `info` and the repair are alive
+    /// and not modified.
+    pub fn apply<'a>(&self, info: &RawDebugInfo, endian: RunTimeEndian) -> Dwarf<ImageReader<'a>> {
+        let reader = |data: &[u8]| unsafe {
+            gimli::EndianSlice::new(
+                core::slice::from_raw_parts(data.as_ptr(), data.len()),
+                endian,
+            )
+        };
+        let mut dwarf = borrow(info, endian);
+        if let Some(data) = &self.debug_info {
+            dwarf.debug_info = reader(data).into();
+        }
+        if let Some(data) = &self.debug_line {
+            dwarf.debug_line = reader(data).into();
+        }
+        if self.invalid.contains(&SectionId::DebugAranges) {
+            dwarf.debug_aranges = reader(&[]).into();
+        }
+        dwarf
+    }
+}
+
+/// Find how to make malformed debug info, e.g. a truncated section, usable as far as it
+/// parses. Units are read one after the other, so `.debug_info` is cut after the last unit
+/// with a valid header. Line programs that cannot be parsed are replaced by empty ones, so
+/// their units still name functions, and malformed `.debug_aranges` are dropped in favor of
+/// the unit ranges. Only the malformed sections are copied.
+#[cfg(feature = "line-info")]
+pub fn repair(info: &RawDebugInfo, endian: RunTimeEndian) -> Repair {
+    let dwarf = borrow(info, endian);
+    let mut units_end = 0;
+    let mut broken_programs = Vec::new();
+    let mut headers = dwarf.units();
+    let units_valid = loop {
+        match headers.next() {
+            Ok(Some(header)) => {
+                if let Some(offset) = header.offset().as_debug_info_offset() {
+                    units_end = offset.0 + header.length_including_self();
+                }
+                let Some(offset) = line_program_offset(&dwarf, &header) else {
+                    continue;
+                };
+                let program = dwarf
+                    .debug_line
+                    .program(offset, header.address_size(), None, None);
+                if program.is_err() {
+                    broken_programs.push(offset.0);
+                }
+            }
+            Ok(None) => break true,
+            Err(_) => break false,
+        }
+    };
+
+    let mut invalid = Vec::new();
+    let debug_info = (!units_valid).then(|| {
+        invalid.push(SectionId::DebugInfo);
+        dwarf.debug_info.reader().slice()[..units_end].to_vec()
+    });
+    let debug_line = (!broken_programs.is_empty()).then(|| {
+        invalid.push(SectionId::DebugLine);
+        let empty = empty_line_program(endian);
+        let mut data = dwarf.debug_line.reader().slice().to_vec();
+        for offset in broken_programs {
+            data.resize(data.len().max(offset + empty.len()), 0);
+            data[offset..offset + empty.len()].copy_from_slice(&empty);
+        }
+        data
+    });
+    if !aranges_parse(&dwarf) {
+        invalid.push(SectionId::DebugAranges);
+    }
+    Repair {
+        debug_info,
+        debug_line,
+        invalid,
+    }
+}
+
+/// The offset of the line program of a unit, from the `DW_AT_stmt_list` of its root entry.
+#[cfg(feature = "line-info")]
+fn line_program_offset<'a>(
+    dwarf: &Dwarf<ImageReader<'a>>,
+    header: &gimli::UnitHeader<ImageReader<'a>>,
+) -> Option<gimli::DebugLineOffset> {
+    let abbreviations = dwarf.abbreviations(header).ok()?;
+    let mut entries = header.entries(&abbreviations);
+    let (_, root) = entries.next_dfs().ok()??;
+    match root.attr_value(gimli::DW_AT_stmt_list).ok()? {
+        Some(gimli::AttributeValue::DebugLineRef(offset)) => Some(offset),
+        _ => None,
+    }
+}
+
+/// A version 2 line program without files or rows.
+#[cfg(feature = "line-info")]
+fn empty_line_program(endian: RunTimeEndian) -> [u8; 17] {
+    let mut program = [0; 17];
+    endian.write_u32(&mut program[0..4], 13);
+    endian.write_u16(&mut program[4..6], 2);
+    endian.write_u32(&mut program[6..10], 7);
+    // The minimum instruction length, `default_is_stmt`, the line base, the line range and the
+    // opcode base, followed by empty directory and file tables.
+    program[10..15].copy_from_slice(&[1, 1, -5i8 as u8, 14, 1]);
+    program
+}
+
+/// Whether all address range tables can be read.
+#[cfg(feature = "line-info")]
+fn aranges_parse(dwarf: &Dwarf<ImageReader<'_>>) -> bool {
+    let mut headers = dwarf.debug_aranges.headers();
+    loop {
+        match headers.next() {
+            Ok(Some(header)) => {
+                let mut entries = header.entries();
+                while let Some(entry) = entries.next().transpose() {
+                    if entry.is_err() {
+                        return false;
+                    }
+                }
+            }
+            Ok(None) => return true,
+            Err(_) => return false,
+        }
+    }
+}
+
+/// Find the unit whose address ranges cover `svma`. Units differ in version, address size and
+/// base types, so expressions must be evaluated in the unit of the code they describe.
+pub fn unit_at<'a>(
+    dwarf: &Dwarf<ImageReader<'a>>,
+    svma: u64,
+) -> Option<gimli::Unit<ImageReader<'a>>> {
+    let mut headers = dwarf.units();
+    while let Ok(Some(header)) = headers.next() {
+        let Ok(unit) = dwarf.unit(header) else {
+            continue;
+        };
+        let Ok(mut ranges) = dwarf.unit_ranges(&unit) else {
+            continue;
+        };
+        while let Ok(Some(range)) = ranges.next() {
+            if range.begin <= svma && svma < range.end {
+                return Some(unit);
+            }
+        }
+    }
+    None
+}
+
+/// The subprograms with code in `dwarf` as `(name, start, size)`, preferring linkage names.
+/// A function split into several ranges is listed once per range.
+pub fn subprograms<'a>(dwarf: &Dwarf<ImageReader<'a>>) -> Vec<(&'a str, u64, u64)> {
+    let mut functions = Vec::new();
+    let mut headers = dwarf.units();
+    while let Ok(Some(header)) = headers.next() {
+        let Ok(unit) = dwarf.unit(header) else {
+            continue;
+        };
+        let mut entries = unit.entries();
+        while let Ok(Some((_, entry))) = entries.next_dfs() {
+            if entry.tag() != gimli::DW_TAG_subprogram {
+                continue;
+            }
+            let Some(name) = subprogram_name(dwarf, &unit, entry, 2) else {
+                continue;
+            };
+            if let Ok(mut ranges) = dwarf.die_ranges(&unit, entry) {
+                while let Ok(Some(range)) = ranges.next() {
+                    functions.push((name, range.begin, range.end - range.begin));
+                }
+            }
+        }
+    }
+    functions
+}
+
+/// The variables of the scopes covering an address, i.e. the function and the inlined
+/// subroutines and lexical blocks within it.
+pub struct Scope<'a> {
+    /// `DW_AT_frame_base` of the function, which `DW_OP_fbreg` is relative to.
+    pub frame_base: Option<gimli::Expression<ImageReader<'a>>>,
+    pub variables: Vec<ScopedVariable<'a>>,
+}
+
+/// A `DW_TAG_variable` or `DW_TAG_formal_parameter` in scope.
+pub struct ScopedVariable<'a> {
+    pub name: Option<&'a str>,
+    pub parameter: bool,
+    /// The location expression covering the address, if the variable has one there.
+    pub location: Option<gimli::Expression<ImageReader<'a>>>,
+}
+
+/// Collect the variables in scope at `svma` in `unit`, outermost scope first.
+pub fn scope_at<'a>(
+    dwarf: &Dwarf<ImageReader<'a>>,
+    unit: &gimli::Unit<ImageReader<'a>>,
+    svma: u64,
+) -> Scope<'a> {
+    let mut scope = Scope {
+        frame_base: None,
+        variables: Vec::new(),
+    };
+    if let Ok(mut tree) = unit.entries_tree(None) {
+        if let Ok(root) = tree.root() {
+            collect_scope(dwarf, unit, root, svma, false, &mut scope);
+        }
+    }
+    scope
+}
+
+fn collect_scope<'a>(
+    dwarf: &Dwarf<ImageReader<'a>>,
+    unit: &gimli::Unit<ImageReader<'a>>,
+    node: gimli::EntriesTreeNode<ImageReader<'a>>,
+    svma: u64,
+    in_scope: bool,
+    scope: &mut Scope<'a>,
+) {
+    let mut children = node.children();
+    while let Ok(Some(child)) = children.next() {
+        let entry = child.entry();
+        let covering = match entry.tag() {
+            gimli::DW_TAG_subprogram
+            | gimli::DW_TAG_inlined_subroutine
+            | gimli::DW_TAG_lexical_block => {
+                if !covers(dwarf, unit, entry, svma) {
+                    continue;
+                }
+                if entry.tag() == gimli::DW_TAG_subprogram {
+                    scope.frame_base = match entry.attr_value(gimli::DW_AT_frame_base) {
+                        Ok(Some(gimli::AttributeValue::Exprloc(expr))) => Some(expr),
+                        _ => None,
+                    };
+                }
+                true
+            }
+            tag @ (gimli::DW_TAG_variable | gimli::DW_TAG_formal_parameter) => {
+                // Variables outside of any function are global rather than in scope.
+                if in_scope {
+                    scope.variables.push(ScopedVariable {
+                        name: variable_name(dwarf, unit, entry, 2),
+                        parameter: tag == gimli::DW_TAG_formal_parameter,
+                        location: location_at(dwarf, unit, entry, svma),
+                    });
+                }
+                continue;
+            }
+            // Functions may be nested in namespaces and types.
+            _ => false,
+        };
+        collect_scope(dwarf, unit, child, svma, covering, scope);
+    }
+}
+
+/// Whether the code of `entry` covers `svma`.
+fn covers<'a>(
+    dwarf: &Dwarf<ImageReader<'a>>,
+    unit: &gimli::Unit<ImageReader<'a>>,
+    entry: &gimli::DebuggingInformationEntry<ImageReader<'a>>,
+    svma: u64,
+) -> bool {
+    let Ok(mut ranges) = dwarf.die_ranges(unit, entry) else {
+        return false;
+    };
+    while let Ok(Some(range)) = ranges.next() {
+        if range.begin <= svma && svma < range.end {
+            return true;
+        }
+    }
+    false
+}
+
+/// The name of a variable, looked up through its abstract origin at most `depth` times, e.g.
+/// for a parameter of an inlined subroutine.
+fn variable_name<'a>(
+    dwarf: &Dwarf<ImageReader<'a>>,
+    unit: &gimli::Unit<ImageReader<'a>>,
+    entry: &gimli::DebuggingInformationEntry<ImageReader<'a>>,
+    depth: usize,
+) -> Option<&'a str> {
+    if let Ok(Some(value)) = entry.attr_value(gimli::DW_AT_name) {
+        return dwarf.attr_string(unit, value).ok()?.to_string().ok();
+    }
+    match entry.attr_value(gimli::DW_AT_abstract_origin) {
+        Ok(Some(gimli::AttributeValue::UnitRef(offset))) => {
+            let origin = unit.entry(offset).ok()?;
+            variable_name(dwarf, unit, &origin, depth.checked_sub(1)?)
+        }
+        _ => None,
+    }
+}
+
+/// The location expression of a variable at `svma`, from a single expression or the entry of
+/// its location list covering the address.
+fn location_at<'a>(
+    dwarf: &Dwarf<ImageReader<'a>>,
+    unit: &gimli::Unit<ImageReader<'a>>,
+    entry: &gimli::DebuggingInformationEntry<ImageReader<'a>>,
+    svma: u64,
+) -> Option<gimli::Expression<ImageReader<'a>>> {
+    match entry.attr_value(gimli::DW_AT_location).ok()?? {
+        gimli::AttributeValue::Exprloc(expr) => Some(expr),
+        value => {
+            let mut locations = dwarf.attr_locations(unit, value).ok()??;
+            while let Ok(Some(location)) = locations.next() {
+                if location.range.begin <= svma && svma < location.range.end {
+                    return Some(location.data);
+                }
+            }
+            None
+        }
+    }
+}
+
+/// The name of a subprogram, looked up through its abstract origin or specification at most
+/// `depth` times.
+fn subprogram_name<'a>(
+    dwarf: &Dwarf<ImageReader<'a>>,
+    unit: &gimli::Unit<ImageReader<'a>>,
+    entry: &gimli::DebuggingInformationEntry<ImageReader<'a>>,
+    depth: usize,
+) -> Option<&'a str> {
+    for name in [
+        gimli::DW_AT_linkage_name,
+        gimli::DW_AT_MIPS_linkage_name,
+        gimli::DW_AT_name,
+    ] {
+        if let Ok(Some(value)) = entry.attr_value(name) {
+            if let Ok(name) = dwarf.attr_string(unit, value) {
+                return name.to_string().ok();
+            }
+        }
+    }
+    for origin in [gimli::DW_AT_abstract_origin, gimli::DW_AT_specification] {
+        if let Ok(Some(gimli::AttributeValue::UnitRef(offset))) = entry.attr_value(origin) {
+            let origin = unit.entry(offset).ok()?;
+            return subprogram_name(dwarf, unit, &origin, depth.checked_sub(1)?);
+        }
+    }
+    None
+}
+
+/// Load the sections of a split DWARF object (`.dwo`), borrowing from its mapping.
+#[cfg(feature = "line-info")]
+pub fn load_dwo(f: &File<'static>) -> RawDebugInfo {
+    Dwarf::load(|id| -> Result<SectionData, gimli::Error> {
+        Ok(id
+            .dwo_name()
+            .and_then(|name| raw_image::section_data(f, name))
             .unwrap_or_else(Default::default))
     })
     .ok()
     .unwrap_or_else(Default::default)
 }
+
+fn load_section(f: &File<'static>, id: gimli::SectionId) -> Result<SectionData, gimli::Error> {
+    Ok(raw_image::section_data(f, id.name()).unwrap_or_default())
+}
+
+fn load_dwarf(f: &File<'static>) -> RawDebugInfo {
+    Dwarf::load(|id| load_section(f, id))
+        .ok()
+        .unwrap_or_else(Default::default)
+}
+
+/// Find the separate debug file of a stripped ELF image, first by build ID, then by
+/// `.gnu_debuglink` next to the image, in its `.debug` directory and under `/usr/lib/debug`.
+/// A candidate with a different build ID belongs to another build and is pushed to `mismatched`.
+#[cfg(feature = "std")]
+fn separate_path(p: &Path, f: &File, mismatched: &mut Vec<PathBuf>) -> Option<PathBuf> {
+    let build_id = f.build_id().ok().flatten().filter(|x| !x.is_empty());
+    let mut candidates = Vec::new();
+    if let Some(id) = build_id {
+        candidates.push(build_id_path(id));
+    }
+    if let Ok(Some((name, _))) = f.gnu_debuglink() {
+        let name = path_from_bytes(name);
+        let dir = p.parent().unwrap_or_else(|| Path::new(""));
+        candidates.push(dir.join(name));
+        candidates.push(dir.join(".debug").join(name));
+        if let Ok(relative) = dir.strip_prefix("/") {
+            candidates.push(Path::new("/usr/lib/debug").join(relative).join(name));
+        }
+    }
+    candidates
+        .into_iter()
+        .filter(|x| x != p && x.is_file())
+        .find(|x| has_build_id(x, build_id, mismatched))
+}
+
+/// Whether the object file at `path` can be loaded and has the build ID `expected`, if that is
+/// known. A file with another build ID is pushed to `mismatched`.
+#[cfg(feature = "std")]
+fn has_build_id(path: &Path, expected: Option<&[u8]>, mismatched: &mut Vec<PathBuf>) -> bool {
+    let Ok((obj, m, file)) = raw_image::load(path) else {
+        return false;
+    };
+    let matches = expected.is_none_or(|id| obj.build_id().ok().flatten() == Some(id));
+    ManuallyDrop::into_inner(m);
+    ManuallyDrop::into_inner(file);
+    if !matches {
+        mismatched.push(path.to_path_buf());
+    }
+    matches
+}
+
+/// A path stored in an object file, as raw bytes on Unix and as UTF-8 elsewhere.
+#[cfg(all(feature = "std", unix))]
+fn path_from_bytes(name: &[u8]) -> &Path {
+    Path::new(<std::ffi::OsStr as std::os::unix::ffi::OsStrExt>::from_bytes(name))
+}
+
+#[cfg(all(feature = "std", not(unix)))]
+fn path_from_bytes(name: &[u8]) -> &Path {
+    Path::new(std::str::from_utf8(name).unwrap_or_default())
+}
+
+/// The path of a debug file in the global build ID directory.
+#[cfg(feature = "std")]
+fn build_id_path(id: &[u8]) -> PathBuf {
+    let hex = id.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    Path::new("/usr/lib/debug/.build-id")
+        .join(&hex[..2])
+        .join(format!("{}.debug", &hex[2..]))
+}
+
+/// Find the supplementary object file referenced by `.gnu_debugaltlink` (as produced by DWZ) or
+/// by the DWARF 5 `.debug_sup` section. A relative path is resolved against the directory of
+/// the image, and a file that cannot be found there is looked up by its build ID. Both carry the
+/// build ID of the supplementary file, and a candidate with another one is pushed to
+/// `mismatched`.
+#[cfg(feature = "std")]
+fn sup_path(p: &Path, f: &File, mismatched: &mut Vec<PathBuf>) -> Option<PathBuf> {
+    let (name, build_id) = match f.gnu_debugaltlink() {
+        Ok(Some(link)) => link,
+        _ => debug_sup(f)?,
+    };
+    let build_id = Some(build_id).filter(|x| !x.is_empty());
+    let dir = p.parent().unwrap_or_else(|| Path::new(""));
+    let mut candidates = vec![dir.join(path_from_bytes(name))];
+    candidates.extend(build_id.map(build_id_path));
+    candidates
+        .into_iter()
+        .filter(|x| x != p && x.is_file())
+        .find(|x| has_build_id(x, build_id, mismatched))
+}
+
+/// The file name and checksum in a `.debug_sup` section: a 2-byte version, a 1-byte
+/// `is_supplementary` flag, the null-terminated name, then the ULEB128 length of the checksum
+/// and the checksum itself, which is the build ID of the supplementary file.
+#[cfg(feature = "std")]
+fn debug_sup<'data>(f: &File<'data>) -> Option<(&'data [u8], &'data [u8])> {
+    let data = f.section_by_name(".debug_sup")?.data().ok()?;
+    let rest = match data {
+        [_, _, 0, rest @ ..] => rest,
+        _ => return None,
+    };
+    let end = rest.iter().position(|x| *x == 0)?;
+    let mut checksum = gimli::EndianSlice::new(&rest[end + 1..], gimli::LittleEndian);
+    let len = gimli::leb128::read::unsigned(&mut checksum).ok()?;
+    let checksum = checksum.slice().get(..usize::try_from(len).ok()?)?;
+    Some((&rest[..end], checksum))
+}
+
+/// Load the supplementary file at `path` into `info`.
+#[cfg(feature = "std")]
+fn load_sup(info: &mut RawDebugInfo, path: PathBuf, mappings: &mut Vec<Mmap>) {
+    if let Ok(raw) = raw_image::load(path) {
+        let (obj, m) = raw_image::keep_mapped(raw);
+        let _ = info.load_sup(|id| load_section(&obj, id));
+        mappings.push(m);
+    }
+}
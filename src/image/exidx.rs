@@ -0,0 +1,338 @@
+//! ARM exception handling ABI (EHABI) unwind tables, as found in `.ARM.exidx` and
+//! `.ARM.extab`.
+use crate::UnwindError;
+use gimli::{Endianity, RunTimeEndian};
+
+const EXIDX_CANTUNWIND: u32 = 1;
+const PC: usize = 15;
+const LR: usize = 14;
+const SP: usize = 13;
+
+/// The `.ARM.exidx` index table of an image, together with its `.ARM.extab`.
+pub struct ExIdx {
+    exidx: (Vec<u8>, u64),
+    extab: Option<(Vec<u8>, u64)>,
+    endian: RunTimeEndian,
+}
+
+/// The unwind opcodes describing a single function.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Instructions {
+    pub function_start: u64,
+    pub opcodes: Vec<u8>,
+}
+
+/// Decode a place-relative 31-bit offset stored at `place`.
+fn prel31(word: u32, place: u64) -> u64 {
+    let offset = ((word << 1) as i32 >> 1) as i64;
+    place.wrapping_add(offset as u64)
+}
+
+impl ExIdx {
+    /// Wrap the contents of `.ARM.exidx` and `.ARM.extab` together with their SVMAs.
+    pub fn new(
+        exidx: (Vec<u8>, u64),
+        extab: Option<(Vec<u8>, u64)>,
+        endian: RunTimeEndian,
+    ) -> Self {
+        Self {
+            exidx,
+            extab,
+            endian,
+        }
+    }
+
+    fn word(&self, data: &[u8], offset: usize) -> Option<u32> {
+        data.get(offset..offset + 4)
+            .map(|x| self.endian.read_u32(x))
+    }
+
+    fn entry_count(&self) -> usize {
+        self.exidx.0.len() / 8
+    }
+
+    fn function_start(&self, index: usize) -> Option<u64> {
+        let offset = index * 8;
+        self.word(&self.exidx.0, offset)
+            .map(|x| prel31(x, self.exidx.1 + offset as u64))
+    }
+
+    /// Find the unwind instructions for the function covering `svma`.
+    pub fn lookup(&self, svma: u64) -> Result<Instructions, UnwindError> {
+        // Entries are sorted by function start, so look for the last entry starting at or
+        // before `svma`.
+        let (mut low, mut high) = (0, self.entry_count());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.function_start(mid) {
+                Some(start) if start <= svma => low = mid + 1,
+                _ => high = mid,
+            }
+        }
+        let index = low.checked_sub(1).ok_or(UnwindError::UnwindLogicalError(
+            "no EHABI entry covers the address",
+        ))?;
+        let offset = index * 8;
+        let function_start = self.function_start(index).unwrap_or_default();
+        let data = self
+            .word(&self.exidx.0, offset + 4)
+            .ok_or(UnwindError::UnwindLogicalError(
+                "truncated EHABI index entry",
+            ))?;
+        let opcodes = match data {
+            EXIDX_CANTUNWIND => return Err(UnwindError::UnwindEnded),
+            // Compact model with personality routine #0, opcodes inlined in the index.
+            x if x & 0x8000_0000 != 0 => compact_opcodes(x, &[])?,
+            x => self.table_opcodes(prel31(x, self.exidx.1 + offset as u64 + 4))?,
+        };
+        Ok(Instructions {
+            function_start,
+            opcodes,
+        })
+    }
+
+    fn table_opcodes(&self, svma: u64) -> Result<Vec<u8>, UnwindError> {
+        let (data, address) = self.extab.as_ref().ok_or(UnwindError::UnwindLogicalError(
+            "missing .ARM.extab section",
+        ))?;
+        let start = svma.checked_sub(*address).map(|x| x as usize).ok_or(
+            UnwindError::UnwindLogicalError("EHABI entry outside of .ARM.extab"),
+        )?;
+        let word = |i: usize| {
+            self.word(data, start + i * 4)
+                .ok_or(UnwindError::UnwindLogicalError(
+                    "truncated EHABI table entry",
+                ))
+        };
+        let first = word(0)?;
+        if first & 0x8000_0000 != 0 {
+            let extra = match (first >> 24) & 0xf {
+                0 => 0,
+                1 | 2 => (first >> 16) & 0xff,
+                _ => {
+                    return Err(UnwindError::NotSupported(
+                        "unknown EHABI personality routine",
+                    ))
+                }
+            };
+            let rest = (1..=extra as usize)
+                .map(word)
+                .collect::<Result<Vec<_>, _>>()?;
+            compact_opcodes(first, &rest)
+        } else {
+            // A generic personality routine; GCC's personality routines store the opcodes
+            // right after it, with the number of extra words in the top byte.
+            let header = word(1)?;
+            let extra = header >> 24;
+            let rest = (2..2 + extra as usize)
+                .map(word)
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut opcodes = header.to_be_bytes()[1..].to_vec();
+            opcodes.extend(rest.iter().flat_map(|x| x.to_be_bytes()));
+            Ok(opcodes)
+        }
+    }
+}
+
+/// Extract the opcodes from a compact model entry. Personality #0 carries three opcodes in
+/// the header word, while #1 and #2 carry two, followed by `rest`.
+fn compact_opcodes(header: u32, rest: &[u32]) -> Result<Vec<u8>, UnwindError> {
+    let bytes = header.to_be_bytes();
+    let mut opcodes = match (header >> 24) & 0xf {
+        0 => bytes[1..].to_vec(),
+        1 | 2 => bytes[2..].to_vec(),
+        _ => {
+            return Err(UnwindError::NotSupported(
+                "unknown EHABI personality routine",
+            ))
+        }
+    };
+    opcodes.extend(rest.iter().flat_map(|x| x.to_be_bytes()));
+    Ok(opcodes)
+}
+
+impl Instructions {
+    /// Apply the opcodes to the core registers `regs`, reading saved registers through `read`.
+    /// On success, `regs` describes the caller's frame.
+    pub fn execute<F>(&self, regs: &mut [u32; 16], mut read: F) -> Result<(), UnwindError>
+    where
+        F: FnMut(u32) -> Result<u32, UnwindError>,
+    {
+        let mut vsp = regs[SP];
+        let mut pc_set = false;
+        let mut iter = self.opcodes.iter().copied();
+        let next = |iter: &mut dyn Iterator<Item = u8>| {
+            iter.next()
+                .ok_or(UnwindError::UnwindLogicalError("truncated EHABI opcodes"))
+        };
+        let mut pop = |regs: &mut [u32; 16], vsp: &mut u32, mask: u16| {
+            let mut sp = None;
+            for i in (0..16).filter(|i| mask & (1 << i) != 0) {
+                let value = read(*vsp)?;
+                *vsp = vsp.wrapping_add(4);
+                match i {
+                    SP => sp = Some(value),
+                    _ => regs[i] = value,
+                }
+            }
+            if let Some(sp) = sp {
+                *vsp = sp;
+            }
+            Ok::<_, UnwindError>(())
+        };
+        while let Some(op) = iter.next() {
+            match op {
+                0x00..=0x3f => vsp = vsp.wrapping_add(((op as u32 & 0x3f) << 2) + 4),
+                0x40..=0x7f => vsp = vsp.wrapping_sub(((op as u32 & 0x3f) << 2) + 4),
+                0x80..=0x8f => {
+                    let mask = ((op as u16 & 0xf) << 8) | next(&mut iter)? as u16;
+                    if mask == 0 {
+                        return Err(UnwindError::UnwindEnded);
+                    }
+                    pc_set |= mask & (1 << 11) != 0;
+                    pop(regs, &mut vsp, mask << 4)?;
+                }
+                0x9d | 0x9f => return Err(UnwindError::NotSupported("reserved EHABI opcode")),
+                0x90..=0x9f => vsp = regs[op as usize & 0xf],
+                0xa0..=0xaf => {
+                    let count = (op & 0x7) as u16 + 1;
+                    let mut mask = ((1 << count) - 1) << 4;
+                    if op & 0x8 != 0 {
+                        mask |= 1 << LR;
+                    }
+                    pop(regs, &mut vsp, mask)?;
+                }
+                0xb0 => break,
+                0xb1 => match next(&mut iter)? {
+                    mask @ 0x01..=0x0f => pop(regs, &mut vsp, mask as u16)?,
+                    _ => return Err(UnwindError::NotSupported("spare EHABI opcode")),
+                },
+                0xb2 => {
+                    let (mut value, mut shift) = (0u32, 0);
+                    loop {
+                        let byte = next(&mut iter)?;
+                        value |= ((byte & 0x7f) as u32).wrapping_shl(shift);
+                        shift += 7;
+                        if byte & 0x80 == 0 {
+                            break;
+                        }
+                    }
+                    vsp = vsp.wrapping_add(0x204).wrapping_add(value << 2);
+                }
+                // VFP registers saved by FSTMFDX carry an extra padding word.
+                0xb3 => {
+                    let count = (next(&mut iter)? & 0xf) as u32 + 1;
+                    vsp = vsp.wrapping_add(count * 8 + 4);
+                }
+                0xb8..=0xbf => vsp = vsp.wrapping_add(((op & 0x7) as u32 + 1) * 8 + 4),
+                0xc0..=0xc5 => vsp = vsp.wrapping_add(((op & 0x7) as u32 + 1) * 8),
+                0xc6 | 0xc8 | 0xc9 => {
+                    let count = (next(&mut iter)? & 0xf) as u32 + 1;
+                    vsp = vsp.wrapping_add(count * 8);
+                }
+                0xc7 => match next(&mut iter)? {
+                    mask @ 0x01..=0x0f => vsp = vsp.wrapping_add(mask.count_ones() * 4),
+                    _ => return Err(UnwindError::NotSupported("spare EHABI opcode")),
+                },
+                0xd0..=0xd7 => vsp = vsp.wrapping_add(((op & 0x7) as u32 + 1) * 8),
+                _ => return Err(UnwindError::NotSupported("spare EHABI opcode")),
+            }
+        }
+        if !pc_set {
+            regs[PC] = regs[LR];
+        }
+        regs[SP] = vsp;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExIdx, Instructions};
+    use crate::UnwindError;
+    use gimli::RunTimeEndian;
+
+    fn le_words(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|x| x.to_le_bytes()).collect()
+    }
+
+    fn prel31_to(target: u64, place: u64) -> u32 {
+        (target.wrapping_sub(place) as u32) & 0x7fff_ffff
+    }
+
+    #[test]
+    fn it_looks_up_exidx_entries() {
+        const EXIDX: u64 = 0x2000;
+        const EXTAB: u64 = 0x3000;
+        let exidx = le_words(&[
+            prel31_to(0x1000, EXIDX),
+            0x80a8_b0b0,
+            prel31_to(0x1100, EXIDX + 8),
+            prel31_to(EXTAB, EXIDX + 12),
+            prel31_to(0x1200, EXIDX + 16),
+            1,
+        ]);
+        let extab = le_words(&[0x8101_b108, 0x8480_b0b0]);
+        let table = ExIdx::new((exidx, EXIDX), Some((extab, EXTAB)), RunTimeEndian::Little);
+
+        assert!(table.lookup(0x0fff).is_err());
+        assert_eq!(
+            table.lookup(0x1010).unwrap(),
+            Instructions {
+                function_start: 0x1000,
+                opcodes: vec![0xa8, 0xb0, 0xb0],
+            }
+        );
+        assert_eq!(
+            table.lookup(0x11ff).unwrap(),
+            Instructions {
+                function_start: 0x1100,
+                opcodes: vec![0xb1, 0x08, 0x84, 0x80, 0xb0, 0xb0],
+            }
+        );
+        assert!(matches!(
+            table.lookup(0x1234),
+            Err(UnwindError::UnwindEnded)
+        ));
+    }
+
+    #[test]
+    fn it_executes_ehabi_opcodes() {
+        let stack = [0u32, 10, 11, 4, 14, 0xdead];
+        let read = |addr: u32| {
+            stack
+                .get((addr as usize - 0x100) / 4)
+                .copied()
+                .ok_or(UnwindError::UnwindEnded)
+        };
+
+        // sub sp, #4; pop {r3}; push {r4, lr}
+        let insns = Instructions {
+            function_start: 0,
+            opcodes: vec![0x00, 0xb1, 0x08, 0xa8],
+        };
+        let mut regs = [0u32; 16];
+        regs[13] = 0x100;
+        insns.execute(&mut regs, read).unwrap();
+        assert_eq!((regs[3], regs[4], regs[14]), (10, 11, 4));
+        assert_eq!(regs[15], 4);
+        assert_eq!(regs[13], 0x110);
+
+        // pop {r10, r11, pc} from an explicit frame pointer.
+        let insns = Instructions {
+            function_start: 0,
+            opcodes: vec![0x9b, 0x88, 0xc0],
+        };
+        let mut regs = [0u32; 16];
+        regs[11] = 0x10c;
+        insns.execute(&mut regs, read).unwrap();
+        assert_eq!((regs[10], regs[11], regs[15]), (4, 14, 0xdead));
+        assert_eq!(regs[13], 0x118);
+
+        let refuse = Instructions {
+            function_start: 0,
+            opcodes: vec![0x80, 0x00],
+        };
+        assert!(refuse.execute(&mut [0; 16], read).is_err());
+    }
+}
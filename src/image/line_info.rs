@@ -1,19 +1,364 @@
-use crate::image::debug_info::RawDebugInfo;
 use crate::image::ImageReader;
-use addr2line::Context as LineCtx;
-use gimli::{EndianSlice, RunTimeEndian};
+use gimli::RunTimeEndian;
+#[cfg(feature = "line-info")]
+use {
+    crate::image::debug_info::{self, RawDebugInfo},
+    crate::image::raw_image::{self, Mmap, SectionData},
+    addr2line::{LookupContinuation, LookupResult, SplitDwarfLoad},
+    gimli::{Dwarf, DwarfPackage, EndianSlice},
+    std::cell::{OnceCell, RefCell},
+    std::collections::HashMap,
+    std::ops::Range,
+    std::path::{Path, PathBuf},
+    std::sync::Arc,
+};
 
+#[cfg(feature = "line-info")]
+pub type LineContext<'a> = addr2line::Context<ImageReader<'a>>;
+
+#[cfg(feature = "line-info")]
+pub type FrameIter<'ctx, 'a> = addr2line::FrameIter<'ctx, ImageReader<'a>>;
+
+/// Placeholder for the line context when the `line-info` feature is disabled. It cannot be
+/// constructed, so `Image::line_context` is always `None`.
+#[cfg(not(feature = "line-info"))]
+pub struct LineContext<'a>(
+    core::convert::Infallible,
+    core::marker::PhantomData<ImageReader<'a>>,
+);
+
+/// The line context of an image along with the split DWARF its lookups loaded. Neither is
+/// `Sync`, so the threads resolving in the image take turns.
+pub struct LineCache<'a> {
+    // The line context may borrow from split DWARF buffers and repaired sections, so it is
+    // dropped first.
+    pub context: core::cell::OnceCell<Option<LineContext<'a>>>,
+    /// The replacements for malformed sections of the debug info, found right before the
+    /// context is built.
+    #[cfg(feature = "line-info")]
+    pub repair: OnceCell<debug_info::Repair>,
+    #[cfg(feature = "line-info")]
+    pub split_dwarf: SplitDwarf<'a>,
+    /// The indexes of the units looked up so far, keyed by their DWO ID and offset.
+    #[cfg(feature = "line-info")]
+    units: RefCell<HashMap<UnitKey, UnitIndex>>,
+}
+
+impl<'a> LineCache<'a> {
+    #[cfg(feature = "line-info")]
+    pub fn new<P: AsRef<Path>>(image_path: P, endian: RunTimeEndian) -> Self {
+        Self {
+            context: Default::default(),
+            repair: OnceCell::new(),
+            split_dwarf: SplitDwarf::new(image_path, endian),
+            units: Default::default(),
+        }
+    }
+
+    /// Without line info, nothing is looked up next to the image, which need not even have a
+    /// path without the `std` feature.
+    #[cfg(not(feature = "line-info"))]
+    pub fn new<P>(_image_path: P, _endian: RunTimeEndian) -> Self {
+        Self {
+            context: Default::default(),
+        }
+    }
+
+    /// Run `f` on the index of the unit covering `svma`, built on the first lookup in the unit.
+    #[cfg(feature = "line-info")]
+    fn with_unit_index<R>(
+        &self,
+        ctx: &LineContext<'a>,
+        svma: u64,
+        f: impl FnOnce(&UnitIndex) -> R,
+    ) -> Option<R> {
+        let (dwarf, unit) = resolve(ctx.find_dwarf_and_unit(svma), &self.split_dwarf)?;
+        let mut units = self.units.borrow_mut();
+        let index = units
+            .entry((unit.dwo_id, unit.header.offset()))
+            .or_insert_with(|| UnitIndex::new(dwarf, unit));
+        Some(f(index))
+    }
+}
+
+/// Split units share offsets with each other and the units of the image, so they are told
+/// apart by their DWO ID.
+#[cfg(feature = "line-info")]
+type UnitKey = (Option<gimli::DwoId>, gimli::UnitSectionOffset);
+
+/// The subprograms and line table rows of a unit, sorted by address so that lookups need not
+/// walk the DIEs or the line program.
+#[cfg(feature = "line-info")]
+struct UnitIndex {
+    /// The ranges of the subprograms, sorted by start, with the index of their subprogram in
+    /// `functions` and the largest end of the ranges up to them.
+    ranges: Vec<(Range<u64>, usize, u64)>,
+    functions: Vec<Vec<Range<u64>>>,
+    /// The address ranges of the rows with a nonzero discriminator, sorted by start.
+    discriminators: Vec<(Range<u64>, u64)>,
+}
+
+#[cfg(feature = "line-info")]
+impl UnitIndex {
+    fn new<'a>(dwarf: &Dwarf<ImageReader<'a>>, unit: &gimli::Unit<ImageReader<'a>>) -> Self {
+        let mut functions = Vec::new();
+        let mut ranges = Vec::new();
+        let mut entries = unit.entries();
+        while let Ok(Some((_, entry))) = entries.next_dfs() {
+            if entry.tag() != gimli::DW_TAG_subprogram {
+                continue;
+            }
+            let mut function = Vec::new();
+            if let Ok(mut iter) = dwarf.die_ranges(unit, entry) {
+                while let Ok(Some(range)) = iter.next() {
+                    function.push(range.begin..range.end);
+                }
+            }
+            ranges.extend(function.iter().map(|x| (x.clone(), functions.len(), 0)));
+            functions.push(function);
+        }
+        ranges.sort_by_key(|x| x.0.start);
+        let mut end = 0;
+        for (range, _, max_end) in &mut ranges {
+            end = end.max(range.end);
+            *max_end = end;
+        }
+
+        let mut discriminators = Vec::new();
+        if let Some(program) = unit.line_program.clone() {
+            let mut rows = program.rows();
+            let mut previous: Option<(u64, u64)> = None;
+            while let Ok(Some((_, row))) = rows.next_row() {
+                if let Some((address, discriminator)) = previous {
+                    if discriminator != 0 && address < row.address() {
+                        discriminators.push((address..row.address(), discriminator));
+                    }
+                }
+                previous = (!row.end_sequence()).then(|| (row.address(), row.discriminator()));
+            }
+        }
+        discriminators.sort_by_key(|x| x.0.start);
+
+        Self {
+            ranges,
+            functions,
+            discriminators,
+        }
+    }
+
+    /// The ranges of the innermost subprogram covering `svma`.
+    fn function_ranges(&self, svma: u64) -> Option<&[Range<u64>]> {
+        let end = self.ranges.partition_point(|x| x.0.start <= svma);
+        self.ranges[..end]
+            .iter()
+            .rev()
+            .take_while(|(_, _, max_end)| svma < *max_end)
+            .find(|(range, _, _)| range.contains(&svma))
+            .map(|(_, function, _)| &self.functions[*function][..])
+    }
+
+    fn discriminator(&self, svma: u64) -> Option<u64> {
+        let end = self.discriminators.partition_point(|x| x.0.start <= svma);
+        let (range, discriminator) = self.discriminators.get(end.checked_sub(1)?)?;
+        range.contains(&svma).then_some(*discriminator)
+    }
+}
+
+/// Build the line context of `dbg_info` with its malformed sections replaced as in `repair`.
+#[cfg(feature = "line-info")]
 pub fn load<'a>(
     dbg_info: &RawDebugInfo,
+    repair: &debug_info::Repair,
+    endian: RunTimeEndian,
+) -> Option<LineContext<'a>> {
+    LineContext::from_dwarf(repair.apply(dbg_info, endian)).ok()
+}
+
+/// A DWARF package together with the mapping and the inflated sections it borrows from.
+#[cfg(feature = "line-info")]
+type OwnedPackage<'a> = (Mmap, Vec<SectionData>, DwarfPackage<ImageReader<'a>>);
+
+/// Split DWARF objects referenced by the skeleton units of an image. Both standalone `.dwo`
+/// files and a `.dwp` package next to the image are supported; they are loaded on demand and
+/// kept alive as long as the image.
+#[cfg(feature = "line-info")]
+pub struct SplitDwarf<'a> {
+    image_path: PathBuf,
     endian: RunTimeEndian,
-) -> Option<LineCtx<ImageReader<'a>>> {
-    LineCtx::from_dwarf({
-        dbg_info.borrow(|data| unsafe {
-            EndianSlice::new(
-                std::slice::from_raw_parts(data.as_ptr(), data.len()),
-                endian,
-            )
+    package: OnceCell<Option<OwnedPackage<'a>>>,
+    loaded: RefCell<Vec<(Mmap, RawDebugInfo)>>,
+}
+
+#[cfg(feature = "line-info")]
+impl<'a> SplitDwarf<'a> {
+    pub fn new<P: AsRef<Path>>(image_path: P, endian: RunTimeEndian) -> Self {
+        Self {
+            image_path: image_path.as_ref().to_path_buf(),
+            endian,
+            package: OnceCell::new(),
+            loaded: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn package(&self) -> Option<&DwarfPackage<ImageReader<'a>>> {
+        self.package
+            .get_or_init(|| {
+                let mut path = self.image_path.clone().into_os_string();
+                path.push(".dwp");
+                let (obj, m) = raw_image::keep_mapped(raw_image::load(path).ok()?);
+                let mut buffers = Vec::new();
+                let endian = self.endian;
+                let package = DwarfPackage::load(
+                    |id| -> Result<ImageReader<'a>, gimli::Error> {
+                        let data = id
+                            .dwo_name()
+                            .and_then(|name| raw_image::section_data(&obj, name))
+                            .unwrap_or_default();
+                        let slice: &'a [u8] =
+                            unsafe { std::slice::from_raw_parts(data.as_ptr(), data.len()) };
+                        buffers.push(data);
+                        Ok(EndianSlice::new(slice, endian))
+                    },
+                    EndianSlice::new(&[], endian),
+                )
+                .ok();
+                package.map(|package| (m, buffers, package))
+            })
+            .as_ref()
+            .map(|(_, _, package)| package)
+    }
+
+    fn dwo_path(&self, load: &SplitDwarfLoad<ImageReader<'a>>) -> Option<PathBuf> {
+        let mut path = PathBuf::new();
+        if let Some(comp_dir) = load.comp_dir.as_ref() {
+            path.push(comp_dir.to_string().ok()?);
+        }
+        path.push(load.path.as_ref()?.to_string().ok()?);
+        Some(path)
+    }
+
+    fn load_dwo(&self, load: &SplitDwarfLoad<ImageReader<'a>>) -> Option<Dwarf<ImageReader<'a>>> {
+        let (obj, m) = raw_image::keep_mapped(raw_image::load(self.dwo_path(load)?).ok()?);
+        let dwo = debug_info::load_dwo(&obj);
+        let mut loaded = self.loaded.borrow_mut();
+        loaded.push((m, dwo));
+        // The mapping and the section buffers are owned by `loaded` and their data never moves
+        // or gets dropped before `self`.
+        loaded
+            .last()
+            .map(|(_, dwo)| debug_info::borrow(dwo, self.endian))
+    }
+
+    /// Find the split unit requested by a lookup, preferring the package over `.dwo` files.
+    pub fn load(
+        &self,
+        load: &SplitDwarfLoad<ImageReader<'a>>,
+    ) -> Option<Arc<Dwarf<ImageReader<'a>>>> {
+        let mut dwarf = self
+            .package()
+            .and_then(|package| package.find_cu(load.dwo_id, &load.parent).ok().flatten())
+            .or_else(|| {
+                self.load_dwo(load).map(|mut dwarf| {
+                    dwarf.make_dwo(&load.parent);
+                    dwarf
+                })
+            })?;
+        // Line programs are read from the skeleton unit, but addr2line resolves their strings
+        // against the split sections, which never carry `.debug_line_str`.
+        dwarf.debug_line_str = load.parent.debug_line_str;
+        Some(Arc::new(dwarf))
+    }
+}
+
+/// Find the line table discriminator of the row covering `svma`. The discriminator tells apart
+/// blocks that share a source line; `None` is returned when it is zero or unknown.
+#[cfg(feature = "line-info")]
+pub fn discriminator<'a>(ctx: &LineContext<'a>, cache: &LineCache<'a>, svma: u64) -> Option<u64> {
+    cache
+        .with_unit_index(ctx, svma, |index| index.discriminator(svma))
+        .flatten()
+}
+
+/// Find the address range of the subprogram covering `svma`. Inlined subroutines are not
+/// considered, so this is the physical function containing the address. Of a function split
+/// into several ranges, e.g. a hot and a cold part, only the range containing `svma` is
+/// returned.
+#[cfg(feature = "line-info")]
+pub fn function_range<'a>(
+    ctx: &LineContext<'a>,
+    cache: &LineCache<'a>,
+    svma: u64,
+) -> Option<std::ops::Range<u64>> {
+    function_ranges(ctx, cache, svma)?
+        .into_iter()
+        .find(|x| x.contains(&svma))
+}
+
+/// Find all address ranges of the subprogram covering `svma`, following `DW_AT_ranges`.
+#[cfg(feature = "line-info")]
+pub fn function_ranges<'a>(
+    ctx: &LineContext<'a>,
+    cache: &LineCache<'a>,
+    svma: u64,
+) -> Option<Vec<std::ops::Range<u64>>> {
+    cache
+        .with_unit_index(ctx, svma, |index| {
+            index.function_ranges(svma).map(<[_]>::to_vec)
         })
-    })
-    .ok()
+        .flatten()
+}
+
+/// Find the entry of the function called by the call returning to `return_svma`, from the
+/// `DW_TAG_call_site` of the caller. Only direct calls to functions defined in the same unit
+/// have a known target.
+#[cfg(feature = "line-info")]
+pub fn call_target<'a>(
+    ctx: &LineContext<'a>,
+    split_dwarf: &SplitDwarf<'a>,
+    return_svma: u64,
+) -> Option<u64> {
+    let (dwarf, unit) = resolve(
+        ctx.find_dwarf_and_unit(return_svma.checked_sub(1)?),
+        split_dwarf,
+    )?;
+    let address = |entry: &gimli::DebuggingInformationEntry<_>, name| {
+        dwarf
+            .attr_address(unit, entry.attr_value(name).ok()??)
+            .ok()?
+    };
+    let mut entries = unit.entries();
+    while let Ok(Some((_, entry))) = entries.next_dfs() {
+        // GCC emits the pre-standard tag for DWARF 4, with the return address as its low PC.
+        let (return_pc, origin) = match entry.tag() {
+            gimli::DW_TAG_call_site => (gimli::DW_AT_call_return_pc, gimli::DW_AT_call_origin),
+            gimli::DW_TAG_GNU_call_site => (gimli::DW_AT_low_pc, gimli::DW_AT_abstract_origin),
+            _ => continue,
+        };
+        if address(entry, return_pc) != Some(return_svma) {
+            continue;
+        }
+        return match entry.attr_value(origin).ok()?? {
+            gimli::AttributeValue::UnitRef(offset) => {
+                address(&unit.entry(offset).ok()?, gimli::DW_AT_low_pc)
+            }
+            _ => None,
+        };
+    }
+    None
+}
+
+/// Drive a lookup to completion, loading split DWARF data when the context asks for it.
+#[cfg(feature = "line-info")]
+pub fn resolve<'a, L>(mut result: LookupResult<L>, split_dwarf: &SplitDwarf<'a>) -> L::Output
+where
+    L: LookupContinuation<Buf = ImageReader<'a>>,
+{
+    loop {
+        result = match result {
+            LookupResult::Output(output) => return output,
+            LookupResult::Load { load, continuation } => {
+                continuation.resume(split_dwarf.load(&load))
+            }
+        }
+    }
 }
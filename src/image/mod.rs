@@ -1,15 +1,46 @@
 use crate::image::debug_info::RawDebugInfo;
+#[cfg(feature = "line-info")]
+use crate::image::line_info::LineContext;
+use crate::image::raw_image::{Mmap, SectionData};
 use crate::image::symbol_map::OwnedSymbolMap;
-use addr2line::Context as LineCtx;
-use findshlibs::{SharedLibrary, TargetSharedLibrary};
-use gimli::{EndianSlice, ParsedEhFrameHdr, RunTimeEndian};
-use object::{Object, ObjectSection};
-use std::mem::ManuallyDrop;
+use crate::UnwindError;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+use findshlibs::{Segment, SharedLibrary, TargetSharedLibrary};
+use gimli::{EndianSlice, ParsedEhFrameHdr, RunTimeEndian, UnwindSection};
+use object::{Object, ObjectSection, ObjectSegment, SectionKind};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+#[cfg(feature = "std")]
+use {
+    std::collections::{HashMap, HashSet},
+    std::ffi::OsString,
+    std::mem::ManuallyDrop,
+    std::sync::{Arc, OnceLock},
+    std::time::{Duration, Instant},
+};
+// Without std, the ordered collections stand in for the hashed ones, which need a source of
+// randomness, and images are not shared between threads, so lazily computed parts live in
+// cells.
+#[cfg(not(feature = "std"))]
+use {
+    alloc::collections::{BTreeMap as HashMap, BTreeSet as HashSet},
+    core::cell::OnceCell as OnceLock,
+};
 
 mod base_addresses;
+pub mod cache;
 mod debug_info;
+#[cfg(any(target_arch = "arm", test))]
+pub mod exidx;
 mod line_info;
+#[cfg(feature = "std")]
+mod plt;
 mod raw_image;
+mod serialized;
 mod symbol_map;
 
 pub struct Image<'a> {
@@ -18,83 +49,1847 @@ pub struct Image<'a> {
     pub bias: usize,
     pub start_avma: usize,
     pub length: usize,
+    /// The AVMA ranges of the loaded segments. The range starting at `start_avma` may have
+    /// unmapped holes between them.
+    pub segments: Vec<Range<usize>>,
+    /// The file offset and SVMA range of the file contents of each loadable segment.
+    file_segments: Vec<(u64, Range<u64>)>,
     pub symbol_map: OwnedSymbolMap,
-    pub dbg_info: RawDebugInfo,
-    pub line_context: Option<LineCtx<ImageReader<'a>>>,
-    pub eh_frame_section: (Vec<u8>, gimli::EhFrame<ImageReader<'a>>),
-    pub eh_frame_hdr_section: Option<(Vec<u8>, ParsedEhFrameHdr<ImageReader<'a>>)>,
+    /// The SVMA ranges of the code sections other than `.text`, with their names in angle
+    /// brackets.
+    sections: Vec<(Range<u64>, String)>,
+    /// The SVMA ranges and contents of all code sections, `.text` included.
+    code: Vec<(Range<u64>, SectionData)>,
+    pub(crate) dbg_info: RawDebugInfo,
+    /// The debug files found for the image that belong to another build.
+    #[cfg(feature = "std")]
+    mismatched_debug_files: Vec<std::path::PathBuf>,
+    /// The line context of the image, shared by the threads resolving in it.
+    line_cache: serialized::Serialized<line_info::LineCache<'a>>,
+    #[cfg(feature = "std")]
+    plt: OnceLock<Vec<plt::PltEntry>>,
+    /// The SVMA ranges covered by more than one FDE of `.eh_frame`, sorted. Found on the first
+    /// lookup through `.eh_frame_hdr`, whose index cannot tell overlapping FDEs apart.
+    fde_overlaps: OnceLock<Vec<Range<u64>>>,
+    /// Whether every entry of the `.eh_frame_hdr` index points at the FDE it is keyed by.
+    /// Checked on the first miss of the index, which is only trusted if it is consistent.
+    index_consistent: OnceLock<bool>,
+    pub(crate) eh_frame_section: (SectionData, gimli::EhFrame<ImageReader<'a>>),
+    pub(crate) eh_frame_hdr_section: Option<(SectionData, ParsedEhFrameHdr<ImageReader<'a>>)>,
+    /// `.debug_frame`, or `__debug_frame` in Mach-O images, consulted for code that
+    /// `.eh_frame` does not cover.
+    pub(crate) debug_frame_section: Option<(SectionData, gimli::DebugFrame<ImageReader<'a>>)>,
+    #[cfg(target_arch = "arm")]
+    pub exidx: Option<exidx::ExIdx>,
     pub endian: RunTimeEndian,
+    address_size: u8,
+    build_id: Option<Vec<u8>>,
+    /// Functions loaded from a cache file, which replace the DWARF for name lookups.
+    cached_functions: Option<cache::FunctionIndex>,
+    /// The mapped files the sections borrow from, i.e. the image file and its separate debug
+    /// files. Sections are read in place rather than copied, so large debug info only takes
+    /// memory for the pages that are touched.
+    _mappings: Vec<Mmap>,
 }
 
 impl<'a> Image<'a> {
     pub fn has(&self, avma: usize) -> bool {
-        self.start_avma <= avma && avma < self.start_avma + self.length
+        covers(self.start_avma, self.length, &self.segments, avma)
+    }
+
+    /// The SVMA, i.e. the address in the image file, that `avma` is loaded from. The bias is the
+    /// slide of the whole image, which for Mach-O images is taken between the loaded `__TEXT`
+    /// segment and its `vmaddr` rather than from a load address of zero.
+    pub fn avma_to_svma(&self, avma: usize) -> u64 {
+        avma.wrapping_sub(self.bias) as u64
+    }
+
+    /// The AVMA that `svma` is loaded at.
+    pub fn svma_to_avma(&self, svma: u64) -> usize {
+        (svma as usize).wrapping_add(self.bias)
+    }
+
+    /// The byte order of the image.
+    pub fn endian(&self) -> RunTimeEndian {
+        self.endian
+    }
+
+    /// The size of an address in the image, in bytes, following its ELF class or Mach-O
+    /// format rather than the running process.
+    pub fn address_size(&self) -> u8 {
+        self.address_size
+    }
+
+    /// The GNU build ID of an ELF image or the UUID of a Mach-O image.
+    pub fn build_id(&self) -> Option<&[u8]> {
+        self.build_id.as_deref()
+    }
+
+    /// The SVMA that `offset` into the image file is loaded at, following the loadable
+    /// segments, e.g. to place an offset reported by `objdump -F` or `perf`. `None` if the
+    /// offset is not loaded.
+    pub fn file_offset_to_svma(&self, offset: u64) -> Option<u64> {
+        self.file_segments
+            .iter()
+            .find(|(start, svmas)| (*start..start + (svmas.end - svmas.start)).contains(&offset))
+            .map(|(start, svmas)| svmas.start + (offset - start))
+    }
+
+    /// The offset into the image file that `svma` is loaded from. `None` if nothing in the
+    /// file backs it, e.g. in `.bss`.
+    pub fn svma_to_file_offset(&self, svma: u64) -> Option<u64> {
+        self.file_segments
+            .iter()
+            .find(|(_, svmas)| svmas.contains(&svma))
+            .map(|(start, svmas)| start + (svma - svmas.start))
+    }
+
+    /// The DWARF sections that were malformed, e.g. truncated. Line info is still resolved from
+    /// them as far as they parse. Without the `line-info` feature, the sections are never
+    /// checked and this is empty.
+    #[cfg(feature = "line-info")]
+    pub fn invalid_debug_sections(&self) -> &[gimli::SectionId] {
+        // SAFETY: the repair is never changed or moved once set.
+        unsafe {
+            self.line_cache
+                .with(|cache| &self.repair(cache).invalid[..])
+        }
+    }
+
+    #[cfg(not(feature = "line-info"))]
+    pub fn invalid_debug_sections(&self) -> &[gimli::SectionId] {
+        &[]
+    }
+
+    /// The replacements for the malformed sections of the debug info, found on first use.
+    #[cfg(feature = "line-info")]
+    fn repair<'s>(&self, cache: &'s line_info::LineCache<'a>) -> &'s debug_info::Repair {
+        cache
+            .repair
+            .get_or_init(|| debug_info::repair(&self.dbg_info, self.endian))
+    }
+
+    /// The separate debug files found for the image, e.g. through `.gnu_debuglink` or as a
+    /// `.dSYM`, that were skipped because their build ID or UUID differs from the image's. A
+    /// stale debug file would report wrong locations.
+    #[cfg(feature = "std")]
+    pub fn mismatched_debug_files(&self) -> &[std::path::PathBuf] {
+        &self.mismatched_debug_files
+    }
+
+    /// Whether the object file at `path` is a debug file of this image, i.e. carries its build
+    /// ID or, in Mach-O, its UUID. Always `false` for images without either.
+    #[cfg(feature = "std")]
+    pub fn matches_debug_file<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+    ) -> Result<bool, UnwindError> {
+        let (object, _mmap) = raw_image::keep_mapped(raw_image::load(path)?);
+        let identity = debug_info::identity(&object);
+        Ok(self.build_id.is_some() && identity == self.build_id)
+    }
+
+    /// Resolve function names and ranges from `functions`, e.g. read from a cache file, rather
+    /// than from the DWARF of the image. The line context is not built for lookups anymore, so
+    /// neither inlined frames nor source locations are reported.
+    pub fn set_cached_functions(&mut self, functions: cache::FunctionIndex) {
+        self.cached_functions = Some(functions);
+    }
+
+    /// Whether function names are resolved from a cached index.
+    pub fn is_cached(&self) -> bool {
+        self.cached_functions.is_some()
+    }
+
+    /// Find the cached function covering the given SVMA, with its SVMA range.
+    pub fn find_cached_function(&self, svma: u64) -> Option<(&str, Range<u64>)> {
+        cache::lookup(self.cached_functions.as_ref()?, svma)
+    }
+
+    /// The DWARF of the image, from the image itself or its separate debug file. Line info
+    /// is resolved from the same sections, so units found here match the resolved frames.
+    pub fn dwarf(&self) -> gimli::Dwarf<ImageReader<'_>> {
+        debug_info::borrow(&self.dbg_info, self.endian)
+    }
+
+    /// The unit of [`Image::dwarf`] covering `svma`, to evaluate expressions of that code in,
+    /// e.g. with [`CursorState::eval_in_unit`](crate::cursor::state::CursorState::eval_in_unit).
+    pub fn find_unit(&self, svma: u64) -> Option<gimli::Unit<ImageReader<'_>>> {
+        debug_info::unit_at(&self.dwarf(), svma)
+    }
+
+    /// The variables in scope at `svma` in `unit`, a unit of [`Image::dwarf`].
+    pub fn scope_at<'s>(
+        &'s self,
+        unit: &gimli::Unit<ImageReader<'s>>,
+        svma: u64,
+    ) -> debug_info::Scope<'s> {
+        debug_info::scope_at(&self.dwarf(), unit, svma)
+    }
+
+    /// The functions of the image as `(name, start, size)`, with raw names and SVMA starts.
+    /// The DWARF subprograms come first; code without debug info is listed from the symbol
+    /// table.
+    pub fn functions(&self) -> impl Iterator<Item = (&str, u64, u64)> + '_ {
+        let mut functions = debug_info::subprograms(&self.dwarf());
+        let known: HashSet<_> = functions.iter().map(|x| x.1).collect();
+        functions.extend(
+            self.symbol_map
+                .symbols()
+                .iter()
+                .filter(|x| x.is_text() && !known.contains(&x.address()))
+                .map(|x| (x.name(), x.address(), x.size())),
+        );
+        functions.into_iter()
+    }
+
+    /// Run `f` on the line context of the image and the cache holding the split DWARF and unit
+    /// indexes its lookups built. The context is built from the debug info on first use and
+    /// shared by all threads, whose lookups are serialized.
+    #[cfg(feature = "line-info")]
+    fn with_line_context<'s, R>(
+        &'s self,
+        f: impl FnOnce(&'s LineContext<'a>, &'s line_info::LineCache<'a>) -> R,
+    ) -> Option<R> {
+        // SAFETY: lookups only fill in lazily parsed parts of the context and add split DWARF,
+        // neither of which is changed or moved once set, and results only borrow from those.
+        // The unit indexes may move as more are added, but are never borrowed past a lookup.
+        unsafe {
+            self.line_cache.with(|cache| {
+                let ctx = cache
+                    .context
+                    .get_or_init(|| {
+                        line_info::load(&self.dbg_info, self.repair(cache), self.endian)
+                    })
+                    .as_ref()?;
+                Some(f(ctx, cache))
+            })
+        }
+    }
+
+    /// Whether the line context of the image has been built.
+    pub fn has_line_context(&self) -> bool {
+        // SAFETY: nothing is borrowed past the call.
+        unsafe { self.line_cache.with(|x| x.context.get().is_some()) }
+    }
+
+    /// The name of the function a PLT stub at the given SVMA jumps to. The relocations are
+    /// read from the image file on first use.
+    #[cfg(feature = "std")]
+    pub fn plt_target(&self, svma: u64) -> Option<&str> {
+        let entries = self.plt.get_or_init(|| {
+            raw_image::load(&self.filename)
+                .map(|(object, mmap, file)| {
+                    let entries = plt::load(&object);
+                    ManuallyDrop::into_inner(mmap);
+                    ManuallyDrop::into_inner(file);
+                    entries
+                })
+                .unwrap_or_default()
+        });
+        plt::lookup(entries, svma)
+    }
+
+    /// Without the `std` feature, the image file cannot be read, so PLT stubs are not named.
+    #[cfg(not(feature = "std"))]
+    pub fn plt_target(&self, _svma: u64) -> Option<&str> {
+        None
+    }
+
+    /// Find the FDE covering the given SVMA, preferring the `.eh_frame_hdr` index. Of
+    /// overlapping FDEs, e.g. left by LTO or hand-written CFI, the one with the tightest range
+    /// is taken. `.eh_frame` is only scanned without an index, in an overlap, or when the index
+    /// misses and turns out to point at the wrong FDEs.
+    pub fn find_fde(
+        &self,
+        svma: u64,
+    ) -> Result<gimli::FrameDescriptionEntry<ImageReader<'a>>, gimli::Error> {
+        let eh_frame = &self.eh_frame_section.1;
+        let bases = &self.base_addresses;
+        let indexed = self
+            .eh_frame_hdr_section
+            .as_ref()
+            .and_then(|x| x.1.table())
+            .map(|table| {
+                table.fde_for_address(eh_frame, bases, svma, gimli::EhFrame::cie_from_offset)
+            });
+        match indexed {
+            Some(Ok(fde)) if !self.in_fde_overlap(svma) => Ok(fde),
+            // A stale or inconsistent `.eh_frame_hdr` may point at the wrong FDE, which gimli
+            // reports as a miss, in which case we fall back to scanning `.eh_frame`.
+            Some(Err(gimli::Error::NoUnwindInfoForAddress)) if !self.index_consistent() => {
+                tightest_fde(eh_frame, bases, svma)
+            }
+            Some(Err(e)) => Err(e),
+            Some(Ok(_)) | None => tightest_fde(eh_frame, bases, svma),
+        }
+    }
+
+    /// Whether each entry of the `.eh_frame_hdr` index points at an FDE starting at its key.
+    fn index_consistent(&self) -> bool {
+        *self.index_consistent.get_or_init(|| {
+            let Some(table) = self.eh_frame_hdr_section.as_ref().and_then(|x| x.1.table()) else {
+                return false;
+            };
+            let eh_frame = &self.eh_frame_section.1;
+            let bases = &self.base_addresses;
+            let mut entries = table.iter(bases);
+            loop {
+                let (key, pointer) = match entries.next() {
+                    Ok(Some(entry)) => entry,
+                    Ok(None) => return true,
+                    Err(_) => return false,
+                };
+                let fde = table.pointer_to_offset(pointer).and_then(|offset| {
+                    eh_frame.fde_from_offset(bases, offset, gimli::EhFrame::cie_from_offset)
+                });
+                match (key.direct(), fde) {
+                    (Ok(key), Ok(fde)) if fde.initial_address() == key => {}
+                    _ => return false,
+                }
+            }
+        })
+    }
+
+    /// Whether more than one FDE of `.eh_frame` covers the given SVMA.
+    fn in_fde_overlap(&self, svma: u64) -> bool {
+        let overlaps = self.fde_overlaps.get_or_init(|| fde_overlaps(self.fdes()));
+        let i = overlaps.partition_point(|x| x.end <= svma);
+        overlaps.get(i).is_some_and(|x| x.contains(&svma))
+    }
+
+    /// The FDEs of `.eh_frame` in section order. Malformed FDEs are skipped, and the walk ends
+    /// at an entry whose length cannot be read.
+    pub fn fdes(&self) -> impl Iterator<Item = FdeInfo> + '_ {
+        let mut entries = self.eh_frame_section.1.entries(&self.base_addresses);
+        core::iter::from_fn(move || loop {
+            if let gimli::CieOrFde::Fde(partial) = entries.next().ok()?? {
+                if let Ok(fde) = partial.parse(gimli::EhFrame::cie_from_offset) {
+                    return Some(FdeInfo {
+                        initial_address: fde.initial_address(),
+                        len: fde.len(),
+                        offset: fde.offset() as u64,
+                        cie_offset: fde.cie().offset() as u64,
+                    });
+                }
+            }
+        })
+    }
+
+    /// Find the FDE covering the given SVMA in `.debug_frame`.
+    pub fn find_debug_frame_fde(
+        &self,
+        svma: u64,
+    ) -> Result<gimli::FrameDescriptionEntry<ImageReader<'a>>, gimli::Error> {
+        let (_, debug_frame) = self
+            .debug_frame_section
+            .as_ref()
+            .ok_or(gimli::Error::NoUnwindInfoForAddress)?;
+        tightest_fde(debug_frame, &self.base_addresses, svma)
+    }
+
+    /// Evaluate the unwind info of the given SVMA into `ctx`, from `.eh_frame` or, failing
+    /// that, `.debug_frame`. The row comes with the return address column of its CIE.
+    pub fn unwind_info_for_address<'ctx, S>(
+        &self,
+        ctx: &'ctx mut gimli::UnwindContext<ImageReader<'a>, S>,
+        svma: u64,
+    ) -> Result<
+        (
+            &'ctx gimli::UnwindTableRow<ImageReader<'a>, S>,
+            gimli::Register,
+        ),
+        gimli::Error,
+    >
+    where
+        S: gimli::UnwindContextStorage<ImageReader<'a>>,
+    {
+        let bases = &self.base_addresses;
+        match (self.find_fde(svma), &self.debug_frame_section) {
+            (Ok(fde), _) => fde
+                .unwind_info_for_address(&self.eh_frame_section.1, bases, ctx, svma)
+                .map(|row| (row, fde.cie().return_address_register())),
+            (Err(e), None) => Err(e),
+            (Err(_), Some((_, debug_frame))) => {
+                let fde = self.find_debug_frame_fde(svma)?;
+                fde.unwind_info_for_address(debug_frame, bases, ctx, svma)
+                    .map(|row| (row, fde.cie().return_address_register()))
+            }
+        }
+    }
+
+    /// The SVMAs covered by the FDE for `svma`, from `.eh_frame` or else `.debug_frame`.
+    pub fn fde_range(&self, svma: u64) -> Option<Range<u64>> {
+        let fde = self
+            .find_fde(svma)
+            .or_else(|_| self.find_debug_frame_fde(svma))
+            .ok()?;
+        Some(fde.initial_address()..fde.initial_address() + fde.len())
+    }
+
+    /// Attach the unwind tables of the object file at `path`, e.g. kept aside with `objcopy
+    /// --only-section=.eh_frame --only-section=.eh_frame_hdr` before the image was stripped of
+    /// them. The sections must lie at the same SVMAs as in the image. Only the tables the image
+    /// lacks are taken, so its own CFI is still preferred.
+    #[cfg(feature = "std")]
+    pub fn attach_unwind_info<P: AsRef<std::path::Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<(), UnwindError> {
+        let (object, mmap) = raw_image::keep_mapped(raw_image::load(path)?);
+        let bases = base_addresses::set_unwind_tables(self.base_addresses.clone(), &object);
+        let tables = unwind_tables(&object, &bases, self.endian, self.address_size);
+        let attach_eh_frame = self.eh_frame_section.0.is_empty() && !tables.eh_frame.0.is_empty();
+        let attach_debug_frame = self.debug_frame_section.is_none() && tables.debug_frame.is_some();
+        if !attach_eh_frame && !attach_debug_frame {
+            return Err(UnwindError::NotSupported(
+                "no unwind tables the image lacks",
+            ));
+        }
+        if attach_eh_frame {
+            self.base_addresses = bases;
+            self.eh_frame_section = tables.eh_frame;
+            self.eh_frame_hdr_section = tables.eh_frame_hdr;
+            self.fde_overlaps = OnceLock::new();
+            self.index_consistent = OnceLock::new();
+        }
+        if attach_debug_frame {
+            self.debug_frame_section = tables.debug_frame;
+        }
+        self._mappings.push(mmap);
+        Ok(())
+    }
+
+    /// Drop the resources that are rebuilt on demand: the line contexts of all threads and any
+    /// split DWARF they loaded. Metadata needed for unwinding and symbol map lookups is kept.
+    pub fn release_heavy(&mut self) {
+        *self.line_cache.get_mut() = line_info::LineCache::new(&self.filename, self.endian);
+    }
+
+    /// Pass the (possibly inlined) frames at the given SVMA to `f`, loading split DWARF on
+    /// demand. The frames are read lazily, so they must be collected within `f`.
+    #[cfg(feature = "line-info")]
+    pub(crate) fn find_frames<'s, R>(
+        &'s self,
+        svma: u64,
+        f: impl FnOnce(line_info::FrameIter<'s, 'a>) -> R,
+    ) -> Option<R> {
+        self.with_line_context(|ctx, cache| {
+            line_info::resolve(ctx.find_frames(svma), &cache.split_dwarf)
+                .ok()
+                .map(f)
+        })
+        .flatten()
+    }
+
+    /// Find the symbol covering the given SVMA. A symbol without a size is not taken past the
+    /// FDE it starts in, as the code that follows may belong to a function whose symbol was
+    /// stripped, e.g. a static constructor.
+    pub fn find_symbol(&self, svma: u64) -> Option<&symbol_map::OwnedSymbolMapName> {
+        let symbol = symbol_map::lookup(&self.symbol_map, svma)?;
+        let foreign = !symbol.is_sized()
+            && self
+                .fde_range(svma)
+                .is_some_and(|x| x.start > symbol.address());
+        (!foreign).then_some(symbol)
+    }
+
+    /// Find the name of the code section containing the given SVMA, e.g. `<.plt>`.
+    pub fn find_section(&self, svma: u64) -> Option<&str> {
+        self.sections
+            .iter()
+            .find(|(range, _)| range.contains(&svma))
+            .map(|(_, name)| name.as_str())
+    }
+
+    /// Up to `len` bytes of machine code starting at `svma`, cut short at the end of its code
+    /// section, e.g. to disassemble a function found by `find_function_range`.
+    pub fn code_bytes(&self, svma: u64, len: usize) -> Option<&[u8]> {
+        let (range, data) = self.code.iter().find(|(range, _)| range.contains(&svma))?;
+        let start = (svma - range.start) as usize;
+        let end = data.len().min(start.saturating_add(len));
+        data.get(start..end)
+    }
+
+    /// Find the SVMA range of the function containing the given SVMA, preferring the size of
+    /// the ELF symbol and falling back to the cached function or the DWARF subprogram.
+    pub fn find_function_range(&self, svma: u64) -> Option<Range<u64>> {
+        let symbol = self
+            .find_symbol(svma)
+            .and_then(|x| x.range())
+            .filter(|x| x.contains(&svma));
+        if self.is_cached() {
+            return symbol.or_else(|| self.find_cached_function(svma).map(|x| x.1));
+        }
+        #[cfg(feature = "line-info")]
+        let symbol = symbol.or_else(|| self.find_subprogram_range(svma));
+        symbol
+    }
+
+    /// Find the SVMA range of the DWARF subprogram containing the given SVMA.
+    #[cfg(feature = "line-info")]
+    pub fn find_subprogram_range(&self, svma: u64) -> Option<Range<u64>> {
+        self.with_line_context(|ctx, cache| line_info::function_range(ctx, cache, svma))
+            .flatten()
+    }
+
+    /// Find all SVMA ranges of the DWARF subprogram containing the given SVMA, e.g. both the
+    /// hot and the cold part of a split function.
+    #[cfg(feature = "line-info")]
+    pub fn find_subprogram_ranges(&self, svma: u64) -> Option<Vec<Range<u64>>> {
+        self.with_line_context(|ctx, cache| line_info::function_ranges(ctx, cache, svma))
+            .flatten()
+    }
+
+    /// Find the SVMA of the function called by the call returning to the given SVMA.
+    #[cfg(feature = "line-info")]
+    pub fn find_call_target(&self, return_svma: u64) -> Option<u64> {
+        self.with_line_context(|ctx, cache| {
+            line_info::call_target(ctx, &cache.split_dwarf, return_svma)
+        })
+        .flatten()
+    }
+
+    /// Find the line table discriminator at the given SVMA.
+    #[cfg(feature = "line-info")]
+    pub fn find_discriminator(&self, svma: u64) -> Option<u64> {
+        self.with_line_context(|ctx, cache| line_info::discriminator(ctx, cache, svma))
+            .flatten()
     }
 }
 
 pub type ImageReader<'a> = EndianSlice<'a, RunTimeEndian>;
 
-pub fn load_all<'a>() -> Vec<Image<'a>> {
-    let mut vec = Vec::new();
+/// Scan `section` for the FDE with the tightest range covering `svma`. A malformed entry ends
+/// the scan, which fails unless a covering FDE was found before it.
+fn tightest_fde<'a, S>(
+    section: &S,
+    bases: &gimli::BaseAddresses,
+    svma: u64,
+) -> Result<gimli::FrameDescriptionEntry<ImageReader<'a>>, gimli::Error>
+where
+    S: UnwindSection<ImageReader<'a>>,
+{
+    let mut tightest: Option<gimli::FrameDescriptionEntry<_>> = None;
+    let mut entries = section.entries(bases);
+    let error = loop {
+        let fde = match entries.next() {
+            Ok(Some(gimli::CieOrFde::Fde(partial))) => partial.parse(S::cie_from_offset),
+            Ok(Some(gimli::CieOrFde::Cie(_))) => continue,
+            Ok(None) => break gimli::Error::NoUnwindInfoForAddress,
+            Err(e) => break e,
+        };
+        match fde {
+            Ok(fde) if fde.contains(svma) => {
+                if tightest.as_ref().is_none_or(|x| fde.len() < x.len()) {
+                    tightest = Some(fde);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => break e,
+        }
+    };
+    tightest.ok_or(error)
+}
+
+/// The SVMA ranges covered by more than one of `fdes`, sorted and disjoint.
+fn fde_overlaps(fdes: impl Iterator<Item = FdeInfo>) -> Vec<Range<u64>> {
+    let mut ranges: Vec<_> = fdes.map(|x| x.range()).collect();
+    ranges.sort_by_key(|x| x.start);
+    let mut overlaps: Vec<Range<u64>> = Vec::new();
+    let mut end = 0;
+    for range in ranges {
+        if range.start < end {
+            let overlap = range.start..range.end.min(end);
+            match overlaps.last_mut() {
+                Some(last) if last.end >= overlap.start => last.end = last.end.max(overlap.end),
+                _ => overlaps.push(overlap),
+            }
+        }
+        end = end.max(range.end);
+    }
+    overlaps
+}
+
+/// An FDE of `.eh_frame`, as listed by [`Image::fdes`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FdeInfo {
+    /// The first SVMA covered.
+    pub initial_address: u64,
+    /// The number of bytes covered.
+    pub len: u64,
+    /// The offset of the FDE in `.eh_frame`.
+    pub offset: u64,
+    /// The offset of its CIE in `.eh_frame`.
+    pub cie_offset: u64,
+}
+
+impl FdeInfo {
+    /// The SVMAs covered.
+    pub fn range(&self) -> Range<u64> {
+        self.initial_address..self.initial_address.saturating_add(self.len)
+    }
+}
+
+/// The name of an image: the path it is loaded from, which need not be UTF-8, or without the
+/// `std` feature the name it is given.
+#[cfg(feature = "std")]
+type ImageName = OsString;
+#[cfg(not(feature = "std"))]
+type ImageName = String;
+
+/// The location of a loaded shared library, collected before its image is parsed.
+struct ImageDescriptor {
+    name: ImageName,
+    bias: usize,
+    start_avma: usize,
+    length: usize,
+    segments: Vec<Range<usize>>,
+}
+
+/// Whether `avma` lies in one of `segments`, or in the whole range if no segment is known.
+fn covers(start_avma: usize, length: usize, segments: &[Range<usize>], avma: usize) -> bool {
+    if segments.is_empty() {
+        start_avma <= avma && avma < start_avma + length
+    } else {
+        segments.iter().any(|x| x.contains(&avma))
+    }
+}
+
+/// Limits on the work spent loading images up front. Images beyond the limits are loaded on
+/// demand, the first time an address inside them is looked up.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Default)]
+pub struct LoadOptions {
+    /// The maximum number of images to load eagerly.
+    pub max_images: Option<usize>,
+    /// Stop loading eagerly once this much time has passed. The budget is checked between
+    /// batches of images loaded together, one per thread with the `parallel` feature and a
+    /// single image otherwise, so at least one batch is loaded.
+    pub time_budget: Option<Duration>,
+    /// Enumerate the images from the file mappings in `/proc/self/maps` rather than through
+    /// the dynamic linker. This is the fallback anyway when the dynamic linker reports no
+    /// image, e.g. in some fully static binaries. Only supported on Linux.
+    pub from_proc_maps: bool,
+    /// A cache file written by [`GlobalContext::save_cache`](crate::GlobalContext::save_cache)
+    /// to resolve function names from. The DWARF of the images whose build ID it covers is not
+    /// loaded at all. A cache that cannot be read is ignored.
+    pub symbol_cache: Option<std::path::PathBuf>,
+}
+
+/// What all images of one load share, read once rather than for every image. The default is
+/// empty, for images that are not mapped by the process.
+#[derive(Default)]
+struct LoadContext {
+    symbol_cache: HashMap<Vec<u8>, cache::FunctionIndex>,
+    /// The memory mappings of the process when the load started.
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    maps: Vec<raw_image::Mapping>,
+}
+
+#[cfg(feature = "std")]
+impl LoadContext {
+    fn new(options: &LoadOptions) -> Self {
+        let symbol_cache = options
+            .symbol_cache
+            .as_ref()
+            .and_then(|path| std::fs::File::open(path).ok())
+            .and_then(|file| cache::read(std::io::BufReader::new(file)).ok())
+            .unwrap_or_default();
+        Self {
+            symbol_cache,
+            #[cfg(target_os = "linux")]
+            maps: raw_image::read_maps(),
+        }
+    }
+}
+
+/// An image left out of the initial load.
+#[cfg(feature = "std")]
+pub struct DeferredImage<'a> {
+    desc: ImageDescriptor,
+    context: Arc<LoadContext>,
+    image: OnceLock<Option<Image<'a>>>,
+}
+
+#[cfg(feature = "std")]
+impl<'a> DeferredImage<'a> {
+    fn new(desc: ImageDescriptor, context: Arc<LoadContext>) -> Self {
+        Self {
+            desc,
+            context,
+            image: OnceLock::new(),
+        }
+    }
+
+    pub fn has(&self, avma: usize) -> bool {
+        covers(
+            self.desc.start_avma,
+            self.desc.length,
+            &self.desc.segments,
+            avma,
+        )
+    }
+
+    /// The image, loaded on first use. `None` if it cannot be read.
+    pub fn get(&self) -> Option<&Image<'a>> {
+        self.image
+            .get_or_init(|| load(&self.desc, &self.context).ok())
+            .as_ref()
+    }
+
+    /// The image if it has been loaded already.
+    pub fn loaded(&self) -> Option<&Image<'a>> {
+        self.image.get().and_then(Option::as_ref)
+    }
+
+    /// The image if it has been loaded already.
+    pub fn loaded_mut(&mut self) -> Option<&mut Image<'a>> {
+        self.image.get_mut().and_then(Option::as_mut)
+    }
+}
+
+/// WebAssembly modules cannot enumerate the loaded code, so there are no images to load.
+#[cfg(all(feature = "std", target_arch = "wasm32"))]
+fn descriptors(_options: &LoadOptions, _context: &LoadContext) -> Vec<ImageDescriptor> {
+    Vec::new()
+}
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+fn descriptors(options: &LoadOptions, context: &LoadContext) -> Vec<ImageDescriptor> {
+    #[cfg(target_os = "linux")]
+    if options.from_proc_maps {
+        return mapped_descriptors(&context.maps);
+    }
+    let descriptors = shared_libraries();
+    #[cfg(target_os = "linux")]
+    if descriptors.is_empty() {
+        return mapped_descriptors(&context.maps);
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (options, context);
+    descriptors
+}
+
+/// The images the dynamic linker reports.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+fn shared_libraries() -> Vec<ImageDescriptor> {
+    let mut descriptors = Vec::new();
 
     TargetSharedLibrary::each(|x| {
-        if let Ok((object, mmap, file)) = raw_image::load(x.name()) {
-            if let Some(ba) = base_addresses::load(&object) {
-                let symbol_map = symbol_map::load(&object);
-
-                let dbg_info = debug_info::load(x.name(), &object);
-                let endian = if object.is_little_endian() {
-                    RunTimeEndian::Little
-                } else {
-                    RunTimeEndian::Big
-                };
+        descriptors.push(ImageDescriptor {
+            name: x.name().to_os_string(),
+            bias: slide(x),
+            start_avma: x.actual_load_addr().0,
+            length: x.len(),
+            segments: x
+                .segments()
+                .filter(|segment| segment.is_load())
+                .map(|segment| {
+                    let start = segment.actual_virtual_memory_address(x).0;
+                    start..start + segment.len()
+                })
+                .collect(),
+        })
+    });
 
-                let line_context = line_info::load(&dbg_info, endian);
-
-                let address_size = std::mem::size_of::<*const ()>() as u8;
-                let eh_frame_hdr_section = object
-                    .section_by_name(".eh_frame_hdr")
-                    .and_then(|x| x.uncompressed_data().ok())
-                    .map(|x| x.to_vec())
-                    .and_then(|data| unsafe {
-                        let slice: &'a [u8] = std::slice::from_raw_parts(data.as_ptr(), data.len());
-                        gimli::EhFrameHdr::new(slice, endian)
-                            .parse(&ba, address_size)
-                            .ok()
-                            .map(|hdr| (data, hdr))
-                    });
+    descriptors
+}
 
-                let eh_frame_data = object
-                    .section_by_name(".eh_frame")
-                    .and_then(|x| x.uncompressed_data().ok())
-                    .map(|x| x.to_vec())
-                    .unwrap_or_else(Default::default);
+/// How far a shared library was moved from the addresses it was linked at. Mach-O images are
+/// linked at a nonzero `vmaddr` of their `__TEXT` segment, so the slide is measured there.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+fn slide(library: &TargetSharedLibrary) -> usize {
+    #[cfg(target_os = "macos")]
+    if let Some(text) = library.segments().find(|x| x.name() == "__TEXT") {
+        let actual = text.actual_virtual_memory_address(library).0;
+        return actual.wrapping_sub(text.stated_virtual_memory_address().0);
+    }
+    library.virtual_memory_bias().0
+}
 
-                let eh_frame = unsafe {
-                    let slice: &'a [u8] =
-                        std::slice::from_raw_parts(eh_frame_data.as_ptr(), eh_frame_data.len());
-                    gimli::EhFrame::new(slice, endian)
-                };
+/// The images mapped from ELF files, found in `/proc/self/maps`. A file counts as an image if
+/// one of its mapped headers describes executable segments that are mapped executable from the
+/// file, which tells loaded images from plain mappings of the file, e.g. of the running
+/// executable itself for reading its debug info.
+#[cfg(all(feature = "std", target_os = "linux"))]
+fn mapped_descriptors(maps: &[raw_image::Mapping]) -> Vec<ImageDescriptor> {
+    struct MappedFile<'m> {
+        path: &'m str,
+        headers: Vec<usize>,
+        executable: Vec<Range<usize>>,
+    }
 
-                vec.push(Image {
-                    filename: x.name().to_string_lossy().to_string(),
-                    base_addresses: ba,
-                    bias: x.virtual_memory_bias().0,
-                    start_avma: x.actual_load_addr().0,
-                    length: x.len(),
-                    symbol_map,
-                    dbg_info,
-                    line_context,
-                    eh_frame_section: (eh_frame_data, eh_frame),
-                    eh_frame_hdr_section,
-                    endian,
+    let mut files: Vec<MappedFile> = Vec::new();
+    for mapping in maps {
+        let path = mapping.path.as_str();
+        if !path.starts_with('/') {
+            continue;
+        }
+        let file = match files.iter().position(|x| x.path == path) {
+            Some(i) => &mut files[i],
+            None => {
+                files.push(MappedFile {
+                    path,
+                    headers: Vec::new(),
+                    executable: Vec::new(),
                 });
+                files.last_mut().unwrap()
+            }
+        };
+        if mapping.offset == 0 && mapping.perms.starts_with('r') {
+            file.headers.push(mapping.range.start);
+        }
+        if mapping.perms.contains('x') {
+            file.executable.push(mapping.range.clone());
+        }
+    }
+    files
+        .into_iter()
+        .filter(|x| !x.executable.is_empty())
+        .filter_map(|x| {
+            let (bias, segments) = x.headers.iter().find_map(|header| {
+                let (bias, segments) = raw_image::elf_segments(*header)?;
+                segments
+                    .iter()
+                    .filter(|(_, executable)| *executable)
+                    .all(|(range, _)| x.executable.iter().any(|x| x.contains(&range.start)))
+                    .then_some((bias, segments))
+            })?;
+            let segments: Vec<_> = segments.into_iter().map(|(range, _)| range).collect();
+            let start_avma = segments.iter().map(|x| x.start).min()?;
+            let end_avma = segments.iter().map(|x| x.end).max()?;
+            Some(ImageDescriptor {
+                name: x.path.into(),
+                bias,
+                start_avma,
+                length: end_avma - start_avma,
+                segments,
+            })
+        })
+        .collect()
+}
+
+#[cfg(feature = "std")]
+fn load_each<'a>(
+    descriptors: &[ImageDescriptor],
+    context: &LoadContext,
+) -> Vec<Result<Image<'a>, UnwindError>> {
+    // Every image only borrows from buffers it owns, so images can be built independently.
+    #[cfg(feature = "parallel")]
+    return descriptors.par_iter().map(|x| load(x, context)).collect();
+    #[cfg(not(feature = "parallel"))]
+    return descriptors.iter().map(|x| load(x, context)).collect();
+}
+
+/// How many images [`load_each`] loads at once, i.e. how many are loaded between two checks
+/// of a time budget.
+#[cfg(feature = "std")]
+pub(crate) fn batch_size() -> usize {
+    #[cfg(feature = "parallel")]
+    return rayon::current_num_threads();
+    #[cfg(not(feature = "parallel"))]
+    return 1;
+}
+
+pub(crate) fn sorted(mut images: Vec<Image<'_>>) -> Vec<Image<'_>> {
+    images.sort_by_key(|x| core::cmp::Reverse(x.start_avma));
+    images
+}
+
+/// Load every image mapped into the process, skipping those that cannot be read.
+#[cfg(feature = "std")]
+pub fn load_all<'a>() -> Vec<Image<'a>> {
+    let options = LoadOptions::default();
+    let context = LoadContext::new(&options);
+    sorted(
+        load_each(&descriptors(&options, &context), &context)
+            .into_iter()
+            .filter_map(Result::ok)
+            .collect(),
+    )
+}
+
+/// Load the images mapped into the process within the limits of `options`, returning the
+/// loaded images and those deferred.
+#[cfg(feature = "std")]
+pub fn load_with<'a>(options: &LoadOptions) -> (Vec<Image<'a>>, Vec<DeferredImage<'a>>) {
+    // The main executable comes first, followed by the other images in load order, which
+    // roughly matches how likely they are to appear in stacks.
+    let context = Arc::new(LoadContext::new(options));
+    let mut eager = descriptors(options, &context);
+    let max_images = options.max_images.unwrap_or(usize::MAX).min(eager.len());
+    let mut deferred = eager.split_off(max_images);
+    let images = match options.time_budget {
+        None => load_each(&eager, &context)
+            .into_iter()
+            .filter_map(Result::ok)
+            .collect(),
+        Some(budget) => {
+            let start = Instant::now();
+            let mut images = Vec::new();
+            let mut loaded = 0;
+            for batch in eager.chunks(batch_size()) {
+                images.extend(
+                    load_each(batch, &context)
+                        .into_iter()
+                        .filter_map(Result::ok),
+                );
+                loaded += batch.len();
+                if start.elapsed() >= budget {
+                    break;
+                }
+            }
+            deferred.splice(0..0, eager.drain(loaded..));
+            images
+        }
+    };
+    (
+        sorted(images),
+        deferred
+            .into_iter()
+            .map(|x| DeferredImage::new(x, context.clone()))
+            .collect(),
+    )
+}
+
+/// Load every image mapped into the process. Unreadable images are skipped as in
+/// [`load_all`], but it is an error if none of them can be loaded; the first failure is
+/// reported in that case.
+#[cfg(feature = "std")]
+pub fn try_load_all<'a>() -> Result<Vec<Image<'a>>, UnwindError> {
+    let options = LoadOptions::default();
+    let context = LoadContext::new(&options);
+    try_load(&descriptors(&options, &context), &context)
+}
+
+#[cfg(feature = "std")]
+fn try_load<'a>(
+    descriptors: &[ImageDescriptor],
+    context: &LoadContext,
+) -> Result<Vec<Image<'a>>, UnwindError> {
+    let mut first_error = None;
+    let mut images = Vec::new();
+    for result in load_each(descriptors, context) {
+        match result {
+            Ok(image) => images.push(image),
+            Err(e) => {
+                first_error.get_or_insert(e);
             }
-            ManuallyDrop::into_inner(mmap);
-            ManuallyDrop::into_inner(file);
         }
+    }
+    match first_error {
+        Some(e) if images.is_empty() => Err(e),
+        None if images.is_empty() => Err(UnwindError::UnwindLogicalError(
+            "no loaded image found in the process",
+        )),
+        _ => Ok(sorted(images)),
+    }
+}
+
+#[cfg(feature = "std")]
+fn load<'a>(desc: &ImageDescriptor, context: &LoadContext) -> Result<Image<'a>, UnwindError> {
+    #[cfg(target_os = "linux")]
+    if let Some(data) = raw_image::vdso(desc.start_avma, desc.length) {
+        return build(desc, &object::File::parse(data)?, Vec::new(), context);
+    }
+    #[cfg(target_os = "linux")]
+    let mapped = raw_image::deleted_mapping(&context.maps, desc.start_avma);
+    #[cfg(not(target_os = "linux"))]
+    let mapped = None::<std::path::PathBuf>;
+    let path = mapped.as_deref().unwrap_or(desc.name.as_ref());
+    let (object, mmap) = raw_image::keep_mapped(raw_image::load(path)?);
+    build(desc, &object, vec![mmap], context)
+}
+
+/// Load the image of an object file held in memory rather than read from the file system, e.g.
+/// one embedded in the program, whose segments are loaded at `bias` plus their SVMAs. Nothing
+/// is copied: the sections borrow from `data`.
+pub fn load_bytes<'a>(
+    name: &str,
+    data: &'static [u8],
+    bias: usize,
+) -> Result<Image<'a>, UnwindError> {
+    let object = object::File::parse(data)?;
+    let segments: Vec<_> = object
+        .segments()
+        .filter(|x| x.size() != 0)
+        .map(|x| x.address() as usize + bias..(x.address() + x.size()) as usize + bias)
+        .collect();
+    let start_avma = segments.iter().map(|x| x.start).min();
+    let end_avma = segments.iter().map(|x| x.end).max();
+    let (start_avma, end_avma) = start_avma
+        .zip(end_avma)
+        .ok_or(UnwindError::NotSupported("image without loadable segments"))?;
+    let desc = ImageDescriptor {
+        name: name.into(),
+        bias,
+        start_avma,
+        length: end_avma - start_avma,
+        segments,
+    };
+    build(&desc, &object, Vec::new(), &LoadContext::default())
+}
+
+/// The GNU build ID of the object file at `path`, if it has one.
+#[cfg(feature = "std")]
+pub(crate) fn build_id_of<P: AsRef<std::path::Path>>(
+    path: P,
+) -> Result<Option<Vec<u8>>, UnwindError> {
+    let (object, _mmap) = raw_image::keep_mapped(raw_image::load(path)?);
+    let build_id = object.build_id()?.map(<[u8]>::to_vec);
+    Ok(build_id)
+}
+
+/// Build the image of `object`, whose sections stay valid as long as `mappings` are kept.
+#[cfg_attr(not(feature = "std"), allow(unused_mut))]
+fn build<'a>(
+    desc: &ImageDescriptor,
+    object: &object::File<'static>,
+    mut mappings: Vec<Mmap>,
+    context: &LoadContext,
+) -> Result<Image<'a>, UnwindError> {
+    let ba = base_addresses::load(object);
+    let symbol_map = symbol_map::load(object);
+    let sections = object
+        .sections()
+        .filter(|x| x.kind() == SectionKind::Text && x.size() != 0)
+        .filter_map(|x| {
+            let name = x.name().ok()?;
+            // Code without a symbol in `.text` is better told apart by its offset.
+            (name != ".text").then(|| (x.address()..x.address() + x.size(), format!("<{}>", name)))
+        })
+        .collect();
+
+    let code = object
+        .sections()
+        .filter(|x| x.kind() == SectionKind::Text && x.size() != 0)
+        .filter_map(|x| Some((x.address()..x.address() + x.size(), x.data().ok()?.into())))
+        .collect();
+
+    let file_segments = object
+        .segments()
+        .map(|x| {
+            let (offset, size) = x.file_range();
+            (offset, x.address()..x.address() + size)
+        })
+        .collect();
+
+    let build_id = debug_info::identity(object);
+    let cached_functions = build_id
+        .as_ref()
+        .and_then(|x| context.symbol_cache.get(x))
+        .cloned();
+    #[cfg(feature = "std")]
+    let mut mismatched_debug_files = Vec::new();
+    // Cached functions stand in for the DWARF, so it is not even looked for.
+    let dbg_info = match cached_functions {
+        Some(_) => Default::default(),
+        #[cfg(feature = "std")]
+        None => debug_info::load(
+            &desc.name,
+            object,
+            &mut mappings,
+            &mut mismatched_debug_files,
+        ),
+        #[cfg(not(feature = "std"))]
+        None => debug_info::load(object),
+    };
+    let endian = if object.is_little_endian() {
+        RunTimeEndian::Little
+    } else {
+        RunTimeEndian::Big
+    };
+
+    // Follow the class of the image rather than the running process, whose pointer
+    // width may differ.
+    let address_size = if object.is_64() { 8 } else { 4 };
+    let tables = unwind_tables(object, &ba, endian, address_size);
+
+    #[cfg(target_arch = "arm")]
+    let exidx = section_with_address(object, ".ARM.exidx")
+        .map(|exidx| exidx::ExIdx::new(exidx, section_with_address(object, ".ARM.extab"), endian));
+
+    Ok(Image {
+        #[cfg(feature = "std")]
+        filename: desc.name.to_string_lossy().to_string(),
+        #[cfg(not(feature = "std"))]
+        filename: desc.name.clone(),
+        base_addresses: ba,
+        bias: desc.bias,
+        start_avma: desc.start_avma,
+        length: desc.length,
+        segments: desc.segments.clone(),
+        file_segments,
+        symbol_map,
+        sections,
+        code,
+        dbg_info,
+        #[cfg(feature = "std")]
+        mismatched_debug_files,
+        line_cache: serialized::Serialized::new(line_info::LineCache::new(&desc.name, endian)),
+        #[cfg(feature = "std")]
+        plt: OnceLock::new(),
+        fde_overlaps: OnceLock::new(),
+        index_consistent: OnceLock::new(),
+        eh_frame_section: tables.eh_frame,
+        eh_frame_hdr_section: tables.eh_frame_hdr,
+        debug_frame_section: tables.debug_frame,
+        #[cfg(target_arch = "arm")]
+        exidx,
+        endian,
+        address_size,
+        build_id,
+        cached_functions,
+        _mappings: mappings,
+    })
+}
+
+/// The unwind tables of an object file, borrowed from its mapping.
+struct UnwindTables<'a> {
+    eh_frame: (SectionData, gimli::EhFrame<ImageReader<'a>>),
+    eh_frame_hdr: Option<(SectionData, ParsedEhFrameHdr<ImageReader<'a>>)>,
+    debug_frame: Option<(SectionData, gimli::DebugFrame<ImageReader<'a>>)>,
+}
+
+/// Load the unwind tables of `object`, whose sections lie at the addresses in `ba`. Missing
+/// tables are left empty.
+fn unwind_tables<'a>(
+    object: &object::File<'static>,
+    ba: &gimli::BaseAddresses,
+    endian: RunTimeEndian,
+    address_size: u8,
+) -> UnwindTables<'a> {
+    let eh_frame_hdr_section = object.section_by_name(".eh_frame_hdr").and_then(|x| {
+        let data = x.uncompressed_data().ok()?;
+        // `pcrel` and `datarel` pointers of the header are relative to the header itself.
+        let bases = ba.clone().set_eh_frame_hdr(x.address());
+        let slice: &'a [u8] = unsafe { core::slice::from_raw_parts(data.as_ptr(), data.len()) };
+        let hdr = gimli::EhFrameHdr::new(slice, endian)
+            .parse(&bases, address_size)
+            .ok()?;
+        // The table may be omitted, leaving nothing to search.
+        hdr.table()?;
+        Some((data, hdr))
     });
 
-    vec.sort_by_key(|x| std::cmp::Reverse(x.start_avma));
-    vec
+    // Compressed sections are inflated, so the data always holds the plain records.
+    let eh_frame_data = raw_image::section_data(object, ".eh_frame").unwrap_or_default();
+
+    let debug_frame_section = raw_image::section_data(object, ".debug_frame").map(|data| {
+        let slice: &'a [u8] = unsafe { core::slice::from_raw_parts(data.as_ptr(), data.len()) };
+        let mut debug_frame = gimli::DebugFrame::new(slice, endian);
+        debug_frame.set_address_size(address_size);
+        (data, debug_frame)
+    });
+
+    let eh_frame = unsafe {
+        let slice: &'a [u8] =
+            core::slice::from_raw_parts(eh_frame_data.as_ptr(), eh_frame_data.len());
+        let mut eh_frame = gimli::EhFrame::new(slice, endian);
+        eh_frame.set_address_size(address_size);
+        eh_frame
+    };
+
+    UnwindTables {
+        eh_frame: (eh_frame_data, eh_frame),
+        eh_frame_hdr: eh_frame_hdr_section,
+        debug_frame: debug_frame_section,
+    }
+}
+
+#[cfg(target_arch = "arm")]
+fn section_with_address(object: &object::File, name: &str) -> Option<(Vec<u8>, u64)> {
+    let section = object.section_by_name(name)?;
+    let data = section.uncompressed_data().ok()?;
+    Some((data.to_vec(), section.address()))
+}
+
+#[cfg(all(test, feature = "std", not(target_arch = "wasm32")))]
+mod tests {
+    use super::{load, try_load, ImageDescriptor, LoadContext};
+    use crate::{GlobalContext, UnwindError};
+    use gimli::Endianity;
+    use object::Object;
+
+    #[test]
+    fn it_fails_without_readable_images() {
+        assert!(matches!(
+            try_load(&[], &LoadContext::default()),
+            Err(UnwindError::UnwindLogicalError(_))
+        ));
+        let missing = ImageDescriptor {
+            name: "/nonexistent/libunwinder-missing.so".into(),
+            bias: 0,
+            start_avma: 0x1000,
+            length: 0x1000,
+            segments: Vec::new(),
+        };
+        assert!(matches!(
+            try_load(&[missing], &LoadContext::default()),
+            Err(UnwindError::IOError(_))
+        ));
+        assert!(GlobalContext::try_new().is_ok());
+    }
+
+    #[test]
+    fn it_reports_address_size() {
+        let g = GlobalContext::new();
+        let image = g
+            .find_image(it_reports_address_size as *const () as usize)
+            .unwrap();
+        let data = std::fs::read(&image.filename).unwrap();
+        let object = object::File::parse(&*data).unwrap();
+        assert_eq!(image.address_size(), if object.is_64() { 8 } else { 4 });
+        assert_eq!(image.address_size() as usize, std::mem::size_of::<usize>());
+        assert_eq!(image.endian().is_big_endian(), !object.is_little_endian());
+    }
+
+    #[test]
+    fn it_parses_foreign_width_images() {
+        use object::{ObjectSection, ObjectSymbol};
+        let source = "int narrow(int x) { return x + 1; }\n";
+        let fixture = match crate::tests::Fixture::build("narrow", source, &["-m32", "-nostdlib"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let path = &fixture.path;
+        let data = std::fs::read(path).unwrap();
+        let object = object::File::parse(&*data).unwrap();
+        assert!(!object.is_64());
+        let svma = object
+            .symbols()
+            .find(|x| x.name() == Ok("narrow"))
+            .unwrap()
+            .address();
+        let desc = ImageDescriptor {
+            name: path.into(),
+            bias: 0,
+            start_avma: 0x1000,
+            length: object.section_by_name(".text").unwrap().size() as usize,
+            segments: Vec::new(),
+        };
+        let image = load(&desc, &LoadContext::new(&Default::default())).unwrap();
+        assert_eq!(image.address_size(), 4);
+        let (_, hdr) = image.eh_frame_hdr_section.as_ref().unwrap();
+        let table = hdr.table().unwrap();
+        assert!(table.lookup(svma, &image.base_addresses).is_ok());
+        assert!(image.find_fde(svma).unwrap().contains(svma));
+    }
+
+    #[test]
+    fn it_handles_eh_frame_hdr_encodings() {
+        use gimli::{constants, Pointer};
+        use object::{ObjectSection, ObjectSymbol};
+        let source = "int hdr_enc(int x) { return x * 7; }\n";
+        let fixture = match crate::tests::Fixture::build("hdr_enc", source, &["-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let path = &fixture.path;
+        let mut data = std::fs::read(path).unwrap();
+        let object = object::File::parse(&*data).unwrap();
+        let svma = object
+            .symbols()
+            .find(|x| x.name() == Ok("hdr_enc"))
+            .unwrap()
+            .address();
+        let eh_frame = object.section_by_name(".eh_frame").unwrap().address();
+        let hdr_section = object.section_by_name(".eh_frame_hdr").unwrap();
+        let (offset, _) = hdr_section.file_range().unwrap();
+        let desc = ImageDescriptor {
+            name: path.clone().into(),
+            bias: 0,
+            start_avma: 0x1000,
+            length: 0x1000,
+            segments: Vec::new(),
+        };
+
+        // The pointer to `.eh_frame` is relative to the header.
+        assert_eq!(
+            data[offset as usize + 1],
+            constants::DW_EH_PE_pcrel.0 | constants::DW_EH_PE_sdata4.0
+        );
+        let image = load(&desc, &LoadContext::new(&Default::default())).unwrap();
+        let (_, hdr) = image.eh_frame_hdr_section.as_ref().unwrap();
+        assert_eq!(hdr.eh_frame_ptr(), Pointer::Direct(eh_frame));
+        assert!(image.find_fde(svma).unwrap().contains(svma));
+
+        // Without a table, `.eh_frame` is scanned.
+        data[offset as usize + 2] = constants::DW_EH_PE_omit.0;
+        data[offset as usize + 3] = constants::DW_EH_PE_omit.0;
+        std::fs::write(path, data).unwrap();
+        let image = load(&desc, &LoadContext::new(&Default::default())).unwrap();
+        assert!(image.eh_frame_hdr_section.is_none());
+        assert!(image.find_fde(svma).unwrap().contains(svma));
+    }
+
+    #[test]
+    fn it_trusts_misses_of_consistent_eh_frame_hdr() {
+        use object::ObjectSymbol;
+        let source = "int hdr_miss(int x) { return x * 3; }\n";
+        let fixture = match crate::tests::Fixture::build("hdr_miss", source, &["-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let data: &'static [u8] = std::fs::read(&fixture.path).unwrap().leak();
+        let image = super::load_bytes("hdr_miss", data, 0).unwrap();
+        assert!(image.eh_frame_hdr_section.is_some());
+        let svma = object::File::parse(data)
+            .unwrap()
+            .symbols()
+            .find(|x| x.name() == Ok("hdr_miss"))
+            .unwrap()
+            .address();
+        assert!(image.find_fde(svma).unwrap().contains(svma));
+        assert!(image.index_consistent.get().is_none());
+
+        // A miss is answered by the index once it is known to agree with `.eh_frame`.
+        assert!(matches!(
+            image.find_fde(u64::MAX - 1),
+            Err(gimli::Error::NoUnwindInfoForAddress)
+        ));
+        assert_eq!(image.index_consistent.get(), Some(&true));
+    }
+
+    #[test]
+    fn it_prefers_tightest_of_overlapping_fdes() {
+        use gimli::write::{
+            Address, CallFrameInstruction, CommonInformationEntry, EndianVec,
+            FrameDescriptionEntry, FrameTable,
+        };
+        use gimli::{constants, CfaRule, RunTimeEndian, UnwindSection, X86_64};
+        use std::borrow::Cow;
+
+        let source = "int overlap(int x) { return x * 5; }\n";
+        let fixture = match crate::tests::Fixture::build("overlap", source, &["-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let path = &fixture.path;
+        let data: &'static [u8] = std::fs::read(path).unwrap().leak();
+        let mut image = super::load_bytes("overlap", data, 0).unwrap();
+        let endian = RunTimeEndian::Little;
+        if image.endian != endian {
+            return;
+        }
+
+        // A loose FDE listed first, and a tight one at the same start with another CFA.
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 1,
+            address_size: 8,
+        };
+        let mut table = FrameTable::default();
+        let mut cie = CommonInformationEntry::new(encoding, 1, -8, X86_64::RA);
+        cie.add_instruction(CallFrameInstruction::Cfa(X86_64::RSP, 8));
+        let cie = table.add_cie(cie);
+        for (len, cfa_offset) in [(0xc0, 64), (0x20, 16)] {
+            let mut fde = FrameDescriptionEntry::new(Address::Constant(0x1040), len);
+            fde.add_instruction(0, CallFrameInstruction::CfaOffset(cfa_offset));
+            table.add_fde(cie, fde);
+        }
+        let mut eh_frame = gimli::write::EhFrame(EndianVec::new(endian));
+        table.write_eh_frame(&mut eh_frame).unwrap();
+        let bytes: &'static [u8] = eh_frame.0.into_vec().leak();
+        let mut section = gimli::EhFrame::new(bytes, endian);
+        section.set_address_size(8);
+        image.eh_frame_section = (Cow::Borrowed(bytes), section);
+        image.eh_frame_hdr_section = None;
+        image.debug_frame_section = None;
+
+        let cfa_offset = |image: &super::Image| {
+            let mut ctx = gimli::UnwindContext::new();
+            let (row, _) = image.unwind_info_for_address(&mut ctx, 0x1050).unwrap();
+            match row.cfa() {
+                CfaRule::RegisterAndOffset { offset, .. } => *offset,
+                CfaRule::Expression(_) => unreachable!(),
+            }
+        };
+        assert_eq!(cfa_offset(&image), 16);
+        assert_eq!(image.fde_range(0x1050), Some(0x1040..0x1060));
+        assert_eq!(image.fde_range(0x1070), Some(0x1040..0x1100));
+
+        // An index listing the tight FDE first, so that its search lands on the loose one.
+        let eh_frame_address = image.base_addresses.eh_frame.section.unwrap();
+        let mut offsets = Vec::new();
+        let mut entries = section.entries(&image.base_addresses);
+        while let Some(entry) = entries.next().unwrap() {
+            if let gimli::CieOrFde::Fde(partial) = entry {
+                let fde = partial.parse(gimli::EhFrame::cie_from_offset).unwrap();
+                offsets.push((fde.len(), fde.offset() as u64));
+            }
+        }
+        offsets.sort();
+        let mut hdr = vec![
+            1,
+            constants::DW_EH_PE_udata8.0,
+            constants::DW_EH_PE_udata4.0,
+            constants::DW_EH_PE_udata8.0,
+        ];
+        hdr.extend(eh_frame_address.to_le_bytes());
+        hdr.extend(2u32.to_le_bytes());
+        for (_, offset) in offsets {
+            hdr.extend(0x1040u64.to_le_bytes());
+            hdr.extend((eh_frame_address + offset).to_le_bytes());
+        }
+        let hdr: &'static [u8] = hdr.leak();
+        let parsed = gimli::EhFrameHdr::new(hdr, endian)
+            .parse(&image.base_addresses, 8)
+            .unwrap();
+        image.eh_frame_hdr_section = Some((Cow::Borrowed(hdr), parsed));
+        assert_eq!(cfa_offset(&image), 16);
+        assert_eq!(image.fde_range(0x1070), Some(0x1040..0x1100));
+    }
+
+    #[test]
+    fn it_skips_mismatched_debug_files() {
+        let split = |name: &str, source: &str| {
+            let fixture = crate::tests::Fixture::build(name, source, &["-g", "-O0"])?;
+            crate::tests::Fixture::split_debug_info(&fixture.path).then_some(fixture)
+        };
+        let (fixture, other) = match (
+            split("matched", "int matched(int x) { return x - 1; }\n"),
+            split("other_build", "int matched(int x) { return x + 2; }\n"),
+        ) {
+            (Some(fixture), Some(other)) => (fixture, other),
+            _ => return,
+        };
+        let path = &fixture.path;
+        let load_image = || {
+            let desc = ImageDescriptor {
+                name: path.clone().into(),
+                bias: 0,
+                start_avma: 0x1000,
+                length: 0x1000,
+                segments: Vec::new(),
+            };
+            load(&desc, &LoadContext::new(&Default::default())).unwrap()
+        };
+        let debug = path.with_extension("debug");
+        let image = load_image();
+        if image.build_id().is_none() {
+            return;
+        }
+        assert!(image.dwarf().units().next().unwrap().is_some());
+        assert!(image.mismatched_debug_files().is_empty());
+        assert!(image.matches_debug_file(&debug).unwrap());
+
+        // The debug file of another build, left where the debug link points.
+        std::fs::copy(other.path.with_extension("debug"), &debug).unwrap();
+        let image = load_image();
+        assert!(!image.matches_debug_file(&debug).unwrap());
+        assert!(image.dwarf().units().next().unwrap().is_none());
+        assert_eq!(image.mismatched_debug_files(), [debug]);
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "arm"))]
+    fn it_evaluates_with_separate_debug_info() {
+        use crate::cursor::state::{CursorState, NativeState};
+        use crate::cursor::LocalTarget;
+        use gimli::{EndianSlice, Expression};
+        let source = "int separate(int x) { return x - 1; }\n";
+        let fixture = match crate::tests::Fixture::build("separate", source, &["-g", "-O0"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let path = &fixture.path;
+        if !crate::tests::Fixture::split_debug_info(path) {
+            return;
+        }
+        let data = std::fs::read(path).unwrap();
+        assert!(!object::File::parse(&*data).unwrap().has_debug_symbols());
+        let desc = ImageDescriptor {
+            name: path.into(),
+            bias: 0,
+            start_avma: 0x1000,
+            length: 0x1000,
+            segments: Vec::new(),
+        };
+        let image = load(&desc, &LoadContext::new(&Default::default())).unwrap();
+        let dwarf = image.dwarf();
+        let header = dwarf.units().next().unwrap().unwrap();
+        let unit = dwarf.unit(header).unwrap();
+        let mut entries = unit.entries();
+        let offset = loop {
+            let (_, entry) = entries.next_dfs().unwrap().unwrap();
+            if entry.tag() == gimli::DW_TAG_base_type {
+                break entry.offset();
+            }
+        };
+
+        // DW_OP_lit1; DW_OP_convert <int>; DW_OP_stack_value
+        let mut bytes = vec![gimli::DW_OP_lit1.0, gimli::DW_OP_convert.0];
+        gimli::leb128::write::unsigned(&mut bytes, offset.0 as u64).unwrap();
+        bytes.push(gimli::DW_OP_stack_value.0);
+        let expr = Expression(EndianSlice::new(&bytes, image.endian()));
+        let state = NativeState::from_pc_sp(0, 0);
+        assert_eq!(
+            state.eval_in_unit(&expr, &unit, 0, &LocalTarget).unwrap(),
+            1
+        );
+    }
+
+    /// Wrap `data` in a zlib stream of stored blocks.
+    fn zlib_stored(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x78, 0x01];
+        let mut chunks = data.chunks(0xffff).peekable();
+        while let Some(chunk) = chunks.next() {
+            out.push(chunks.peek().is_none() as u8);
+            out.extend((chunk.len() as u16).to_le_bytes());
+            out.extend((!(chunk.len() as u16)).to_le_bytes());
+            out.extend(chunk);
+        }
+        let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), x| {
+            let a = (a + *x as u32) % 65521;
+            (a, (b + a) % 65521)
+        });
+        out.extend(((b << 16) | a).to_be_bytes());
+        out
+    }
+
+    #[test]
+    fn it_reads_compressed_eh_frame() {
+        use object::{ObjectSection, ObjectSymbol};
+        let source = "int zipped(int x) { return x * 9; }\n";
+        let fixture = match crate::tests::Fixture::build("zipped", source, &["-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let path = &fixture.path;
+        let mut data = std::fs::read(path).unwrap();
+        let object = object::File::parse(&*data).unwrap();
+        if !object.is_64() || !object.is_little_endian() {
+            return;
+        }
+        let svma = object
+            .symbols()
+            .find(|x| x.name() == Ok("zipped"))
+            .unwrap()
+            .address();
+        let section = object.section_by_name(".eh_frame").unwrap();
+        let plain = section.data().unwrap().to_vec();
+        let index = section.index().0;
+
+        // Append the compressed section and point its header at it.
+        let mut compressed = Vec::new();
+        compressed.extend(1u32.to_le_bytes()); // ELFCOMPRESS_ZLIB
+        compressed.extend(0u32.to_le_bytes());
+        compressed.extend((plain.len() as u64).to_le_bytes());
+        compressed.extend(8u64.to_le_bytes());
+        compressed.extend(zlib_stored(&plain));
+        let offset = data.len() as u64;
+        let word =
+            |data: &[u8], at: usize| u64::from_le_bytes(data[at..at + 8].try_into().unwrap());
+        let shoff = word(&data, 0x28) as usize;
+        let shentsize = u16::from_le_bytes([data[0x3a], data[0x3b]]) as usize;
+        let header = shoff + index * shentsize;
+        let flags = word(&data, header + 8) | object::elf::SHF_COMPRESSED as u64;
+        data[header + 8..header + 16].copy_from_slice(&flags.to_le_bytes());
+        data[header + 24..header + 32].copy_from_slice(&offset.to_le_bytes());
+        data[header + 32..header + 40].copy_from_slice(&(compressed.len() as u64).to_le_bytes());
+        data.extend(compressed);
+        std::fs::write(path, &data).unwrap();
+
+        let object = object::File::parse(&*data).unwrap();
+        let section = object.section_by_name(".eh_frame").unwrap();
+        assert_ne!(section.data().unwrap(), plain.as_slice());
+        let desc = ImageDescriptor {
+            name: path.into(),
+            bias: 0,
+            start_avma: 0x1000,
+            length: 0x1000,
+            segments: Vec::new(),
+        };
+        let image = load(&desc, &LoadContext::new(&Default::default())).unwrap();
+        assert_eq!(image.eh_frame_section.0, plain);
+        assert!(image.find_fde(svma).unwrap().contains(svma));
+    }
+
+    #[test]
+    fn it_finds_unit_of_address() {
+        use crate::cursor::state::{CursorState, NativeState};
+        use crate::cursor::LocalTarget;
+        use gimli::{EndianSlice, Expression};
+        let second = std::env::temp_dir().join(format!("unwinder-second-{}.c", std::process::id()));
+        std::fs::write(&second, "long unit_second(long x) { return x * 2; }\n").unwrap();
+        let source = "int unit_first(int x) { return x + 1; }\n";
+        let flags = ["-g", "-O0", second.to_str().unwrap()];
+        let fixture = crate::tests::Fixture::load("units", source, &flags);
+        std::fs::remove_file(&second).unwrap();
+        let fixture = match fixture {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let g = crate::GlobalContext::new();
+        let image = fixture.image(&g);
+        let unit_name = |unit: &gimli::Unit<super::ImageReader>| {
+            unit.name.unwrap().to_string_lossy().into_owned()
+        };
+        let svma = |name| (fixture.symbol(name) - image.bias) as u64;
+        let first = image.find_unit(svma("unit_first")).unwrap();
+        assert!(unit_name(&first).ends_with("units.c"));
+        let unit = image.find_unit(svma("unit_second")).unwrap();
+        assert!(unit_name(&unit).ends_with(".c"));
+        assert_ne!(unit_name(&unit), unit_name(&first));
+
+        let mut entries = unit.entries();
+        let offset = loop {
+            let (_, entry) = entries.next_dfs().unwrap().unwrap();
+            if entry.tag() == gimli::DW_TAG_base_type {
+                break entry.offset();
+            }
+        };
+        // DW_OP_lit1; DW_OP_convert <long>; DW_OP_stack_value
+        let mut bytes = vec![gimli::DW_OP_lit1.0, gimli::DW_OP_convert.0];
+        gimli::leb128::write::unsigned(&mut bytes, offset.0 as u64).unwrap();
+        bytes.push(gimli::DW_OP_stack_value.0);
+        let expr = Expression(EndianSlice::new(&bytes, image.endian()));
+        let state = NativeState::from_pc_sp(0, 0);
+        assert_eq!(
+            state.eval_in_unit(&expr, &unit, 0, &LocalTarget).unwrap(),
+            1
+        );
+        std::fs::remove_file(second).ok();
+    }
+
+    #[test]
+    fn it_lists_functions() {
+        let g = GlobalContext::new();
+        let address = it_lists_functions as *const () as usize;
+        let image = g.find_image(address).unwrap();
+        let svma = (address - image.bias) as u64;
+        let (name, _, size) = image.functions().find(|x| x.1 == svma).unwrap();
+        assert!(rustc_demangle::demangle(name)
+            .to_string()
+            .contains("it_lists_functions"));
+        assert!(size > 0);
+
+        // Without debug info, the symbol table is listed.
+        let source = "int listed(int x) { return x - 4; }\n";
+        let fixture = match crate::tests::Fixture::load("listed", source, &["-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let g = GlobalContext::new();
+        let image = fixture.image(&g);
+        let svma = (fixture.symbol("listed") - image.bias) as u64;
+        let listed = image.functions().find(|x| x.0 == "listed").unwrap();
+        assert_eq!(listed.1, svma);
+        assert_eq!(
+            Some(listed.1..listed.1 + listed.2),
+            image.find_function_range(svma)
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn it_loads_deleted_images_from_mapping() {
+        let source = "int deleted(int x) { return x * 5; }\n";
+        let fixture = match crate::tests::Fixture::load("deleted", source, &["-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        std::fs::remove_file(&fixture.path).unwrap();
+        let g = GlobalContext::new();
+        let image = fixture.image(&g);
+        let svma = (fixture.symbol("deleted") - image.bias) as u64;
+        assert!(image.find_fde(svma).unwrap().contains(svma));
+        assert!(image.functions().any(|x| x.0 == "deleted" && x.1 == svma));
+    }
+
+    #[test]
+    fn it_borrows_sections_from_mapping() {
+        use std::borrow::Cow;
+        let g = GlobalContext::new();
+        let image = g
+            .find_image(it_borrows_sections_from_mapping as *const () as usize)
+            .unwrap();
+        assert!(matches!(image.eh_frame_section.0, Cow::Borrowed(_)));
+        let mut sections = Vec::new();
+        image.dbg_info.borrow(|x| sections.push(x));
+        assert!(sections.iter().any(|x| !x.is_empty()));
+        assert!(sections
+            .iter()
+            .all(|x| x.is_empty() || matches!(x, Cow::Borrowed(_))));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn it_enumerates_images_from_proc_maps() {
+        let options = super::LoadOptions {
+            from_proc_maps: true,
+            ..Default::default()
+        };
+        let g = GlobalContext::new_with_options(&options);
+        let pc = it_enumerates_images_from_proc_maps as *const () as usize;
+        let image = g.find_image(pc).unwrap();
+        let exe = std::env::current_exe().unwrap();
+        assert_eq!(std::path::Path::new(&image.filename), exe);
+        let svma = (pc - image.bias) as u64;
+        assert!(image.find_fde(svma).unwrap().contains(svma));
+        let linker = GlobalContext::new();
+        assert_eq!(linker.find_image(pc).unwrap().bias, image.bias);
+    }
+
+    /// Test binaries for musl are linked statically, without a dynamic linker to list images.
+    #[cfg(all(target_os = "linux", target_env = "musl"))]
+    #[test]
+    fn it_loads_static_executables() {
+        let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+        assert!(!maps.contains(".so"), "not a static executable");
+        let pc = it_loads_static_executables as *const () as usize;
+        let exe = std::env::current_exe().unwrap();
+        for from_proc_maps in [false, true] {
+            let options = super::LoadOptions {
+                from_proc_maps,
+                ..Default::default()
+            };
+            let g = GlobalContext::new_with_options(&options);
+            let image = g.find_image(pc).unwrap();
+            // The main executable may be named `/proc/self/exe`.
+            assert_eq!(std::fs::canonicalize(&image.filename).unwrap(), exe);
+            let svma = (pc - image.bias) as u64;
+            assert!(image.find_fde(svma).unwrap().contains(svma));
+        }
+    }
+
+    #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+    #[test]
+    fn it_rejects_malformed_elf_headers() {
+        use crate::image::raw_image::elf_segments;
+        use std::mem::{size_of, MaybeUninit};
+
+        let mut header = unsafe { MaybeUninit::<libc::Elf64_Ehdr>::zeroed().assume_init() };
+        header.e_ident[..5].copy_from_slice(b"\x7fELF\x02");
+        header.e_phentsize = size_of::<libc::Elf64_Phdr>() as u16;
+        header.e_phnum = 1;
+        // The program headers point far past the header, into unmapped memory.
+        header.e_phoff = 1 << 46;
+        let segments = |header: &libc::Elf64_Ehdr| elf_segments(header as *const _ as usize);
+        assert!(segments(&header).is_none());
+        header.e_phoff = u64::MAX;
+        assert!(segments(&header).is_none());
+        // A header of the other class or with program headers of another size.
+        header.e_phoff = 0;
+        header.e_ident[4] = 1;
+        assert!(segments(&header).is_none());
+        header.e_ident[4] = 2;
+        header.e_phentsize += 1;
+        assert!(segments(&header).is_none());
+    }
+
+    #[test]
+    fn it_translates_file_offsets() {
+        use object::ObjectSection;
+        let g = GlobalContext::new();
+        let image = g
+            .find_image(it_translates_file_offsets as *const () as usize)
+            .unwrap();
+        let data = std::fs::read(&image.filename).unwrap();
+        let object = object::File::parse(data.as_slice()).unwrap();
+        let text = object.section_by_name(".text").unwrap();
+        let (offset, _) = text.file_range().unwrap();
+        assert_eq!(
+            image.file_offset_to_svma(offset + 4),
+            Some(text.address() + 4)
+        );
+        assert_eq!(
+            image.svma_to_file_offset(text.address() + 4),
+            Some(offset + 4)
+        );
+        assert_eq!(image.file_offset_to_svma(data.len() as u64), None);
+    }
+
+    #[test]
+    fn it_translates_avmas_to_svmas() {
+        let g = GlobalContext::new();
+        let address = it_translates_avmas_to_svmas as *const () as usize;
+        let image = g.find_image(address).unwrap();
+        let svma = image.avma_to_svma(address);
+        assert_eq!(image.svma_to_avma(svma), address);
+        let range = image.find_function_range(svma).unwrap();
+        assert_eq!(range.start, svma);
+        assert!(image.fde_range(svma).unwrap().contains(&svma));
+    }
+
+    #[test]
+    fn it_lists_fdes() {
+        let g = GlobalContext::new();
+        let address = it_lists_fdes as *const () as usize;
+        let image = g.find_image(address).unwrap();
+        let svma = (address - image.bias) as u64;
+        let fdes: Vec<_> = image.fdes().collect();
+        assert!(!fdes.is_empty());
+        let fde = fdes.iter().find(|x| x.range().contains(&svma)).unwrap();
+        assert_eq!(image.fde_range(svma), Some(fde.range()));
+        assert!(fdes.iter().all(|x| x.cie_offset < x.offset));
+    }
+
+    #[test]
+    fn it_reads_code_bytes() {
+        let g = GlobalContext::new();
+        let address = it_reads_code_bytes as *const () as usize;
+        let image = g.find_image(address).unwrap();
+        let svma = (address - image.bias) as u64;
+        let code = image.code_bytes(svma, 16).unwrap();
+        assert_eq!(code.len(), 16);
+        let loaded = unsafe { std::slice::from_raw_parts(address as *const u8, 16) };
+        assert_eq!(code, loaded);
+
+        // Reads stop at the end of the section.
+        let range = image.find_function_range(svma).unwrap();
+        let (section, _) = image.code.iter().find(|x| x.0.contains(&svma)).unwrap();
+        let rest = image.code_bytes(section.end - 1, 16).unwrap();
+        assert_eq!(rest.len(), 1);
+        assert!(image
+            .code_bytes(svma, (range.end - range.start) as usize)
+            .is_some());
+        assert!(image.code_bytes(u64::MAX, 1).is_none());
+    }
+
+    #[test]
+    fn it_loads_images_without_text_section() {
+        let source = "int no_text(int x) { return x * 7; }\n";
+        let fixture = match crate::tests::Fixture::build("no_text", source, &["-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let path = &fixture.path;
+        let renamed = crate::tests::Fixture::run(
+            std::process::Command::new("objcopy")
+                .args(["--rename-section", ".text=.code"])
+                .arg(path),
+        );
+        if !renamed {
+            return;
+        }
+        let fixture = fixture.open();
+        let g = GlobalContext::new();
+        let image = fixture.image(&g);
+        let avma = fixture.symbol("no_text");
+        let svma = (avma - image.bias) as u64;
+        assert!(image.find_fde(svma).unwrap().contains(svma));
+        assert!(g.has_unwind_info(avma));
+    }
+
+    #[test]
+    fn it_excludes_gaps_between_segments() {
+        let g = GlobalContext::new();
+        let image = g
+            .find_image(it_excludes_gaps_between_segments as *const () as usize)
+            .unwrap();
+        let mut segments = image.segments.clone();
+        segments.sort_by_key(|x| x.start);
+        let gap = segments
+            .windows(2)
+            .map(|x| x[0].end..x[1].start)
+            .find(|x| !x.is_empty())
+            .expect("no gap between the segments of the test binary");
+        assert!(image.start_avma <= gap.start && gap.end <= image.start_avma + image.length);
+        assert!(!image.has(gap.start));
+        assert!(image.has(gap.end));
+        assert!(g.find_image(gap.start).is_none());
+    }
 }
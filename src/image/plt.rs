@@ -0,0 +1,130 @@
+//! Mapping of PLT stubs to the functions they jump to.
+use object::read::elf::{ElfFile64, FileHeader, SectionHeader};
+use object::{
+    Architecture, Endianness, File, Object, ObjectSymbol, ObjectSymbolTable, RelocationTarget,
+};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// The sections holding PLT stubs. With IBT, the stubs that are called live in `.plt.sec` and
+/// those in `.plt` only do the lazy binding.
+const PLT_SECTIONS: &[&str] = &[".plt", ".plt.sec", ".plt.got"];
+
+/// Decodes the GOT slot a stub at the given address jumps through.
+type SlotDecoder = fn(&[u8], u64) -> Option<u64>;
+
+#[derive(Debug)]
+pub struct PltEntry {
+    range: Range<u64>,
+    target: String,
+}
+
+/// Build the map from PLT stubs to the names of their targets. Each stub jumps through a GOT
+/// slot, whose dynamic relocation names the target symbol. The sections are split into stubs
+/// by their `sh_entsize`, and the slot is decoded from the stub, so the PLT header and the
+/// lazy binding stubs, which jump elsewhere, are left out. Only x86-64 and AArch64 stubs are
+/// understood.
+pub fn load(f: &File) -> Vec<PltEntry> {
+    let (elf, decode): (_, SlotDecoder) = match f {
+        File::Elf64(elf) if f.architecture() == Architecture::X86_64 => (elf, x86_64_slot),
+        File::Elf64(elf) if f.architecture() == Architecture::Aarch64 => (elf, aarch64_slot),
+        _ => return Vec::new(),
+    };
+    let symbols = match f.dynamic_symbol_table() {
+        Some(symbols) => symbols,
+        None => return Vec::new(),
+    };
+    let slots: HashMap<u64, &str> = f
+        .dynamic_relocations()
+        .into_iter()
+        .flatten()
+        .filter_map(|(offset, relocation)| match relocation.target() {
+            RelocationTarget::Symbol(index) => {
+                let name = symbols.symbol_by_index(index).ok()?.name().ok()?;
+                Some((offset, name))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let mut entries = Vec::new();
+    for (address, data, entry_size) in PLT_SECTIONS.iter().filter_map(|x| plt_section(elf, x)) {
+        for (i, entry) in data.chunks(entry_size).enumerate() {
+            let start = address + (i * entry_size) as u64;
+            if let Some(target) = decode(entry, start).and_then(|x| slots.get(&x)) {
+                entries.push(PltEntry {
+                    range: start..start + entry.len() as u64,
+                    target: target.to_string(),
+                });
+            }
+        }
+    }
+    entries.sort_by_key(|x| x.range.start);
+    entries
+}
+
+/// The address and contents of the PLT section called `name`, and the size of its entries.
+fn plt_section<'a>(elf: &ElfFile64<'a, Endianness>, name: &str) -> Option<(u64, &'a [u8], usize)> {
+    let endian = elf.endian();
+    let sections = elf.raw_header().sections(endian, elf.data()).ok()?;
+    let (_, header) = sections.section_by_name(endian, name.as_bytes())?;
+    let data = header.data(endian, elf.data()).ok()?;
+    // Linkers that leave `sh_entsize` unset still align every stub.
+    let entry_size = match header.sh_entsize(endian) {
+        0 => header.sh_addralign(endian),
+        x => x,
+    } as usize;
+    (entry_size > 0).then_some((header.sh_addr(endian), data, entry_size))
+}
+
+/// Decode the GOT slot of an x86-64 stub at `start`, which jumps with `jmp *disp32(%rip)`,
+/// possibly behind `endbr64` or a `bnd` prefix.
+fn x86_64_slot(entry: &[u8], start: u64) -> Option<u64> {
+    let pos = entry.windows(6).position(|x| x[..2] == [0xff, 0x25])?;
+    let disp = i32::from_le_bytes(entry[pos + 2..pos + 6].try_into().ok()?);
+    Some((start + pos as u64 + 6).wrapping_add(disp as i64 as u64))
+}
+
+/// Decode the GOT slot of an AArch64 stub at `start`, which loads its target with
+/// `adrp x16, slot` and `ldr x17, [x16, #slot]`, possibly behind `bti c`.
+fn aarch64_slot(entry: &[u8], start: u64) -> Option<u64> {
+    let words: Vec<u32> = entry
+        .chunks_exact(4)
+        .map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+        .collect();
+    let pos = words
+        .windows(2)
+        .position(|x| x[0] & 0x9f00_001f == 0x9000_0010 && x[1] & 0xffc0_03ff == 0xf940_0211)?;
+    let (adrp, ldr) = (words[pos], words[pos + 1]);
+    let imm = ((adrp >> 3) & 0x1f_fffc) | ((adrp >> 29) & 0x3);
+    // Sign-extend the 21 bit page offset.
+    let pages = ((imm << 11) as i32 >> 11) as i64;
+    let pc = start + 4 * pos as u64;
+    let page = (pc & !0xfff).wrapping_add((pages << 12) as u64);
+    Some(page + ((ldr >> 10) & 0xfff) as u64 * 8)
+}
+
+/// Find the target of the PLT stub covering `svma`.
+pub fn lookup(entries: &[PltEntry], svma: u64) -> Option<&str> {
+    let i = entries.partition_point(|x| x.range.start <= svma);
+    entries
+        .get(i.checked_sub(1)?)
+        .filter(|x| x.range.contains(&svma))
+        .map(|x| x.target.as_str())
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn it_decodes_aarch64_stubs() {
+        // adrp x16, 0x11000; ldr x17, [x16, #0xfa8]; add x16, x16, #0xfa8; br x17
+        let words: [u32; 4] = [0xb000_0090, 0xf947_d611, 0x913e_a210, 0xd61f_0220];
+        let entry: Vec<u8> = words.iter().flat_map(|x| x.to_le_bytes()).collect();
+        assert_eq!(super::aarch64_slot(&entry, 0x540), Some(0x11fa8));
+        // With `bti c` in front, the pair is found one instruction later.
+        let bti = 0xd503_245fu32.to_le_bytes().into_iter();
+        let entry: Vec<u8> = bti.chain(entry).collect();
+        assert_eq!(super::aarch64_slot(&entry, 0x53c), Some(0x11fa8));
+        assert_eq!(super::aarch64_slot(&entry[4..8], 0x540), None);
+    }
+}
@@ -1,22 +1,278 @@
-use crate::UnwindError;
-use memmap::Mmap;
-use object::File as ObjFile;
-use std::fs::File;
-use std::mem::ManuallyDrop;
-use std::path::Path;
+use alloc::borrow::Cow;
+#[cfg(not(all(feature = "std", not(target_arch = "wasm32"))))]
+use alloc::vec::Vec;
+#[cfg(all(feature = "std", target_os = "linux"))]
+use core::ops::Range;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub use memmap::Mmap;
+use object::{Object, ObjectSection};
+#[cfg(feature = "std")]
+use {
+    crate::UnwindError,
+    object::read::macho::{FatArch, FatHeader},
+    object::{Architecture, File as ObjFile, FileKind},
+    std::fs::File,
+    std::mem::ManuallyDrop,
+    std::path::Path,
+};
 
+/// WebAssembly has no memory mapping, so files are read into memory instead. Without the `std`
+/// feature, no file is read at all and images only borrow from the bytes they are built from.
+#[cfg(not(all(feature = "std", not(target_arch = "wasm32"))))]
+pub type Mmap = Vec<u8>;
+
+#[cfg(feature = "std")]
 type RawImage<'a> = (object::File<'a>, ManuallyDrop<Mmap>, ManuallyDrop<File>);
 
+/// The data of a section, borrowed from the mapped file when it is stored uncompressed and
+/// inflated into an owned buffer otherwise. Borrowed data is only valid as long as the mapping
+/// it was read from is kept alive, so the `'static` lifetime must never leave the crate: an
+/// image keeps its sections in private fields next to the mappings they borrow from, and only
+/// lends them out for as long as it is borrowed itself.
+pub type SectionData = Cow<'static, [u8]>;
+
+#[cfg(all(feature = "std", target_arch = "x86_64"))]
+const HOST_ARCH: Architecture = Architecture::X86_64;
+#[cfg(all(feature = "std", target_arch = "aarch64"))]
+const HOST_ARCH: Architecture = Architecture::Aarch64;
+#[cfg(all(feature = "std", target_arch = "x86"))]
+const HOST_ARCH: Architecture = Architecture::I386;
+#[cfg(all(feature = "std", target_arch = "arm"))]
+const HOST_ARCH: Architecture = Architecture::Arm;
+#[cfg(all(feature = "std", target_arch = "wasm32"))]
+const HOST_ARCH: Architecture = Architecture::Wasm32;
+
+#[cfg(feature = "std")]
 pub fn load<'a, T: AsRef<Path>>(x: T) -> Result<RawImage<'a>, UnwindError> {
+    load_matching(x, None)
+}
+
+/// Load an object file, selecting the slice for the running architecture if the file is a
+/// Mach-O universal binary. If `uuid` is given, the selected slice must also carry that
+/// `LC_UUID`.
+#[cfg(feature = "std")]
+pub fn load_matching<'a, T: AsRef<Path>>(
+    x: T,
+    uuid: Option<[u8; 16]>,
+) -> Result<RawImage<'a>, UnwindError> {
     File::open(x)
         .map(ManuallyDrop::new)
         .map_err(UnwindError::from)
-        .and_then(|f| unsafe { Ok((ManuallyDrop::new(Mmap::map(&f)?), f)) })
+        .and_then(|f| Ok((ManuallyDrop::new(map(&f)?), f)))
         .and_then(|(m, f)| unsafe {
-            Ok((
-                ObjFile::parse(std::slice::from_raw_parts(m.as_ptr(), m.len()))?,
-                m,
-                f,
-            ))
+            let data: &'a [u8] = std::slice::from_raw_parts(m.as_ptr(), m.len());
+            Ok((ObjFile::parse(select_slice(data, uuid)?)?, m, f))
+        })
+}
+
+/// Keep the mapping of a loaded file and close the file, so that the sections of the object can
+/// be borrowed for as long as the mapping is kept. Moving the mapping does not move its data.
+#[cfg(feature = "std")]
+pub fn keep_mapped((object, mmap, file): RawImage<'static>) -> (object::File<'static>, Mmap) {
+    ManuallyDrop::into_inner(file);
+    (object, ManuallyDrop::into_inner(mmap))
+}
+
+/// The data of the section `name`, borrowed from the mapping of `object` unless compressed.
+pub fn section_data(object: &object::File<'static>, name: &str) -> Option<SectionData> {
+    object.section_by_name(name)?.uncompressed_data().ok()
+}
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+fn map(f: &File) -> std::io::Result<Mmap> {
+    unsafe { Mmap::map(f) }
+}
+
+#[cfg(all(feature = "std", target_arch = "wasm32"))]
+fn map(mut f: &File) -> std::io::Result<Mmap> {
+    let mut data = Vec::new();
+    std::io::Read::read_to_end(&mut f, &mut data)?;
+    Ok(data)
+}
+
+/// The ELF image of the vDSO, if it is the image mapped at `start_avma`. The vDSO has no
+/// backing file, but the kernel maps all of it for the lifetime of the process.
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub fn vdso(start_avma: usize, length: usize) -> Option<&'static [u8]> {
+    #[cfg(target_pointer_width = "64")]
+    type Ehdr = libc::Elf64_Ehdr;
+    #[cfg(target_pointer_width = "32")]
+    type Ehdr = libc::Elf32_Ehdr;
+
+    let base = unsafe { libc::getauxval(libc::AT_SYSINFO_EHDR) } as usize;
+    if base == 0 || !(start_avma..start_avma + length).contains(&base) {
+        return None;
+    }
+    // The loadable segment does not cover the section headers, which sit at the end.
+    let header = unsafe { &*(base as *const Ehdr) };
+    let size = (header.e_shoff as usize + header.e_shnum as usize * header.e_shentsize as usize)
+        .max(start_avma + length - base);
+    Some(unsafe { std::slice::from_raw_parts(base as *const u8, size) })
+}
+
+/// The AVMA range of a loadable segment and whether it is executable.
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub type LoadedSegment = (Range<usize>, bool);
+
+/// The bias of the ELF image whose file header is mapped at `base`, i.e. where its first
+/// loadable segment puts the start of the file, along with the AVMA ranges of its loadable
+/// segments and whether each is executable. The headers are read through [`LocalTarget`], so
+/// `None` is returned rather than faulting if they are not of the running process's class or
+/// point outside the mapped memory.
+///
+/// [`LocalTarget`]: crate::cursor::LocalTarget
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub fn elf_segments(base: usize) -> Option<(usize, Vec<LoadedSegment>)> {
+    use crate::cursor::{LocalTarget, Target};
+    #[cfg(target_pointer_width = "64")]
+    type Ehdr = libc::Elf64_Ehdr;
+    #[cfg(target_pointer_width = "64")]
+    type Phdr = libc::Elf64_Phdr;
+    #[cfg(target_pointer_width = "32")]
+    type Ehdr = libc::Elf32_Ehdr;
+    #[cfg(target_pointer_width = "32")]
+    type Phdr = libc::Elf32_Phdr;
+    #[cfg(target_pointer_width = "64")]
+    const CLASS: u8 = 2;
+    #[cfg(target_pointer_width = "32")]
+    const CLASS: u8 = 1;
+
+    let mut header = std::mem::MaybeUninit::<Ehdr>::uninit();
+    let bytes = unsafe {
+        std::slice::from_raw_parts_mut(header.as_mut_ptr() as *mut u8, std::mem::size_of::<Ehdr>())
+    };
+    LocalTarget.read_memory(base, bytes).ok()?;
+    // Every bit pattern is a valid header.
+    let header = unsafe { header.assume_init() };
+    if header.e_ident[..4] != *b"\x7fELF"
+        || header.e_ident[4] != CLASS
+        || header.e_phentsize as usize != std::mem::size_of::<Phdr>()
+    {
+        return None;
+    }
+    // The program headers are loaded along with the file header in the first segment.
+    let mut phdrs = Vec::<Phdr>::with_capacity(header.e_phnum as usize);
+    let bytes = unsafe {
+        std::slice::from_raw_parts_mut(
+            phdrs.as_mut_ptr() as *mut u8,
+            header.e_phnum as usize * std::mem::size_of::<Phdr>(),
+        )
+    };
+    LocalTarget
+        .read_memory(base.checked_add(header.e_phoff as usize)?, bytes)
+        .ok()?;
+    // Every bit pattern is a valid program header, and all of them were read.
+    unsafe { phdrs.set_len(header.e_phnum as usize) };
+    let mut loads = phdrs
+        .iter()
+        .filter(|x| x.p_type == libc::PT_LOAD)
+        .peekable();
+    let first = loads.peek()?;
+    let bias = base
+        .checked_add(first.p_offset as usize)?
+        .checked_sub(first.p_vaddr as usize)?;
+    let segments = loads
+        .map(|x| {
+            let start = bias.wrapping_add(x.p_vaddr as usize);
+            let end = start.checked_add(x.p_memsz as usize)?;
+            Some((start..end, x.p_flags & libc::PF_X != 0))
+        })
+        .collect::<Option<_>>()?;
+    Some((bias, segments))
+}
+
+/// A memory mapping of the process, as listed in `/proc/self/maps`.
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub struct Mapping {
+    pub range: Range<usize>,
+    /// The permissions, e.g. `r-xp`.
+    pub perms: String,
+    /// The offset into the mapped file.
+    pub offset: usize,
+    /// The mapped file, or a pseudo path such as `[stack]`. Empty for anonymous mappings.
+    pub path: String,
+}
+
+/// Read the memory mappings of the process from `/proc/self/maps`. Empty if it cannot be read.
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub fn read_maps() -> Vec<Mapping> {
+    let Ok(maps) = std::fs::read_to_string("/proc/self/maps") else {
+        return Vec::new();
+    };
+    maps.lines()
+        .filter_map(|line| {
+            // `start-end perms offset dev inode path`, where the path is padded and may contain
+            // spaces.
+            let mut fields = line.splitn(6, ' ');
+            let (Some(range), Some(perms), Some(offset)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return None;
+            };
+            let parse = |x| usize::from_str_radix(x, 16).ok();
+            let (start, end) = range.split_once('-')?;
+            Some(Mapping {
+                range: parse(start)?..parse(end)?,
+                perms: perms.to_string(),
+                offset: parse(offset)?,
+                path: fields.nth(2).unwrap_or_default().trim_start().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The backing file of the mapping at `avma` among `maps` if it was deleted or replaced on
+/// disk since it was mapped. The kernel keeps the mapped file alive and exposes it under
+/// `/proc/self/map_files`, so the image can still be read as it was loaded.
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub fn deleted_mapping(maps: &[Mapping], avma: usize) -> Option<std::path::PathBuf> {
+    maps.iter()
+        .filter(|x| x.path.ends_with(" (deleted)"))
+        .find(|x| x.range.contains(&avma))
+        .map(|x| {
+            Path::new("/proc/self/map_files").join(format!("{:x}-{:x}", x.range.start, x.range.end))
         })
 }
+
+#[cfg(feature = "std")]
+fn select_slice(data: &[u8], uuid: Option<[u8; 16]>) -> Result<&[u8], UnwindError> {
+    match FileKind::parse(data)? {
+        FileKind::MachOFat32 => select_arch(data, FatHeader::parse_arch32(data)?, uuid),
+        FileKind::MachOFat64 => select_arch(data, FatHeader::parse_arch64(data)?, uuid),
+        _ => Ok(data),
+    }
+}
+
+#[cfg(feature = "std")]
+fn select_arch<'a, A: FatArch>(
+    data: &'a [u8],
+    arches: &[A],
+    uuid: Option<[u8; 16]>,
+) -> Result<&'a [u8], UnwindError> {
+    arches
+        .iter()
+        .filter(|arch| arch.architecture() == HOST_ARCH)
+        .filter_map(|arch| arch.data(data).ok())
+        .find(|slice| match uuid {
+            Some(uuid) => {
+                ObjFile::parse(*slice)
+                    .ok()
+                    .and_then(|obj| obj.mach_uuid().ok().flatten())
+                    == Some(uuid)
+            }
+            None => true,
+        })
+        .ok_or(UnwindError::NotSupported(
+            "universal binary has no slice for the running architecture",
+        ))
+}
+
+#[cfg(all(test, target_os = "macos"))]
+mod test {
+    #[test]
+    fn it_selects_fat_slice() {
+        use object::Object;
+        let (obj, _, _) = super::load("/usr/lib/dyld").unwrap();
+        assert_eq!(obj.architecture(), super::HOST_ARCH);
+    }
+}
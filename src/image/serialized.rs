@@ -0,0 +1,42 @@
+//! A value shared between threads whose accesses are serialized, so that caches which are not
+//! `Sync` can live in images shared between threads. Without the `std` feature there is no lock,
+//! and the value is not shared between threads at all.
+#[cfg(feature = "std")]
+use std::sync::{Mutex, PoisonError};
+
+/// A value accessed by one thread at a time.
+pub struct Serialized<T> {
+    #[cfg(feature = "std")]
+    lock: Mutex<()>,
+    value: T,
+}
+
+// SAFETY: `value` is only reached through `with`, which holds the lock, or through `&mut self`,
+// so no two threads access it at once. `T: Send` as the value may be used from any thread.
+#[cfg(feature = "std")]
+unsafe impl<T: Send> Sync for Serialized<T> {}
+
+impl<T> Serialized<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            #[cfg(feature = "std")]
+            lock: Mutex::new(()),
+            value,
+        }
+    }
+
+    /// Run `f` on the value, holding off the other threads.
+    ///
+    /// # Safety
+    /// What `f` returns may borrow from the value past the call, but only from parts that later
+    /// calls never change or move, e.g. lazily computed data that is never replaced once set.
+    pub unsafe fn with<'s, R>(&'s self, f: impl FnOnce(&'s T) -> R) -> R {
+        #[cfg(feature = "std")]
+        let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        f(&self.value)
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
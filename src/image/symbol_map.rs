@@ -1,16 +1,28 @@
-use object::{File, Object, SymbolMap, SymbolMapEntry, SymbolMapName};
+use super::{HashMap, HashSet};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use object::{
+    File, Object, ObjectSection, ObjectSymbol, SectionFlags, SymbolKind, SymbolMap, SymbolMapEntry,
+    SymbolMapName,
+};
 
 #[derive(Debug)]
 pub struct OwnedSymbolMapName {
     address: u64,
+    size: u64,
     name: String,
+    text: bool,
+    sized: bool,
 }
 
 impl OwnedSymbolMapName {
-    pub fn new<S: AsRef<str>>(address: u64, name: S) -> Self {
+    pub fn new<S: AsRef<str>>(address: u64, size: u64, name: S) -> Self {
         OwnedSymbolMapName {
             address,
+            size,
             name: name.as_ref().to_string(),
+            text: true,
+            sized: size != 0,
         }
     }
 
@@ -20,6 +32,30 @@ impl OwnedSymbolMapName {
         self.address
     }
 
+    /// The symbol size. Symbols without a size in the symbol table extend to the next symbol or
+    /// the end of their section; zero if neither is known.
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Whether the size comes from the symbol table rather than from the next symbol.
+    #[inline]
+    pub fn is_sized(&self) -> bool {
+        self.sized
+    }
+
+    /// The address range covered by the symbol, if its size is known.
+    pub fn range(&self) -> Option<core::ops::Range<u64>> {
+        (self.size != 0).then(|| self.address..self.address + self.size)
+    }
+
+    /// Whether the symbol is known to name code rather than data.
+    #[inline]
+    pub fn is_text(&self) -> bool {
+        self.text
+    }
+
     /// The symbol name.
     #[inline]
     pub fn name(&self) -> &str {
@@ -27,8 +63,8 @@ impl OwnedSymbolMapName {
     }
 
     #[inline]
-    pub fn from(origin: &SymbolMapName) -> Self {
-        Self::new(origin.address(), origin.name())
+    pub fn from(origin: &SymbolMapName, size: u64) -> Self {
+        Self::new(origin.address(), size, origin.name())
     }
 }
 
@@ -42,11 +78,97 @@ impl SymbolMapEntry for OwnedSymbolMapName {
 pub type OwnedSymbolMap = SymbolMap<OwnedSymbolMapName>;
 
 pub fn load(f: &File) -> OwnedSymbolMap {
-    SymbolMap::new(
-        f.symbol_map()
-            .symbols()
-            .into_iter()
-            .map(OwnedSymbolMapName::from)
-            .collect(),
-    )
+    // `symbol_map` drops sizes, so recover them from the symbol tables.
+    let mut sizes = HashMap::new();
+    let mut text = HashSet::new();
+    for symbol in f
+        .symbols()
+        .chain(f.dynamic_symbols())
+        .filter(|x| x.is_definition())
+    {
+        let size = sizes.entry(symbol.address()).or_insert(0);
+        *size = symbol.size().max(*size);
+        if symbol.kind() == SymbolKind::Text {
+            text.insert(symbol.address());
+        }
+    }
+    let size_of = |address| sizes.get(&address).copied().unwrap_or(0);
+
+    let mut symbols: Vec<_> = f
+        .symbol_map()
+        .symbols()
+        .iter()
+        .map(|x| OwnedSymbolMapName {
+            text: text.contains(&x.address()),
+            ..OwnedSymbolMapName::from(x, size_of(x.address()))
+        })
+        .collect();
+
+    // `symbol_map` only falls back to `.dynsym` if there is no `.symtab`, which may have been
+    // stripped of exported functions. Merge those back in.
+    let mut known: HashSet<_> = symbols.iter().map(|x| x.address()).collect();
+    for symbol in f.dynamic_symbols() {
+        if !symbol.is_definition() || symbol.kind() != SymbolKind::Text {
+            continue;
+        }
+        match symbol.name() {
+            Ok(name) if !name.is_empty() && known.insert(symbol.address()) => symbols.push(
+                OwnedSymbolMapName::new(symbol.address(), size_of(symbol.address()), name),
+            ),
+            _ => {}
+        }
+    }
+    bound_unsized(f, &mut symbols);
+    SymbolMap::new(symbols)
+}
+
+/// Let symbols without a size extend to the next symbol, but not past their section, so that
+/// e.g. `_init` does not cover the `.plt` following it. Only sections loaded into memory count;
+/// the others have no address.
+fn bound_unsized(f: &File, symbols: &mut [OwnedSymbolMapName]) {
+    let sections: Vec<_> = f
+        .sections()
+        .filter(|x| match x.flags() {
+            SectionFlags::Elf { sh_flags } => sh_flags & u64::from(object::elf::SHF_ALLOC) != 0,
+            _ => true,
+        })
+        .map(|x| x.address()..x.address() + x.size())
+        .filter(|x| !x.is_empty())
+        .collect();
+    symbols.sort_by_key(|x| x.address);
+    for i in 0..symbols.len() {
+        if symbols[i].size != 0 {
+            continue;
+        }
+        let address = symbols[i].address;
+        let section_end = sections
+            .iter()
+            .find(|x| x.contains(&address))
+            .map(|x| x.end);
+        let next = symbols[i + 1..]
+            .iter()
+            .map(|x| x.address)
+            .find(|x| *x > address);
+        if let Some(end) = section_end.into_iter().chain(next).min() {
+            symbols[i].size = end - address;
+        }
+    }
+}
+
+/// Find the symbol covering `svma`. Unlike [`SymbolMap::get`], addresses past the end of the
+/// preceding symbol, e.g. in padding, are not attributed to it. Symbols without a size extend
+/// to the next symbol.
+///
+/// The end of a symbol still counts as covered: the return address of a call that does not
+/// return lies right past the caller. A symbol starting there is found first.
+pub fn lookup(map: &OwnedSymbolMap, svma: u64) -> Option<&OwnedSymbolMapName> {
+    let symbols = map.symbols();
+    let i = symbols
+        .partition_point(|x| x.address() <= svma)
+        .checked_sub(1)?;
+    let covered = match symbols[i].range() {
+        Some(range) => svma <= range.end,
+        None => symbols.get(i + 1).is_none_or(|x| svma < x.address()),
+    };
+    covered.then_some(&symbols[i])
 }
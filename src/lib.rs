@@ -1,17 +1,36 @@
-#![cfg_attr(test, feature(rustc_private))]
+//! Without the default `std` feature, the crate only needs `alloc`: images are built from
+//! caller-provided bytes with [`image::load_bytes`] and [`GlobalContext::with_images`], and
+//! cursors are started from known registers on a caller-provided [`cursor::Target`]. Loading the
+//! images of the process, reading files and line info all take `std`.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
-use std::fmt::{Debug, Display, Formatter};
+extern crate alloc;
 
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::{vec, vec::Vec};
+use core::fmt::{Debug, Display, Formatter};
+
+#[cfg(all(feature = "std", any(target_arch = "x86_64", target_arch = "arm")))]
+pub mod backtrace;
 mod cffi;
+#[cfg(all(
+    feature = "backtrace-compat",
+    any(target_arch = "x86_64", target_arch = "arm")
+))]
+pub mod compat;
 pub mod cursor;
 pub mod image;
 
-#[derive(thiserror::Error, Debug)]
+#[derive(Debug)]
 pub enum UnwindError {
-    IOError(#[from] std::io::Error),
-    ObjectParsingError(#[from] object::Error),
-    GimliError(#[from] gimli::Error),
-    ErrnoError(#[from] nix::errno::Errno),
+    #[cfg(feature = "std")]
+    IOError(std::io::Error),
+    ObjectParsingError(object::Error),
+    GimliError(gimli::Error),
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+    ErrnoError(nix::errno::Errno),
     UnknownProgramCounter(usize),
     UnwindLogicalError(&'static str),
     NotSupported(&'static str),
@@ -19,10 +38,12 @@ pub enum UnwindError {
 }
 
 impl Display for UnwindError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         match self {
+            #[cfg(feature = "std")]
             UnwindError::IOError(e) => Display::fmt(e, f),
             UnwindError::ObjectParsingError(e) => Display::fmt(e, f),
+            #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
             UnwindError::ErrnoError(e) => Display::fmt(e, f),
             UnwindError::GimliError(e) => Display::fmt(e, f),
             UnwindError::UnknownProgramCounter(pc) => {
@@ -41,20 +62,155 @@ impl Display for UnwindError {
     }
 }
 
+#[cfg(feature = "std")]
+impl std::error::Error for UnwindError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            UnwindError::IOError(e) => Some(e),
+            UnwindError::ObjectParsingError(e) => Some(e),
+            #[cfg(not(target_arch = "wasm32"))]
+            UnwindError::ErrnoError(e) => Some(e),
+            UnwindError::GimliError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for UnwindError {
+    fn from(e: std::io::Error) -> Self {
+        UnwindError::IOError(e)
+    }
+}
+
+impl From<object::Error> for UnwindError {
+    fn from(e: object::Error) -> Self {
+        UnwindError::ObjectParsingError(e)
+    }
+}
+
+impl From<gimli::Error> for UnwindError {
+    fn from(e: gimli::Error) -> Self {
+        UnwindError::GimliError(e)
+    }
+}
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+impl From<nix::errno::Errno> for UnwindError {
+    fn from(e: nix::errno::Errno) -> Self {
+        UnwindError::ErrnoError(e)
+    }
+}
+
 pub struct GlobalContext<'a> {
     images: Vec<image::Image<'a>>,
+    #[cfg(feature = "std")]
+    deferred: Vec<image::DeferredImage<'a>>,
+    resolve_plt: bool,
+    include_inlined: bool,
+    #[cfg_attr(not(feature = "line-info"), allow(dead_code))]
+    line_info: bool,
+    #[cfg_attr(not(feature = "line-info"), allow(dead_code))]
+    max_inline_frames: usize,
+    demangler: Option<Box<Demangler>>,
+    resolution_filter: Option<Box<ResolutionFilter>>,
+}
+
+/// Demangles a symbol name, or returns `None` if the name is not in its mangling.
+pub type Demangler = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+/// Decides whether the addresses of an image are resolved.
+pub type ResolutionFilter = dyn Fn(&image::Image) -> bool + Send + Sync;
+
+/// Configures and creates a [`GlobalContext`]. Options left unset keep the defaults of
+/// [`GlobalContext::new`].
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct GlobalContextBuilder {
+    load_options: image::LoadOptions,
+    resolve_plt: bool,
+    include_inlined: bool,
+    line_info: bool,
+    max_inline_frames: usize,
 }
 
-enum Frame<'a> {
+/// The default of [`GlobalContext::set_max_inline_frames`].
+pub const DEFAULT_MAX_INLINE_FRAMES: usize = 128;
+
+pub enum Frame<'a> {
+    #[cfg(feature = "line-info")]
     Dwarf(addr2line::Frame<'a, image::ImageReader<'a>>),
     SymbolMap(&'a str),
+    /// No symbol covers the address, but it lies in a code section of the image, e.g.
+    /// `<.plt>`.
+    Section(&'a str),
+    /// Neither DWARF nor a symbol covers the address; only its object and SVMA are known.
+    ModuleOffset {
+        object: &'a str,
+        offset: usize,
+    },
+    /// Stands for this many inlined frames left out between the innermost frames and the
+    /// outermost one, past [`GlobalContext::set_max_inline_frames`].
+    Elided(usize),
+}
+
+pub struct SymbolInfo<'a> {
+    pub object_name: Option<&'a str>,
+    pub avma: usize,
+    pub svma: Option<usize>,
+    pub associated_frames: Vec<Frame<'a>>,
+    /// The line table discriminator of the innermost frame, if any.
+    pub discriminator: Option<u64>,
+    /// The AVMA range of the physical function containing `avma`, if known.
+    pub function: Option<core::ops::Range<usize>>,
+    /// The offset of `avma` into the function the outermost frame names, i.e. from the low PC
+    /// of the DWARF subprogram or from the address of the symbol.
+    pub offset: Option<usize>,
+    demangler: Option<&'a Demangler>,
 }
 
-struct SymbolInfo<'a> {
-    object_name: Option<&'a str>,
-    avma: usize,
-    svma: Option<usize>,
-    associated_frames: Vec<Frame<'a>>,
+/// An address as its module and SVMA, which is all it takes to symbolize it elsewhere, e.g. on a
+/// server holding the debug info of the module.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ModuleAddress<'a> {
+    pub object_name: &'a str,
+    /// The GNU build ID of an ELF module or the UUID of a Mach-O module, if any.
+    pub build_id: Option<&'a [u8]>,
+    pub svma: usize,
+}
+
+/// Where a resolved frame comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FrameKind {
+    Dwarf,
+    SymbolMap,
+    Section,
+    ModuleOffset,
+    Elided,
+}
+
+/// An owned copy of a resolved frame.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PublicFrame {
+    pub name: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub discriminator: Option<u64>,
+    /// The AVMA of the physical function. Only set on the outermost frame of an address.
+    pub function_start: Option<usize>,
+    pub function_size: Option<usize>,
+    /// The offset of the address into the function, as in `function+0x1234`. Only set on the
+    /// outermost frame of an address.
+    pub offset: Option<usize>,
+    /// Where an inlined frame is called in its caller, which is the location of the next
+    /// frame. `None` for the outermost frame of an address, which is not inlined.
+    pub call_file: Option<String>,
+    pub call_line: Option<u32>,
+    pub call_column: Option<u32>,
+    /// The source language of the compilation unit of a DWARF frame.
+    pub language: Option<gimli::DwLang>,
+    pub kind: FrameKind,
 }
 
 impl<'a> SymbolInfo<'a> {
@@ -64,85 +220,1990 @@ impl<'a> SymbolInfo<'a> {
             avma,
             svma: None,
             associated_frames: Vec::new(),
+            discriminator: None,
+            function: None,
+            offset: None,
+            demangler: None,
+        }
+    }
+
+    /// Owned copies of the associated frames, innermost first.
+    pub fn public_frames(&self) -> Vec<PublicFrame> {
+        let mut frames: Vec<_> = self
+            .associated_frames
+            .iter()
+            .map(|x| PublicFrame::new(x, self.demangler))
+            .collect();
+        // Outer frames are located at call sites, which carry no discriminator.
+        if let Some(innermost) = frames.first_mut() {
+            innermost.discriminator = self.discriminator;
+        }
+        // The location of each frame is the call site of the frame inlined into it.
+        for i in 1..frames.len() {
+            let call_file = frames[i].file.clone();
+            let (call_line, call_column) = (frames[i].line, frames[i].column);
+            let inner = &mut frames[i - 1];
+            inner.call_file = call_file;
+            inner.call_line = call_line;
+            inner.call_column = call_column;
+        }
+        if let Some(outermost) = frames.last_mut() {
+            outermost.function_start = self.function.as_ref().map(|x| x.start);
+            outermost.function_size = self.function.as_ref().map(|x| x.len());
+            outermost.offset = self.offset;
         }
+        frames
     }
 }
 
-impl<'a> GlobalContext<'a> {
-    fn new() -> Self {
-        let images = image::load_all();
-        GlobalContext { images }
+impl<'a> From<&SymbolInfo<'a>> for Vec<PublicFrame> {
+    fn from(info: &SymbolInfo<'a>) -> Self {
+        info.public_frames()
     }
+}
 
-    fn find_image(&self, avma: usize) -> Option<&image::Image<'a>> {
-        match self
-            .images
-            .binary_search_by_key(&std::cmp::Reverse(avma), |x| {
-                std::cmp::Reverse(x.start_avma)
-            }) {
-            Ok(i) => Some(&self.images[i]),
-            Err(i) if i >= self.images.len() => None,
-            Err(i) => {
-                if self.images[i].has(avma) {
-                    Some(&self.images[i])
-                } else {
-                    None
+impl PublicFrame {
+    fn new(frame: &Frame, demangler: Option<&Demangler>) -> Self {
+        match frame {
+            #[cfg(feature = "line-info")]
+            Frame::Dwarf(frame) => {
+                let location = frame.location.as_ref();
+                PublicFrame {
+                    name: frame
+                        .function
+                        .as_ref()
+                        .and_then(|x| x.raw_name().ok())
+                        .map(|x| demangle(&x, demangler)),
+                    file: location.and_then(|x| x.file).map(String::from),
+                    line: location.and_then(|x| x.line),
+                    column: location.and_then(|x| x.column),
+                    language: frame.function.as_ref().and_then(|x| x.language),
+                    ..PublicFrame::named(String::new(), FrameKind::Dwarf)
                 }
             }
+            Frame::SymbolMap(name) => {
+                PublicFrame::named(demangle(name, demangler), FrameKind::SymbolMap)
+            }
+            Frame::Section(name) => PublicFrame::named(name.to_string(), FrameKind::Section),
+            Frame::ModuleOffset { object, offset } => {
+                PublicFrame::named(format!("{}+{:#x}", object, offset), FrameKind::ModuleOffset)
+            }
+            Frame::Elided(count) => PublicFrame::named(
+                format!("... {} more inlined frames", count),
+                FrameKind::Elided,
+            ),
+        }
+    }
+
+    /// A frame known only by name, without location.
+    fn named(name: String, kind: FrameKind) -> Self {
+        Self {
+            name: Some(name),
+            file: None,
+            line: None,
+            column: None,
+            discriminator: None,
+            function_start: None,
+            function_size: None,
+            offset: None,
+            call_file: None,
+            call_line: None,
+            call_column: None,
+            language: None,
+            kind,
+        }
+    }
+}
+
+/// Converts a single frame on its own. Fields that depend on the neighbouring frames or on the
+/// address, i.e. discriminators, function ranges and call sites, are only filled in by
+/// [`SymbolInfo::public_frames`].
+impl<'a> From<&Frame<'a>> for PublicFrame {
+    fn from(frame: &Frame<'a>) -> Self {
+        PublicFrame::new(frame, None)
+    }
+}
+
+/// Demangle `name` with `custom` if it recognizes the name, and as a Rust name otherwise.
+fn demangle(name: &str, custom: Option<&Demangler>) -> String {
+    custom
+        .and_then(|f| f(name))
+        .unwrap_or_else(|| rustc_demangle::demangle(name).to_string())
+}
+
+#[cfg(feature = "std")]
+impl Default for GlobalContextBuilder {
+    fn default() -> Self {
+        Self {
+            load_options: Default::default(),
+            resolve_plt: false,
+            include_inlined: true,
+            line_info: true,
+            max_inline_frames: DEFAULT_MAX_INLINE_FRAMES,
         }
     }
+}
+
+#[cfg(feature = "std")]
+impl GlobalContextBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the images loaded up front. See [`GlobalContext::new_with_options`].
+    pub fn load_options(mut self, options: image::LoadOptions) -> Self {
+        self.load_options = options;
+        self
+    }
+
+    /// See [`GlobalContext::set_resolve_plt`].
+    pub fn resolve_plt(mut self, enabled: bool) -> Self {
+        self.resolve_plt = enabled;
+        self
+    }
+
+    /// See [`GlobalContext::set_include_inlined`].
+    pub fn include_inlined(mut self, enabled: bool) -> Self {
+        self.include_inlined = enabled;
+        self
+    }
+
+    /// See [`GlobalContext::set_line_info`].
+    pub fn line_info(mut self, enabled: bool) -> Self {
+        self.line_info = enabled;
+        self
+    }
+
+    /// See [`GlobalContext::set_max_inline_frames`].
+    pub fn max_inline_frames(mut self, max: usize) -> Self {
+        self.max_inline_frames = max;
+        self
+    }
+
+    /// Load the images of the process and create the context.
+    pub fn build<'a>(self) -> GlobalContext<'a> {
+        let (images, deferred) = image::load_with(&self.load_options);
+        GlobalContext {
+            deferred,
+            resolve_plt: self.resolve_plt,
+            include_inlined: self.include_inlined,
+            line_info: self.line_info,
+            max_inline_frames: self.max_inline_frames,
+            ..GlobalContext::from_images(images)
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> Default for GlobalContext<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> GlobalContext<'a> {
+    #[cfg(feature = "std")]
+    pub fn new() -> Self {
+        Self::from_images(image::load_all())
+    }
+
+    /// Create a context to share between threads. Images are loaded once, and each image keeps a
+    /// single line context behind a lock, so threads resolving locations in the same image at
+    /// the same time wait for each other. Unwinding itself does not take the lock.
+    #[cfg(feature = "std")]
+    pub fn new_arc() -> std::sync::Arc<Self> {
+        std::sync::Arc::new(Self::new())
+    }
+
+    /// Configure a context before loading it.
+    #[cfg(feature = "std")]
+    pub fn builder() -> GlobalContextBuilder {
+        GlobalContextBuilder::new()
+    }
+
+    fn from_images(images: Vec<image::Image<'a>>) -> Self {
+        GlobalContext {
+            images,
+            #[cfg(feature = "std")]
+            deferred: Vec::new(),
+            resolve_plt: false,
+            include_inlined: true,
+            line_info: true,
+            max_inline_frames: DEFAULT_MAX_INLINE_FRAMES,
+            demangler: None,
+            resolution_filter: None,
+        }
+    }
+
+    /// Create a context from images loaded by the caller, e.g. with [`image::load_bytes`] where
+    /// the images cannot be enumerated or read from files. The process is not inspected.
+    pub fn with_images(images: Vec<image::Image<'a>>) -> Self {
+        Self::from_images(image::sorted(images))
+    }
+
+    /// Create a context that only loads the images allowed by `options` up front. The others
+    /// are loaded the first time an address inside them is resolved.
+    #[cfg(feature = "std")]
+    pub fn new_with_options(options: &image::LoadOptions) -> Self {
+        let (images, deferred) = image::load_with(options);
+        GlobalContext {
+            deferred,
+            ..Self::from_images(images)
+        }
+    }
+
+    /// Like [`GlobalContext::new`], but fails if no image of the process could be loaded.
+    #[cfg(feature = "std")]
+    pub fn try_new() -> Result<Self, UnwindError> {
+        image::try_load_all().map(Self::from_images)
+    }
+
+    /// Name frames in PLT stubs after the function the stub jumps to. This is off by default,
+    /// as it reads the dynamic relocations of an image on first use.
+    pub fn set_resolve_plt(&mut self, enabled: bool) {
+        self.resolve_plt = enabled;
+    }
+
+    /// Demangle names with `demangler` when converting frames to [`PublicFrame`]s, e.g. for
+    /// Swift, D or Nim symbols. Names it returns `None` for fall back to the built-in Rust
+    /// demangler.
+    pub fn set_demangler<F>(&mut self, demangler: F)
+    where
+        F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+    {
+        self.demangler = Some(Box::new(demangler));
+    }
+
+    /// Only resolve addresses in the images `filter` accepts, e.g. to skip the debug info of
+    /// system libraries. Addresses in other images are reported by their module and offset,
+    /// without looking up any symbol. Unwinding is not affected.
+    pub fn set_resolution_filter<F>(&mut self, filter: F)
+    where
+        F: Fn(&image::Image) -> bool + Send + Sync + 'static,
+    {
+        self.resolution_filter = Some(Box::new(filter));
+    }
+
+    /// Whether the resolution filter accepts `image`.
+    fn resolves(&self, image: &image::Image) -> bool {
+        self.resolution_filter.as_ref().is_none_or(|f| f(image))
+    }
 
-    fn resolve_symbol(&'a self, avma: usize) -> SymbolInfo<'a> {
+    /// Whether the frame at `pc` can be unwound with the unwind info of its image. No cursor is
+    /// involved, so this can decide up front between DWARF unwinding and a fallback.
+    pub fn has_unwind_info(&self, pc: usize) -> bool {
+        self.find_image(pc).is_some_and(|image| {
+            let svma = image.avma_to_svma(pc);
+            #[cfg(target_arch = "arm")]
+            if let Some(exidx) = image.exidx.as_ref() {
+                return exidx.lookup(svma).is_ok();
+            }
+            image.find_fde(svma).is_ok() || image.find_debug_frame_fde(svma).is_ok()
+        })
+    }
+
+    /// Report inlined frames along with the physical frame of an address. This is on by
+    /// default; when off, only the outermost DWARF frame is kept for each address.
+    pub fn set_include_inlined(&mut self, enabled: bool) {
+        self.include_inlined = enabled;
+    }
+
+    /// Collect at most `max` inlined frames per address, innermost first, which bounds the
+    /// memory held for pathologically deep inline chains. The frames past them are replaced by
+    /// a [`Frame::Elided`] marker ahead of the outermost frame, which is always kept, so the
+    /// chain is still walked to its end. Defaults to [`DEFAULT_MAX_INLINE_FRAMES`].
+    pub fn set_max_inline_frames(&mut self, max: usize) {
+        self.max_inline_frames = max;
+    }
+
+    /// Resolve frames from the DWARF debug info of the images. This is on by default; when
+    /// off, frames are only named after symbols, and no line context is ever built.
+    pub fn set_line_info(&mut self, enabled: bool) {
+        self.line_info = enabled;
+    }
+
+    fn find_image(&self, avma: usize) -> Option<&image::Image<'a>> {
+        let image = self.find_loaded_image(avma);
+        #[cfg(feature = "std")]
+        let image = image.or_else(|| {
+            self.deferred
+                .iter()
+                .find(|x| x.has(avma))
+                .and_then(image::DeferredImage::get)
+        });
+        image
+    }
+
+    fn find_loaded_image(&self, avma: usize) -> Option<&image::Image<'a>> {
+        // Images are sorted by descending start, so the first one starting at or below `avma`
+        // is the only candidate. An address above every start falls to the topmost image.
+        let i = self.images.partition_point(|x| x.start_avma > avma);
+        self.images.get(i).filter(|x| x.has(avma))
+    }
+
+    /// Resolve the frames at `avma`. Functions are named after their DWARF subprograms first,
+    /// so that code whose symbol was stripped, e.g. a static constructor run from
+    /// `.init_array`, keeps its name. Then the symbol table is consulted, and code outside any
+    /// symbol is reported by its section, e.g. `<.init>`, or else by its offset in the module.
+    pub fn resolve_symbol(&self, avma: usize) -> SymbolInfo<'_> {
         self.find_image(avma)
             .map(|image| {
-                let svma = avma - image.bias;
+                let svma = image.avma_to_svma(avma);
                 let object_name = Some(&image.filename as &str);
                 let mut associated_frames = Vec::new();
+                #[cfg_attr(not(feature = "line-info"), allow(unused_mut))]
+                let mut discriminator = None;
+                // The SVMA of the function the outermost frame names.
+                let mut start = None;
+
+                if !self.resolves(image) {
+                    return SymbolInfo {
+                        associated_frames: vec![Frame::ModuleOffset {
+                            object: &image.filename,
+                            offset: svma as usize,
+                        }],
+                        object_name,
+                        svma: Some(svma as usize),
+                        demangler: self.demangler.as_deref(),
+                        ..SymbolInfo::new_unresolved(avma)
+                    };
+                }
+
+                #[cfg(feature = "line-info")]
+                if let Some(frames) = (self.line_info && !image.is_cached())
+                    .then(|| image.find_frames(svma, |x| collect_frames(x, self.max_inline_frames)))
+                    .flatten()
+                {
+                    associated_frames = frames;
+                    if !self.include_inlined && associated_frames.len() > 1 {
+                        // The outermost frame is located at a call site, which has no
+                        // discriminator.
+                        associated_frames.drain(..associated_frames.len() - 1);
+                    } else if !associated_frames.is_empty() {
+                        discriminator = image.find_discriminator(svma);
+                    }
+                    if !associated_frames.is_empty() {
+                        start = image.find_subprogram_range(svma).map(|x| x.start);
+                    }
+                }
+
+                if associated_frames.is_empty() {
+                    if let Some((name, range)) = image.find_cached_function(svma) {
+                        associated_frames.push(Frame::SymbolMap(name));
+                        start = Some(range.start);
+                    }
+                }
 
-                if let Some(line_ctx) = image.line_context.as_ref() {
-                    if let Ok(mut frames) = line_ctx.find_frames(svma as u64) {
-                        while let Ok(Some(frame)) = frames.next() {
-                            associated_frames.push(Frame::Dwarf(frame));
-                        }
+                if associated_frames.is_empty() && self.resolve_plt {
+                    if let Some(target) = image.plt_target(svma) {
+                        associated_frames.push(Frame::SymbolMap(target));
                     }
                 }
 
                 if associated_frames.is_empty() {
                     // Find the symbol at the current address.
-                    if let Some(elf_symbol) = image.symbol_map.get(svma as u64) {
+                    if let Some(elf_symbol) = image.find_symbol(svma) {
                         associated_frames.push(Frame::SymbolMap(elf_symbol.name()));
+                        start = Some(elf_symbol.address());
                     }
                 }
 
+                if associated_frames.is_empty() {
+                    if let Some(section) = image.find_section(svma) {
+                        associated_frames.push(Frame::Section(section));
+                    }
+                }
+
+                if associated_frames.is_empty() {
+                    associated_frames.push(Frame::ModuleOffset {
+                        object: &image.filename,
+                        offset: svma as usize,
+                    });
+                }
+
+                let function = image
+                    .find_function_range(svma)
+                    .map(|x| image.svma_to_avma(x.start)..image.svma_to_avma(x.end));
+
                 SymbolInfo {
                     object_name,
                     avma,
-                    svma: Some(svma),
+                    svma: Some(svma as usize),
                     associated_frames,
+                    discriminator,
+                    function,
+                    offset: start.and_then(|x| svma.checked_sub(x)).map(|x| x as usize),
+                    demangler: self.demangler.as_deref(),
                 }
             })
             .unwrap_or(SymbolInfo::new_unresolved(avma))
     }
+
+    /// The images loaded so far, including deferred ones that have been loaded on demand.
+    #[cfg(feature = "std")]
+    fn loaded_images(&self) -> impl Iterator<Item = &image::Image<'a>> {
+        self.images.iter().chain(
+            self.deferred
+                .iter()
+                .filter_map(image::DeferredImage::loaded),
+        )
+    }
+
+    /// Resolve the frames of the call returning to `return_address`. A return address points
+    /// past its call, often at the next line or even into the next function, so the frames are
+    /// looked up at the byte before it. The address, SVMA and offset reported are still those of
+    /// `return_address`.
+    pub fn resolve_call_site(&self, return_address: usize) -> SymbolInfo<'_> {
+        let call = match return_address.checked_sub(1) {
+            Some(call) => call,
+            None => return self.resolve_symbol(return_address),
+        };
+        let mut info = self.resolve_symbol(call);
+        info.avma = return_address;
+        info.svma = info.svma.map(|x| x + 1);
+        info.offset = info.offset.map(|x| x + 1);
+        info
+    }
+
+    /// The module containing `avma` and the SVMA of the address in it, without looking up any
+    /// symbol.
+    pub fn module_address(&self, avma: usize) -> Option<ModuleAddress<'_>> {
+        self.find_image(avma).map(|image| ModuleAddress {
+            object_name: &image.filename,
+            build_id: image.build_id(),
+            svma: image.avma_to_svma(avma) as usize,
+        })
+    }
+
+    /// The inline chain at `avma` from its debug info, innermost first and ending with the
+    /// physical function, whether or not inlined frames are included in resolved symbols. Empty
+    /// if the address has no line info.
+    #[cfg_attr(not(feature = "line-info"), allow(unused_variables))]
+    pub fn inline_frames(&self, avma: usize) -> Vec<PublicFrame> {
+        #[cfg(feature = "line-info")]
+        if let Some(image) = self.find_image(avma).filter(|x| self.resolves(x)) {
+            let mut info = SymbolInfo::new_unresolved(avma);
+            info.demangler = self.demangler.as_deref();
+            let svma = image.avma_to_svma(avma);
+            if let Some(frames) =
+                image.find_frames(svma, |x| collect_frames(x, self.max_inline_frames))
+            {
+                info.associated_frames = frames;
+            }
+            return info.public_frames();
+        }
+        Vec::new()
+    }
+
+    /// The AVMA of the function called by the call returning to `return_address`, from the
+    /// call site info of the caller. `None` for indirect calls and calls to functions the
+    /// caller's unit does not define.
+    #[cfg_attr(not(feature = "line-info"), allow(unused_variables))]
+    pub fn call_site_target(&self, return_address: usize) -> Option<usize> {
+        #[cfg(feature = "line-info")]
+        if let Some(image) = self.find_image(return_address) {
+            return image
+                .find_call_target(image.avma_to_svma(return_address))
+                .map(|x| image.svma_to_avma(x));
+        }
+        None
+    }
+
+    /// Whether the call returning to `return_address` targets another function than the one
+    /// containing `pc`, so the called function has left the stack through a tail call and its
+    /// frame is missing between the two. `false` if the target of the call is unknown.
+    pub fn is_tail_call_gap(&self, pc: usize, return_address: usize) -> bool {
+        let target = match self.call_site_target(return_address) {
+            Some(target) => target,
+            None => return false,
+        };
+        let image = match self.find_image(pc) {
+            Some(image) if image.has(target) => image,
+            _ => return true,
+        };
+        let svma = image.avma_to_svma(pc);
+        #[cfg(feature = "line-info")]
+        let ranges = image.find_subprogram_ranges(svma);
+        #[cfg(not(feature = "line-info"))]
+        let ranges = None;
+        ranges
+            .or_else(|| image.find_function_range(svma).map(|x| vec![x]))
+            .is_some_and(|ranges| {
+                !ranges
+                    .iter()
+                    .any(|x| x.contains(&image.avma_to_svma(target)))
+            })
+    }
+
+    /// Save the functions of the loaded images to `path`, keyed by build ID, so that a later
+    /// run can skip the DWARF with [`GlobalContext::load_cache`]. Images without a build ID are
+    /// left out.
+    #[cfg(feature = "std")]
+    pub fn save_cache<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), UnwindError> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        image::cache::write_header(&mut file)?;
+        for image in self.loaded_images() {
+            if let Some(build_id) = image.build_id() {
+                image::cache::write_image(&mut file, build_id, image.functions())?;
+            }
+        }
+        std::io::Write::flush(&mut file)?;
+        Ok(())
+    }
+
+    /// Resolve function names of the loaded images from a cache written by
+    /// [`GlobalContext::save_cache`]. Only images whose build ID matches an entry use it, so
+    /// stale entries of rebuilt images are ignored. Returns the number of images covered.
+    ///
+    /// Cached images report one frame per address, named after the physical function, and no
+    /// source locations. Their DWARF has been loaded already; to skip loading it, pass the cache
+    /// as [`LoadOptions::symbol_cache`](image::LoadOptions::symbol_cache) instead.
+    #[cfg(feature = "std")]
+    pub fn load_cache<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<usize, UnwindError> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let mut indexes = image::cache::read(file)?;
+        let mut covered = 0;
+        for image in self.images.iter_mut().chain(
+            self.deferred
+                .iter_mut()
+                .filter_map(image::DeferredImage::loaded_mut),
+        ) {
+            if let Some(index) = image.build_id().and_then(|x| indexes.remove(x)) {
+                image.set_cached_functions(index);
+                covered += 1;
+            }
+        }
+        Ok(covered)
+    }
+
+    /// Attach the unwind tables of the object file at `path` to the loaded images with its build
+    /// ID, see [`image::Image::attach_unwind_info`]. Returns the number of images covered.
+    #[cfg(feature = "std")]
+    pub fn attach_unwind_info<P: AsRef<std::path::Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<usize, UnwindError> {
+        let build_id = image::build_id_of(path.as_ref())?
+            .ok_or(UnwindError::NotSupported("unwind info without build ID"))?;
+        self.attach_unwind_info_where(path.as_ref(), |x| x.build_id() == Some(&build_id))
+    }
+
+    /// Like [`GlobalContext::attach_unwind_info`], but for the loaded images at `name`, e.g.
+    /// when the tables were kept without the build ID note.
+    #[cfg(feature = "std")]
+    pub fn attach_unwind_info_by_name<P: AsRef<std::path::Path>>(
+        &mut self,
+        name: &str,
+        path: P,
+    ) -> Result<usize, UnwindError> {
+        self.attach_unwind_info_where(path.as_ref(), |x| x.filename == name)
+    }
+
+    #[cfg(feature = "std")]
+    fn attach_unwind_info_where<F>(
+        &mut self,
+        path: &std::path::Path,
+        matches: F,
+    ) -> Result<usize, UnwindError>
+    where
+        F: Fn(&image::Image) -> bool,
+    {
+        let mut covered = 0;
+        for image in self.images.iter_mut().chain(
+            self.deferred
+                .iter_mut()
+                .filter_map(image::DeferredImage::loaded_mut),
+        ) {
+            if matches(image) {
+                image.attach_unwind_info(path)?;
+                covered += 1;
+            }
+        }
+        Ok(covered)
+    }
+
+    /// Release the line contexts of all images to reclaim memory. They are rebuilt when an image
+    /// is resolved again.
+    pub fn evict_line_contexts(&mut self) {
+        self.images.iter_mut().for_each(image::Image::release_heavy);
+        #[cfg(feature = "std")]
+        self.deferred
+            .iter_mut()
+            .filter_map(image::DeferredImage::loaded_mut)
+            .for_each(image::Image::release_heavy);
+    }
+
+    /// Walk the stack starting at the registers of `u_ctx`, e.g. those passed to a signal
+    /// handler, calling `f` with the program counter of each frame until it breaks or the
+    /// stack ends. Unwind rules are kept on the stack, so nothing is allocated.
+    #[cfg(all(
+        any(target_arch = "x86_64", target_arch = "arm"),
+        not(target_os = "none")
+    ))]
+    pub fn unwind_with<F>(&'a self, u_ctx: &libc::ucontext_t, mut f: F)
+    where
+        F: FnMut(usize) -> core::ops::ControlFlow<()>,
+    {
+        use cursor::{state::CursorState, StaticCursor, Unwinding};
+        let mut cursor = StaticCursor::<cursor::state::NativeState>::from_ucontext(self, *u_ctx);
+        while f(cursor.state().get_program_counter()).is_continue() && cursor.next().is_ok() {}
+    }
+
+    /// Collect the program counters of at most `max` frames starting at the registers of `u_ctx`,
+    /// without resolving them. This is the cheap part of a backtrace, e.g. for a profiler; the
+    /// addresses can be passed to [`GlobalContext::resolve_symbol`] later.
+    #[cfg(all(
+        any(target_arch = "x86_64", target_arch = "arm"),
+        not(target_os = "none")
+    ))]
+    pub fn capture_pcs(&'a self, u_ctx: &libc::ucontext_t, max: usize) -> Vec<usize> {
+        let mut pcs = Vec::new();
+        self.unwind_with(u_ctx, |pc| {
+            if pcs.len() < max {
+                pcs.push(pc);
+            }
+            if pcs.len() < max {
+                core::ops::ControlFlow::Continue(())
+            } else {
+                core::ops::ControlFlow::Break(())
+            }
+        });
+        pcs
+    }
+
+    /// Continue a walk saved with [`cursor::UnwindCursor::snapshot`] on the default target,
+    /// e.g. in a later sample of a profiler. The images must be loaded at the same addresses as
+    /// when the snapshot was taken.
+    pub fn resume<Storage, State, T>(
+        &'a self,
+        snapshot: cursor::CursorSnapshot<State>,
+    ) -> cursor::UnwindCursor<'a, Storage, State, T>
+    where
+        Storage: gimli::UnwindContextStorage<image::ImageReader<'a>>,
+        State: cursor::state::CursorState,
+        T: cursor::Target + Default,
+    {
+        cursor::UnwindCursor::from_snapshot(self, Default::default(), snapshot)
+    }
+
+    /// Resolve the location of the caller without setting up a cursor.
+    ///
+    /// This is always inlined so that the captured program counter lies in the caller. Its
+    /// own inlined frame is left out like the capture frames of a cursor.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "arm"))]
+    #[inline(always)]
+    pub fn current_location(&self) -> SymbolInfo<'_> {
+        // Step back into the call instruction so that the address stays inside the inlined body.
+        let mut info = self.resolve_symbol(cffi::return_address() - 1);
+        cursor::hide_capture_frames(&mut info);
+        info
+    }
+}
+
+/// Collect the innermost `max` frames of `frames` and the outermost one, which names the
+/// physical function. The frames in between are only counted into a [`Frame::Elided`].
+#[cfg(feature = "line-info")]
+fn collect_frames<'s, 'a: 's>(
+    mut frames: addr2line::FrameIter<'s, image::ImageReader<'a>>,
+    max: usize,
+) -> Vec<Frame<'s>> {
+    let mut collected = Vec::new();
+    let mut outermost = None;
+    let mut elided = 0;
+    while let Ok(Some(frame)) = frames.next() {
+        let frame = Frame::Dwarf(reborrow_frame(frame));
+        if collected.len() < max {
+            collected.push(frame);
+        } else if outermost.replace(frame).is_some() {
+            elided += 1;
+        }
+    }
+    if elided > 0 {
+        collected.push(Frame::Elided(elided));
+    }
+    collected.extend(outermost);
+    collected
+}
+
+/// Frames are invariant over the image reader lifetime because of `R::Offset`, so rebuild them
+/// to only borrow for as long as the context reference.
+#[cfg(feature = "line-info")]
+fn reborrow_frame<'s, 'a: 's>(
+    frame: addr2line::Frame<'s, image::ImageReader<'a>>,
+) -> addr2line::Frame<'s, image::ImageReader<'s>> {
+    addr2line::Frame {
+        dw_die_offset: frame.dw_die_offset,
+        function: frame.function.map(|x| addr2line::FunctionName {
+            name: x.name,
+            language: x.language,
+        }),
+        location: frame.location,
+    }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std", not(target_arch = "wasm32")))]
 mod tests {
-    use crate::{Frame, GlobalContext};
+    use crate::cursor::state::{CursorState, NativeState};
+    use crate::cursor::{DynamicCursor, Unwinding};
+    use crate::{Frame, FrameKind, GlobalContext, PublicFrame};
+    use object::{Object, ObjectSection};
+    use std::ffi::CString;
+    use std::ops::ControlFlow;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    /// Set to skip the tests that need a tool the host lacks, e.g. a C compiler able to build
+    /// for the target or `objcopy`, instead of failing them.
+    const SKIP_ENV: &str = "UNWINDER_SKIP_MISSING_TOOLS";
+
+    /// Report that `what` failed, presumably for lack of a tool. The test fails unless
+    /// [`SKIP_ENV`] is set, in which case it is skipped by the caller.
+    fn unavailable(what: &str) {
+        if std::env::var_os(SKIP_ENV).is_none() {
+            panic!(
+                "{} failed; set {} to skip the tests that need it",
+                what, SKIP_ENV
+            );
+        }
+        eprintln!("skipped: {} failed", what);
+    }
+
+    /// A C shared object compiled into its own temporary directory, and possibly loaded into
+    /// the test process. It is unloaded and the directory removed on drop.
+    pub(crate) struct Fixture {
+        pub path: PathBuf,
+        handle: *mut libc::c_void,
+    }
+
+    impl Fixture {
+        /// Compile `source` into a shared object with extra compiler `flags` and `dlopen` it.
+        /// Returns `None` if the test is to be skipped, see [`unavailable`].
+        pub fn load(name: &str, source: &str, flags: &[&str]) -> Option<Self> {
+            Self::build(name, source, flags).map(Self::open)
+        }
+
+        /// Compile `source` into a shared object without loading it.
+        pub fn build(name: &str, source: &str, flags: &[&str]) -> Option<Self> {
+            let dir =
+                std::env::temp_dir().join(format!("unwinder-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let src = dir.join(format!("{}.c", name));
+            let fixture = Self {
+                path: dir.join(format!("lib{}.so", name)),
+                handle: std::ptr::null_mut(),
+            };
+            std::fs::write(&src, source).unwrap();
+            Self::run(
+                Command::new("cc")
+                    .current_dir(&dir)
+                    .args(["-shared", "-fPIC", "-o"])
+                    .arg(&fixture.path)
+                    .arg(&src)
+                    .args(flags),
+            )
+            .then_some(fixture)
+        }
+
+        /// Load the shared object, which may have been modified since it was built.
+        pub fn open(mut self) -> Self {
+            let c_path = CString::new(self.path.to_str().unwrap()).unwrap();
+            self.handle = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW) };
+            if self.handle.is_null() {
+                let error = unsafe { std::ffi::CStr::from_ptr(libc::dlerror()) };
+                panic!("cannot load {}: {:?}", self.path.display(), error);
+            }
+            self
+        }
+
+        /// Run a tool, returning whether it succeeded. Failures are [`unavailable`].
+        pub fn run(command: &mut Command) -> bool {
+            let success = command.status().is_ok_and(|x| x.success());
+            if !success {
+                unavailable(&format!("{:?}", command));
+            }
+            success
+        }
+
+        /// The image of this fixture in a context.
+        pub fn image<'a, 'g>(&self, g: &'g GlobalContext<'a>) -> &'g crate::image::Image<'a> {
+            g.images
+                .iter()
+                .find(|x| std::path::Path::new(&x.filename) == self.path)
+                .unwrap()
+        }
+
+        /// Move the debug info of the fixture at `path` into a separate file, linked by
+        /// `.gnu_debuglink`. Returns `false` if the test is to be skipped.
+        pub fn split_debug_info(path: &std::path::Path) -> bool {
+            let debug = path.with_extension("debug");
+            let objcopy = |args: &[&std::ffi::OsStr]| {
+                Self::run(
+                    Command::new("objcopy")
+                        .current_dir(path.parent().unwrap())
+                        .args(args),
+                )
+            };
+            objcopy(&["--only-keep-debug".as_ref(), path.as_ref(), debug.as_ref()])
+                && objcopy(&[
+                    "--strip-debug".as_ref(),
+                    "--add-gnu-debuglink".as_ref(),
+                    debug.as_ref(),
+                    path.as_ref(),
+                ])
+        }
+
+        pub fn symbol(&self, name: &str) -> usize {
+            let name = CString::new(name).unwrap();
+            unsafe { libc::dlsym(self.handle, name.as_ptr()) as usize }
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            if !self.handle.is_null() {
+                unsafe { libc::dlclose(self.handle) };
+            }
+            let _ = std::fs::remove_dir_all(self.path.parent().unwrap());
+        }
+    }
+
+    fn frame_name(frame: &Frame) -> Option<String> {
+        match frame {
+            #[cfg(feature = "line-info")]
+            Frame::Dwarf(frame) => frame
+                .function
+                .as_ref()
+                .and_then(|x| x.raw_name().ok())
+                .map(|x| rustc_demangle::demangle(&x).to_string()),
+            Frame::SymbolMap(symbol) => Some(rustc_demangle::demangle(symbol).to_string()),
+            Frame::Section(name) => Some(name.to_string()),
+            Frame::ModuleOffset { .. } | Frame::Elided(_) => None,
+        }
+    }
 
     #[test]
     fn it_works() {
         GlobalContext::new();
     }
 
+    #[test]
+    fn it_resolves_concurrently_from_shared_context() {
+        let g = GlobalContext::new_arc();
+        let avma = it_resolves_concurrently_from_shared_context as *const () as usize;
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let g = g.clone();
+                std::thread::spawn(move || {
+                    (0..16)
+                        .map(|_| g.resolve_symbol(avma).public_frames())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for frames in threads.into_iter().flat_map(|x| x.join().unwrap()) {
+            let name = frames.last().unwrap().name.clone().unwrap();
+            assert!(name.contains("it_resolves_concurrently_from_shared_context"));
+        }
+    }
+
+    #[cfg(feature = "line-info")]
+    #[test]
+    fn it_keeps_frames_of_exited_threads() {
+        let g = GlobalContext::new();
+        let avma = it_keeps_frames_of_exited_threads as *const () as usize;
+        let image = g.find_image(avma).unwrap();
+        // The file names of the frames borrow from the line context the thread built.
+        let info = std::thread::scope(|s| s.spawn(|| g.resolve_symbol(avma)).join().unwrap());
+        assert!(image.has_line_context());
+        let frame = info.public_frames().pop().unwrap();
+        assert!(frame.file.unwrap().ends_with("lib.rs"));
+        assert_eq!(
+            g.resolve_symbol(avma).public_frames().pop().unwrap().line,
+            frame.line
+        );
+    }
+
+    #[cfg(not(feature = "line-info"))]
+    #[test]
+    fn it_skips_line_info() {
+        let g = GlobalContext::new();
+        g.resolve_symbol(it_skips_line_info as *const () as usize);
+        assert!(g.images.iter().all(|x| !x.has_line_context()));
+    }
+
+    #[test]
+    fn it_finds_images_at_boundaries() {
+        let g = GlobalContext::new();
+        let found = |avma| g.find_loaded_image(avma).map(|x| x as *const _);
+        // Images are sorted by descending start.
+        let (top, bottom) = (g.images.first().unwrap(), g.images.last().unwrap());
+        let top_end = top.segments.iter().map(|x| x.end).max().unwrap();
+        assert_eq!(found(top.start_avma), Some(top as *const _));
+        assert_eq!(found(top_end - 1), Some(top as *const _));
+        assert_eq!(found(top_end), None);
+        assert_eq!(found(usize::MAX), None);
+        assert_eq!(found(bottom.start_avma), Some(bottom as *const _));
+        assert_eq!(found(bottom.start_avma - 1), None);
+        assert_eq!(found(0), None);
+        for image in &g.images {
+            let end = image.segments.iter().map(|x| x.end).max().unwrap();
+            assert_eq!(
+                found(end - 1),
+                Some(image as *const _),
+                "{}",
+                image.filename
+            );
+        }
+    }
+
+    #[test]
+    fn it_locates_current() {
+        let g = GlobalContext::new();
+        let location = g.current_location();
+        let name = location.associated_frames.first().and_then(frame_name);
+        assert!(name.unwrap().contains("it_locates_current"));
+    }
+
+    #[cfg(feature = "line-info")]
+    #[test]
+    fn it_reports_language() {
+        let g = GlobalContext::new();
+        let frames = g
+            .resolve_symbol(it_reports_language as *const () as usize)
+            .public_frames();
+        let frame = frames.last().unwrap();
+        assert_eq!(frame.kind, FrameKind::Dwarf);
+        assert_eq!(frame.language, Some(gimli::DW_LANG_Rust));
+    }
+
+    #[test]
+    fn it_checks_unwind_info() {
+        let g = GlobalContext::new();
+        assert!(g.has_unwind_info(it_checks_unwind_info as *const () as usize));
+        assert!(g.has_unwind_info(GlobalContext::new as *const () as usize));
+        assert!(!g.has_unwind_info(0));
+        assert!(!g.has_unwind_info(usize::MAX));
+    }
+
+    #[test]
+    fn it_names_constructors_without_symbols() {
+        let source = "void *ctor_address;\n\
+                      __attribute__((constructor)) static void anonymous_ctor(void) {\n\
+                          ctor_address = (void *)anonymous_ctor;\n\
+                      }\n";
+        let fixture = match Fixture::build("ctor", source, &["-g", "-O0"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let path = &fixture.path;
+        let stripped = Fixture::run(
+            Command::new("objcopy")
+                .args(["--strip-symbol", "anonymous_ctor"])
+                .arg(path),
+        );
+        if !stripped {
+            return;
+        }
+        let fixture = fixture.open();
+        let avma = unsafe { *(fixture.symbol("ctor_address") as *const usize) };
+        let g = GlobalContext::new();
+        let image = fixture.image(&g);
+        assert!(image.find_symbol((avma - image.bias) as u64).is_none());
+        let frames = g.resolve_symbol(avma).public_frames();
+        let frame = frames.last().unwrap();
+        #[cfg(feature = "line-info")]
+        assert_eq!(frame.name.as_deref(), Some("anonymous_ctor"));
+        #[cfg(not(feature = "line-info"))]
+        assert_eq!(frame.kind, FrameKind::ModuleOffset);
+    }
+
+    #[test]
+    fn it_unwinds_with_callback() {
+        let g = GlobalContext::new();
+        let mut ucp = std::mem::MaybeUninit::<libc::ucontext_t>::zeroed();
+        assert_eq!(unsafe { crate::cffi::getcontext(ucp.as_mut_ptr()) }, 0);
+        let u_ctx = unsafe { ucp.assume_init() };
+
+        let mut expected = Vec::new();
+        let mut cursor = DynamicCursor::<NativeState>::from_ucontext(&g, u_ctx);
+        loop {
+            expected.push(cursor.state().get_program_counter());
+            if cursor.next().is_err() {
+                break;
+            }
+        }
+        assert!(expected.len() > 1);
+
+        let mut pcs = Vec::new();
+        g.unwind_with(&u_ctx, |pc| {
+            pcs.push(pc);
+            ControlFlow::Continue(())
+        });
+        assert_eq!(pcs, expected);
+
+        let mut count = 0;
+        g.unwind_with(&u_ctx, |_| {
+            count += 1;
+            if count == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86_64", target_arch = "arm"))]
+    fn it_captures_pcs() {
+        let g = GlobalContext::new();
+        let mut ucp = std::mem::MaybeUninit::<libc::ucontext_t>::zeroed();
+        assert_eq!(unsafe { crate::cffi::getcontext(ucp.as_mut_ptr()) }, 0);
+        let u_ctx = unsafe { ucp.assume_init() };
+        let (backtrace, _) = crate::backtrace::Backtrace::capture(&g);
+
+        let pcs = g.capture_pcs(&u_ctx, usize::MAX);
+        // Both start in this function, at different addresses, and share the callers.
+        let expected: Vec<_> = backtrace.frames.iter().map(|x| x.pc).collect();
+        assert!(expected.len() > 1);
+        assert_eq!(pcs[1..], expected[1..]);
+        assert_eq!(
+            g.resolve_symbol(pcs[0]).function,
+            g.resolve_symbol(expected[0]).function
+        );
+
+        assert_eq!(g.capture_pcs(&u_ctx, 2), pcs[..2]);
+        assert!(g.capture_pcs(&u_ctx, 0).is_empty());
+    }
+
+    #[test]
+    fn it_builds_configured_context() {
+        let g = GlobalContext::builder()
+            .line_info(false)
+            .resolve_plt(true)
+            .load_options(crate::image::LoadOptions {
+                max_images: Some(1),
+                ..Default::default()
+            })
+            .build();
+        assert!(g.resolve_plt);
+        assert!(g.include_inlined);
+        assert!(g.images.len() <= 1);
+        let info = g.resolve_symbol(it_builds_configured_context as *const () as usize);
+        assert!(!info.associated_frames.is_empty());
+        assert!(info
+            .associated_frames
+            .iter()
+            .all(|x| matches!(x, Frame::SymbolMap(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "line-info")]
+    fn it_resolves_names_from_separate_debug_file() {
+        // Only DWARF knows of inlined functions. Long names are kept in `.debug_str`.
+        let source = "static inline __attribute__((always_inline))\n\
+                      int separate_inlined_leaf(int x) { return x * x + 3; }\n\
+                      int separate_outer(int x) { return separate_inlined_leaf(x) + 1; }\n";
+        let fixture = match Fixture::build("separate_str", source, &["-g", "-O0"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let path = &fixture.path;
+        if !Fixture::split_debug_info(path) {
+            return;
+        }
+        let fixture = fixture.open();
+        let start = fixture.symbol("separate_outer");
+        let mut g = GlobalContext::new();
+        assert!(!gimli::Section::reader(&fixture.image(&g).dwarf().debug_str).is_empty());
+        let names = |g: &GlobalContext| {
+            (start..start + 0x40)
+                .map(|x| {
+                    g.resolve_symbol(x)
+                        .associated_frames
+                        .iter()
+                        .filter_map(frame_name)
+                        .collect::<Vec<_>>()
+                })
+                .find(|x| x.len() == 2)
+        };
+        let expected = ["separate_inlined_leaf", "separate_outer"];
+        assert_eq!(names(&g).unwrap(), expected);
+        // The line context is rebuilt from the sections kept by the image.
+        g.evict_line_contexts();
+        assert_eq!(names(&g).unwrap(), expected);
+    }
+
+    #[test]
+    fn it_resolves_from_symbol_cache() {
+        let source = "__attribute__((noinline)) int cache_leaf(int x) { return x * 3; }\n\
+                      int cache_outer(int x) { return cache_leaf(x) + 1; }\n";
+        let fixture = match Fixture::load("cache", source, &["-g", "-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let fresh = GlobalContext::new();
+        let build_id = match fixture.image(&fresh).build_id() {
+            Some(id) => id.iter().map(|x| format!("{:02x}", x)).collect::<String>(),
+            None => return,
+        };
+        let path = fixture.path.with_extension("cache");
+        fresh.save_cache(&path).unwrap();
+
+        let resolve = |g: &GlobalContext, name| {
+            let info = g.resolve_symbol(fixture.symbol(name) + 1);
+            let name = info.associated_frames.last().and_then(frame_name);
+            (name, info.function)
+        };
+        let mut cached = GlobalContext::new();
+        assert!(cached.load_cache(&path).unwrap() > 0);
+        assert!(fixture.image(&cached).is_cached());
+        for name in ["cache_leaf", "cache_outer"] {
+            assert_eq!(resolve(&cached, name), resolve(&fresh, name));
+            assert_eq!(resolve(&cached, name).0.as_deref(), Some(name));
+        }
+        assert!(!fixture.image(&cached).has_line_context());
+
+        // Loaded along with the images, the cache keeps their DWARF from being loaded.
+        let options = crate::image::LoadOptions {
+            symbol_cache: Some(path.clone()),
+            ..Default::default()
+        };
+        let cached = GlobalContext::new_with_options(&options);
+        assert!(fixture.image(&cached).is_cached());
+        assert!(fixture
+            .image(&cached)
+            .dwarf()
+            .units()
+            .next()
+            .unwrap()
+            .is_none());
+        for name in ["cache_leaf", "cache_outer"] {
+            assert_eq!(resolve(&cached, name), resolve(&fresh, name));
+        }
+
+        // The entry of another build of the same image is not used.
+        let stale = std::fs::read_to_string(&path)
+            .unwrap()
+            .replace(&build_id, &"0".repeat(build_id.len()));
+        std::fs::write(&path, stale).unwrap();
+        let mut g = GlobalContext::new();
+        g.load_cache(&path).unwrap();
+        assert!(!fixture.image(&g).is_cached());
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn it_attaches_unwind_info_from_file() {
+        use crate::cursor::state::{CursorState, NativeState};
+        use crate::cursor::{DynamicCursor, Unwinding};
+
+        let source = "int sidecar_leaf(int x) { return x + 1; }\n\
+                      int sidecar_outer(int x) { return sidecar_leaf(x) * 2; }\n";
+        let fixture = match Fixture::build("sidecar", source, &["-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let path = &fixture.path;
+        // Keep the unwind tables aside, then hide them in the image. Removing them would move
+        // the segments, which the dynamic linker rejects.
+        let sidecar = path.with_extension("eh_frame");
+        let objcopy = |args: &[&std::ffi::OsStr]| {
+            Fixture::run(
+                Command::new("objcopy")
+                    .args(args)
+                    .stderr(std::process::Stdio::null()),
+            )
+        };
+        let kept = objcopy(&[
+            "--only-section=.eh_frame".as_ref(),
+            "--only-section=.eh_frame_hdr".as_ref(),
+            "--only-section=.note.gnu.build-id".as_ref(),
+            path.as_ref(),
+            sidecar.as_ref(),
+        ]) && objcopy(&[
+            "--rename-section=.eh_frame=.hidden_frame".as_ref(),
+            "--rename-section=.eh_frame_hdr=.hidden_frame_hdr".as_ref(),
+            path.as_ref(),
+        ]);
+        if !kept {
+            return;
+        }
+        let fixture = fixture.open();
+        let leaf = fixture.symbol("sidecar_leaf");
+        let outer = fixture.symbol("sidecar_outer");
+        let mut g = GlobalContext::new();
+        assert!(!g.has_unwind_info(leaf));
+        let covered = match fixture.image(&g).build_id() {
+            Some(_) => g.attach_unwind_info(&sidecar).unwrap(),
+            None => g
+                .attach_unwind_info_by_name(fixture.path.to_str().unwrap(), &sidecar)
+                .unwrap(),
+        };
+        assert_eq!(covered, 1);
+        assert!(g.has_unwind_info(leaf));
+        // The image has its tables now, so there is nothing left to attach.
+        assert!(fixture.image(&g).eh_frame_hdr_section.is_some());
+        let name = fixture.path.to_str().unwrap();
+        assert!(g.attach_unwind_info_by_name(name, &sidecar).is_err());
+
+        let stack = [outer, 0];
+        let sp = stack.as_ptr() as usize;
+        let mut cursor = DynamicCursor::<NativeState>::from_pc_sp(&g, leaf, sp);
+        cursor.next().unwrap();
+        assert_eq!(cursor.state().get_program_counter(), outer);
+        assert_eq!(cursor.cfa(), Some(sp + 8));
+    }
+
+    #[test]
+    #[cfg(feature = "line-info")]
+    fn it_reports_call_sites_of_inlined_frames() {
+        let source = "#define INLINE static inline __attribute__((always_inline))\n\
+                      INLINE int site_leaf(int x) {\n\
+                      \x20 return x * x + 3;\n\
+                      }\n\
+                      INLINE int site_mid(int x) {\n\
+                      \x20 int y = x + 2;\n\
+                      \x20 return site_leaf(y) - 1;\n\
+                      }\n\
+                      int site_outer(int x) {\n\
+                      \x20 return site_mid(x) * 5;\n\
+                      }\n";
+        let fixture = match Fixture::load("site", source, &["-g", "-O0"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let start = fixture.symbol("site_outer");
+        let g = GlobalContext::new();
+        let frames = (start..start + 0x60)
+            .map(|x| g.resolve_symbol(x).public_frames())
+            .find(|x| x.len() == 3)
+            .expect("no address inside the nested inlined body");
+        let names: Vec<_> = frames.iter().map(|x| x.name.as_deref().unwrap()).collect();
+        assert_eq!(names, ["site_leaf", "site_mid", "site_outer"]);
+        // Each line lies in the function of its frame; call sites lie in the caller.
+        assert_eq!(frames[0].line, Some(3));
+        assert_eq!(frames[0].call_line, Some(7));
+        assert_eq!(frames[1].line, Some(7));
+        assert_eq!(frames[1].call_line, Some(10));
+        assert_eq!(frames[2].line, Some(10));
+        assert_eq!(frames[2].call_line, None);
+        assert_eq!(frames[0].call_file, frames[1].file);
+    }
+
+    #[test]
+    #[cfg(feature = "line-info")]
+    fn it_resolves_return_addresses_at_call_sites() {
+        let source = "volatile int counter;\n\
+                      void call_site(void (*f)(void)) {\n\
+                      \x20 f();\n\
+                      \x20 counter++;\n\
+                      }\n";
+        let fixture = match Fixture::load("call_site", source, &["-g", "-O0"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let start = fixture.symbol("call_site");
+        let g = GlobalContext::new();
+        let line = |info: crate::SymbolInfo| info.public_frames()[0].line;
+        // The statement after the call starts at its return address.
+        let return_address = (start..start + 0x40)
+            .find(|&x| line(g.resolve_symbol(x)) == Some(4))
+            .unwrap();
+        let info = g.resolve_call_site(return_address);
+        assert_eq!(info.avma, return_address);
+        assert_eq!(info.svma, g.resolve_symbol(return_address).svma);
+        assert_eq!(info.offset, Some(return_address - start));
+        assert_eq!(line(info), Some(3));
+    }
+
+    #[test]
+    #[cfg(feature = "line-info")]
+    fn it_caps_inlined_frames() {
+        // A chain of 41 inlined functions, each calling the next.
+        let mut source =
+            String::from("#define INLINE static inline __attribute__((always_inline))\n");
+        source += "INLINE int deep40(int x) { return x * x + 3; }\n";
+        for i in (0..40).rev() {
+            source += &format!(
+                "INLINE int deep{}(int x) {{ return deep{}(x) + 1; }}\n",
+                i,
+                i + 1
+            );
+        }
+        source += "int deep_outer(int x) { return deep0(x) * 5; }\n";
+        let fixture = match Fixture::load("deep_inline", &source, &["-g", "-O0"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let start = fixture.symbol("deep_outer");
+        let mut g = GlobalContext::new();
+        g.set_max_inline_frames(usize::MAX);
+        let deepest = (start..start + 0x400)
+            .max_by_key(|&x| g.resolve_symbol(x).associated_frames.len())
+            .unwrap();
+        let names = |g: &GlobalContext| -> Vec<_> {
+            g.resolve_symbol(deepest)
+                .public_frames()
+                .into_iter()
+                .map(|x| x.name.unwrap())
+                .collect()
+        };
+        let all = names(&g);
+        assert_eq!(all.len(), 42);
+
+        g.set_max_inline_frames(8);
+        let capped = names(&g);
+        assert_eq!(capped.len(), 10);
+        assert_eq!(capped[..8], all[..8]);
+        assert_eq!(capped[8], "... 33 more inlined frames");
+        assert_eq!(capped[9], "deep_outer");
+        assert_eq!(g.inline_frames(deepest).len(), 10);
+    }
+
+    #[test]
+    #[cfg(feature = "line-info")]
+    fn it_resolves_inline_chain_of_address() {
+        let source = "#define INLINE static inline __attribute__((always_inline))\n\
+                      INLINE int chain_leaf(int x) { return x * x + 3; }\n\
+                      INLINE int chain_mid(int x) { return chain_leaf(x + 2) - 1; }\n\
+                      int chain_outer(int x) { return chain_mid(x) * 5; }\n";
+        let fixture = match Fixture::load("chain", source, &["-g", "-O0"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let start = fixture.symbol("chain_outer");
+        let mut g = GlobalContext::new();
+        g.set_include_inlined(false);
+        let frames = (start..start + 0x60)
+            .map(|x| g.inline_frames(x))
+            .find(|x| x.len() == 3)
+            .expect("no address inside the nested inlined body");
+        let names: Vec<_> = frames.iter().map(|x| x.name.as_deref().unwrap()).collect();
+        assert_eq!(names, ["chain_leaf", "chain_mid", "chain_outer"]);
+        assert!(frames.iter().all(|x| x.kind == FrameKind::Dwarf));
+        assert_eq!(frames[0].call_line, frames[1].line);
+        assert!(g.inline_frames(0).is_empty());
+    }
+
+    #[test]
+    fn it_reports_offset_into_function() {
+        let source = "int offset_fn(int x) { return x * 3 + 1; }\n";
+        // Offsets are taken from the DWARF subprogram and from the ELF symbol.
+        for (name, flags) in [
+            ("offset_dwarf", &["-g", "-O0"][..]),
+            ("offset_sym", &["-O0"]),
+        ] {
+            let fixture = match Fixture::load(name, source, flags) {
+                Some(fixture) => fixture,
+                None => return,
+            };
+            let start = fixture.symbol("offset_fn");
+            let g = GlobalContext::new();
+            let info = g.resolve_symbol(start + 4);
+            assert_eq!(info.offset, Some(4));
+            let frame = info.public_frames().pop().unwrap();
+            assert_eq!(frame.name.as_deref(), Some("offset_fn"));
+            assert_eq!(frame.offset, Some(4));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "line-info")]
+    fn it_resolves_names_despite_truncated_line_table() {
+        let source = "int truncated_lines(int x) { return x * 3 + 1; }\n";
+        let fixture = match Fixture::build("truncated", source, &["-g", "-O0"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let path = &fixture.path;
+        let lines = path.with_extension("line");
+        let objcopy = |option: &str| {
+            let section = format!(".debug_line={}", lines.display());
+            Fixture::run(Command::new("objcopy").args([option, &section]).arg(path))
+        };
+        if !objcopy("--dump-section") {
+            return;
+        }
+        let data = std::fs::read(&lines).unwrap();
+        std::fs::write(&lines, &data[..data.len() / 2]).unwrap();
+        assert!(objcopy("--update-section"));
+        let fixture = fixture.open();
+
+        let g = GlobalContext::new();
+        let image = fixture.image(&g);
+        assert_eq!(
+            image.invalid_debug_sections(),
+            [gimli::SectionId::DebugLine]
+        );
+        let frames = g
+            .resolve_symbol(fixture.symbol("truncated_lines"))
+            .public_frames();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].kind, FrameKind::Dwarf);
+        assert_eq!(frames[0].name.as_deref(), Some("truncated_lines"));
+        assert_eq!(frames[0].line, None);
+    }
+
+    #[test]
+    #[cfg(feature = "line-info")]
+    fn it_toggles_inlined_frames() {
+        let source = "static inline __attribute__((always_inline)) int inl_leaf(int x) {\n\
+                      \x20 return x * x + 3;\n\
+                      }\n\
+                      int inl_outer(int x) { return inl_leaf(x) + 1; }\n";
+        let fixture = match Fixture::load("inlined", source, &["-g", "-O0"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let start = fixture.symbol("inl_outer");
+        let mut g = GlobalContext::new();
+        let avma = (start..start + 0x40)
+            .find(|x| g.resolve_symbol(*x).associated_frames.len() == 2)
+            .expect("no address inside the inlined body");
+        let names: Vec<_> = g
+            .resolve_symbol(avma)
+            .associated_frames
+            .iter()
+            .filter_map(frame_name)
+            .collect();
+        assert_eq!(names, ["inl_leaf", "inl_outer"]);
+
+        g.set_include_inlined(false);
+        let resolved = g.resolve_symbol(avma);
+        let names: Vec<_> = resolved
+            .associated_frames
+            .iter()
+            .filter_map(frame_name)
+            .collect();
+        assert_eq!(names, ["inl_outer"]);
+        assert_eq!(resolved.discriminator, None);
+    }
+
+    #[cfg(feature = "line-info")]
+    #[test]
+    fn it_reports_columns_and_discriminators() {
+        let source =
+            "int disc_fn(int x) { int a = 0; for (int i = 0; i < x; i++) a += i; return a; }\n";
+        let fixture = match Fixture::load("disc", source, &["-g", "-O0"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let g = GlobalContext::new();
+        let start = fixture.symbol("disc_fn");
+        let frames: Vec<_> = (start..start + 0x20)
+            .filter_map(|avma| g.resolve_symbol(avma).public_frames().into_iter().next())
+            .collect();
+        assert!(frames.iter().all(|x| x.kind == FrameKind::Dwarf));
+        assert!(frames.iter().all(|x| x.line == Some(1)));
+        let mut columns: Vec<_> = frames.iter().filter_map(|x| x.column).collect();
+        columns.dedup();
+        assert!(columns.len() > 1);
+        assert!(frames.iter().any(|x| x.discriminator.is_some()));
+        assert!(frames.iter().any(|x| x.discriminator.is_none()));
+    }
+
+    #[test]
+    fn it_resolves_dynsym_of_stripped_objects() {
+        let source = "static int hidden(int x) { return x - 1; }\n\
+                      int exported_fn(int x) { return hidden(x) * 5; }\n\
+                      int exported_next(int x) { return x + 7; }\n";
+        // One copy loses `.symtab` altogether, the other only the exported symbol.
+        let strips: [&[&str]; 2] = [&["strip", "--strip-all"], &["objcopy", "-N", "exported_fn"]];
+        for (i, strip) in strips.into_iter().enumerate() {
+            let fixture = match Fixture::build(&format!("dynsym{}", i), source, &["-O0"]) {
+                Some(fixture) => fixture,
+                None => return,
+            };
+            let path = &fixture.path;
+            if !Fixture::run(Command::new(strip[0]).args(&strip[1..]).arg(path)) {
+                return;
+            }
+            let fixture = fixture.open();
+            let g = GlobalContext::new();
+            let resolved = g.resolve_symbol(fixture.symbol("exported_fn") + 1);
+            let names: Vec<_> = resolved
+                .associated_frames
+                .iter()
+                .filter_map(frame_name)
+                .collect();
+            assert_eq!(names, ["exported_fn"]);
+        }
+    }
+
+    #[test]
+    fn it_reports_module_offsets() {
+        let source = "__attribute__((visibility(\"hidden\"), noinline))\n\
+                      int anonymous_fn(int x) { return x * 3 + 1; }\n\
+                      void *exported_address(void) { return (void *)anonymous_fn; }\n";
+        let fixture = match Fixture::build("anonymous", source, &["-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let path = &fixture.path;
+        if !Fixture::run(Command::new("strip").arg("--strip-all").arg(path)) {
+            return;
+        }
+        let fixture = fixture.open();
+        let address: extern "C" fn() -> usize =
+            unsafe { std::mem::transmute(fixture.symbol("exported_address")) };
+        let g = GlobalContext::new();
+        let resolved = g.resolve_symbol(address());
+        let svma = resolved.svma.unwrap();
+        assert!(matches!(
+            resolved.associated_frames.as_slice(),
+            [Frame::ModuleOffset { offset, .. }] if *offset == svma
+        ));
+        let frames = resolved.public_frames();
+        assert_eq!(frames[0].kind, FrameKind::ModuleOffset);
+        assert_eq!(
+            frames[0].name.as_deref(),
+            Some(format!("{}+{:#x}", fixture.path.display(), svma).as_str())
+        );
+    }
+
+    #[test]
+    fn it_bounds_symbols_by_size() {
+        // Aligned functions leave padding after the first one.
+        let source = "int gap_a(int x) { return x + 1; }\nint gap_b(int x) { return x * 2; }\n";
+        let fixture = match Fixture::load("gap", source, &["-O1", "-falign-functions=64"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let g = GlobalContext::new();
+        let image = fixture.image(&g);
+        let (a, b) = (fixture.symbol("gap_a"), fixture.symbol("gap_b"));
+        let size = image.find_symbol((a - image.bias) as u64).unwrap().size() as usize;
+        assert!(size > 0 && a + size + 1 < b);
+        let names = |avma: usize| -> Vec<_> {
+            g.resolve_symbol(avma)
+                .associated_frames
+                .iter()
+                .filter_map(frame_name)
+                .collect()
+        };
+        // A return address may lie right past the function.
+        assert_eq!(names(a + size), ["gap_a"]);
+        assert!(matches!(
+            g.resolve_symbol(a + size + 1).associated_frames.as_slice(),
+            [Frame::ModuleOffset { .. }]
+        ));
+        assert_eq!(names(b), ["gap_b"]);
+    }
+
+    #[test]
+    fn it_converts_to_owned_frames() {
+        let source = "int owned_frame(int x) { return x + 1; }\n";
+        let fixture = match Fixture::load("owned", source, &["-g", "-O0"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let avma = fixture.symbol("owned_frame");
+        let frames: Vec<PublicFrame> = {
+            let g = GlobalContext::new();
+            let info = g.resolve_symbol(avma);
+            (&info).into()
+        };
+        // The frames outlive the context and move across threads.
+        let frame = std::thread::spawn(move || frames.into_iter().last())
+            .join()
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.name.as_deref(), Some("owned_frame"));
+        assert_eq!(frame.function_start, Some(avma));
+        #[cfg(feature = "line-info")]
+        {
+            assert_eq!(frame.kind, FrameKind::Dwarf);
+            assert!(frame.file.unwrap().ends_with("owned.c"));
+            assert_eq!(frame.line, Some(1));
+        }
+
+        let frame = PublicFrame::from(&Frame::SymbolMap("_ZN3foo3barE"));
+        assert_eq!(frame.name.as_deref(), Some("foo::bar"));
+        assert_eq!((frame.file, frame.line), (None, None));
+        assert_eq!(frame.kind, FrameKind::SymbolMap);
+    }
+
+    #[test]
+    fn it_uses_custom_demangler() {
+        let source = "int _D4main3fooFZi(void) { return 42; }\n";
+        let fixture = match Fixture::load("demangle", source, &["-g", "-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let mut g = GlobalContext::new();
+        g.set_demangler(|name| (name == "_D4main3fooFZi").then(|| "main.foo()".to_string()));
+        let avma = fixture.symbol("_D4main3fooFZi");
+        let frames = g.resolve_symbol(avma).public_frames();
+        assert_eq!(frames.last().unwrap().name.as_deref(), Some("main.foo()"));
+
+        // Names the custom demangler does not recognize are still demangled as Rust names.
+        let frames = g
+            .resolve_symbol(it_uses_custom_demangler as fn() as usize)
+            .public_frames();
+        let name = frames.last().unwrap().name.clone().unwrap();
+        assert!(name.contains("it_uses_custom_demangler"), "{}", name);
+    }
+
+    #[test]
+    fn it_filters_images_to_resolve() {
+        let mut g = GlobalContext::new();
+        let libc_avma = libc::getpid as *const () as usize;
+        // Statically linked C libraries are part of the executable.
+        if !g
+            .module_address(libc_avma)
+            .is_some_and(|x| x.object_name.contains("libc"))
+        {
+            return;
+        }
+        g.set_resolution_filter(|image| !image.filename.contains("libc"));
+
+        let info = g.resolve_symbol(libc_avma);
+        assert!(info.object_name.unwrap().contains("libc"));
+        assert!(info.function.is_none());
+        let frames = info.public_frames();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].kind, FrameKind::ModuleOffset);
+        assert!(g.inline_frames(libc_avma).is_empty());
+
+        let frames = g
+            .resolve_symbol(it_filters_images_to_resolve as fn() as usize)
+            .public_frames();
+        let name = frames.last().unwrap().name.clone().unwrap();
+        assert!(name.contains("it_filters_images_to_resolve"), "{}", name);
+    }
+
+    #[test]
+    fn it_names_sections_without_symbols() {
+        // Taking the address of `puts` as well moves its stub to `.plt.got`.
+        let source = "#include <stdio.h>\n\
+                      int sec_call(const char *s) { return puts(s); }\n\
+                      void *sec_address(void) { return (void *)puts; }\n";
+        let fixture = match Fixture::load("sections", source, &["-O1"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let data = std::fs::read(&fixture.path).unwrap();
+        let object = object::File::parse(&*data).unwrap();
+        let g = GlobalContext::new();
+        let bias = fixture.image(&g).bias;
+        // `.init` starts with the `_init` symbol, but the PLT sections have none.
+        for name in [".plt", ".plt.got"] {
+            let section = object.section_by_name(name).unwrap().address() as usize;
+            let resolved = g.resolve_symbol(bias + section);
+            let label = format!("<{}>", name);
+            assert!(matches!(
+                resolved.associated_frames.as_slice(),
+                [Frame::Section(x)] if *x == label
+            ));
+            let frames = resolved.public_frames();
+            assert_eq!(frames[0].kind, FrameKind::Section);
+            assert_eq!(frames[0].name.as_deref(), Some(label.as_str()));
+        }
+    }
+
+    #[test]
+    fn it_resolves_plt_stubs() {
+        let source = "#include <stdio.h>\n\
+                      int plt_call(const char *s) { return puts(s) + printf(\"%s\", s); }\n";
+        // With IBT, the calls go through `.plt.sec` and `.plt` only holds lazy binding stubs.
+        let variants: [(&str, &[&str]); 2] = [
+            ("plt", &["-O1"]),
+            ("plt_ibt", &["-O1", "-fcf-protection=full", "-Wl,-z,ibtplt"]),
+        ];
+        for (name, flags) in variants {
+            let fixture = match Fixture::load(name, source, flags) {
+                Some(fixture) => fixture,
+                None => return,
+            };
+            let data = std::fs::read(&fixture.path).unwrap();
+            let object = object::File::parse(&*data).unwrap();
+            let mut g = GlobalContext::new();
+            let bias = fixture.image(&g).bias;
+            let stubs = [".plt", ".plt.sec"]
+                .into_iter()
+                .filter_map(|x| object.section_by_name(x))
+                .flat_map(|x| x.address()..x.address() + x.size())
+                .collect::<Vec<_>>();
+            let targets = |g: &GlobalContext| {
+                let mut targets = stubs
+                    .iter()
+                    .filter_map(|x| {
+                        g.resolve_symbol(bias + *x as usize)
+                            .associated_frames
+                            .iter()
+                            .find_map(frame_name)
+                    })
+                    .collect::<Vec<_>>();
+                targets.sort();
+                targets.dedup();
+                targets
+            };
+            assert!(!targets(&g).iter().any(|x| x == "puts"));
+            g.set_resolve_plt(true);
+            let targets = targets(&g);
+            assert!(
+                targets.iter().any(|x| x == "puts"),
+                "{}: {:?}",
+                name,
+                targets
+            );
+            assert!(
+                targets.iter().any(|x| x == "printf"),
+                "{}: {:?}",
+                name,
+                targets
+            );
+            // The PLT header jumps to the resolver, not to a function.
+            let plt = object.section_by_name(".plt").unwrap().address() as usize;
+            let header = g.resolve_symbol(bias + plt);
+            assert_eq!(
+                header
+                    .associated_frames
+                    .iter()
+                    .find_map(frame_name)
+                    .as_deref(),
+                Some("<.plt>")
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "line-info")]
+    fn it_resolves_cold_parts_of_split_functions() {
+        use object::ObjectSymbol;
+        let source = "__attribute__((noinline, cold)) void cold_sink(int x) {\n\
+                      \x20 __asm__ volatile(\"\" :: \"r\"(x));\n\
+                      }\n\
+                      int hotcold(int x) {\n\
+                      \x20 if (__builtin_expect(x == 42, 0)) {\n\
+                      \x20   cold_sink(x);\n\
+                      \x20   cold_sink(x + 1);\n\
+                      \x20   return -1;\n\
+                      \x20 }\n\
+                      \x20 return x + 1;\n\
+                      }\n";
+        let flags = ["-g", "-O2", "-freorder-blocks-and-partition"];
+        let fixture = match Fixture::load("hotcold", source, &flags) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let data = std::fs::read(&fixture.path).unwrap();
+        let object = object::File::parse(&*data).unwrap();
+        let cold = match object.symbols().find(|x| x.name() == Ok("hotcold.cold")) {
+            Some(symbol) => symbol.address(),
+            None => return,
+        };
+        let g = GlobalContext::new();
+        let image = fixture.image(&g);
+        let hot = (fixture.symbol("hotcold") - image.bias) as u64;
+
+        let names: Vec<_> = g
+            .resolve_symbol(image.bias + cold as usize + 1)
+            .associated_frames
+            .iter()
+            .filter_map(frame_name)
+            .collect();
+        assert_eq!(names, ["hotcold"]);
+        let range = image.find_subprogram_range(cold + 1).unwrap();
+        assert!(range.contains(&cold) && !range.contains(&hot));
+        let ranges = image.find_subprogram_ranges(cold + 1).unwrap();
+        assert_eq!(ranges.len(), 2);
+        assert!(ranges.iter().any(|x| x.contains(&hot)));
+        assert_eq!(image.find_subprogram_ranges(hot), Some(ranges));
+    }
+
+    #[test]
+    fn it_reports_function_range() {
+        let source = "int range_fn(int x) {\n  int y = x * x;\n  return y + x;\n}\n";
+        let fixture = match Fixture::load("range", source, &["-g", "-O0"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        let g = GlobalContext::new();
+        let start = fixture.symbol("range_fn");
+        let resolved = g.resolve_symbol(start + 4);
+        let function = resolved.function.clone().unwrap();
+        assert_eq!(function.start, start);
+        assert!(function.contains(&(start + 4)));
+        let frame = resolved.public_frames().pop().unwrap();
+        assert_eq!(frame.function_start, Some(start));
+        assert_eq!(frame.function_size, Some(function.len()));
+
+        let image = fixture.image(&g);
+        let svma = (start + 4 - image.bias) as u64;
+        let symbol = image.symbol_map.get(svma).unwrap().range().unwrap();
+        assert_eq!(image.find_function_range(svma), Some(symbol.clone()));
+        #[cfg(feature = "line-info")]
+        assert_eq!(image.find_subprogram_range(svma), Some(symbol));
+    }
+
+    #[cfg(feature = "line-info")]
+    #[test]
+    fn it_resolves_split_dwarf() {
+        let source = "int split_fn(int x) {\n  return x * 3;\n}\n";
+        let fixture = match Fixture::load("split", source, &["-g", "-gsplit-dwarf"]) {
+            Some(fixture) => fixture,
+            None => return,
+        };
+        assert!(fixture
+            .path
+            .with_file_name("libsplit.so-split.dwo")
+            .exists());
+        let g = GlobalContext::new();
+        let resolved = g.resolve_symbol(fixture.symbol("split_fn"));
+        let location = resolved.associated_frames.iter().find_map(|x| match x {
+            Frame::Dwarf(frame) => frame.location.as_ref(),
+            _ => None,
+        });
+        let location = location.expect("no line info from the split unit");
+        assert!(location.file.unwrap().ends_with("split.c"));
+        assert_eq!(location.line, Some(1));
+    }
+
+    /// An image whose only subprogram is named through `DW_FORM_GNU_strp_alt`, as DWZ leaves
+    /// it, and a `.gnu_debugaltlink` to `sup.debug` with the build ID of `sup_build_id`.
+    #[cfg(target_arch = "x86_64")]
+    const ALT_NAMED_SOURCE: &str = "__asm__(\n\
+        \".text\\n.globl sup_fn\\n.type sup_fn, @function\\n\"\n\
+        \"sup_fn:\\n.Lsup_start: lea 1(%rdi), %eax\\n ret\\n.Lsup_end:\\n\"\n\
+        \".size sup_fn, .-sup_fn\\n\"\n\
+        \".section .debug_abbrev, \\\"\\\", @progbits\\n.Labbrev:\\n\"\n\
+        \".uleb128 1, 0x11\\n.byte 1\\n.uleb128 0x11, 0x01, 0x12, 0x01, 0, 0\\n\"\n\
+        \".uleb128 2, 0x2e\\n.byte 0\\n.uleb128 0x03, 0x1f21, 0x11, 0x01, 0x12, 0x01, 0, 0\\n\"\n\
+        \".byte 0\\n\"\n\
+        \".section .debug_info, \\\"\\\", @progbits\\n\"\n\
+        \".long .Linfo_end - .Linfo_start\\n.Linfo_start:\\n\"\n\
+        \".short 4\\n.long .Labbrev\\n.byte 8\\n\"\n\
+        \".uleb128 1\\n.quad .Lsup_start, .Lsup_end\\n\"\n\
+        \".uleb128 2\\n.long 0\\n.quad .Lsup_start, .Lsup_end\\n\"\n\
+        \".byte 0\\n.Linfo_end:\\n.text\\n\");\n";
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn it_loads_supplementary_dwarf() {
+        use object::Object;
+        let sup_source = |name: &str| {
+            format!(
+                "__asm__(\".section .debug_str, \\\"MS\\\", @progbits, 1\\n\
+                 .asciz \\\"{}\\\"\\n.text\\n\");\n",
+                name
+            )
+        };
+        let build_id = |path: &std::path::Path| {
+            let data = std::fs::read(path).unwrap();
+            let id = object::File::parse(&*data).unwrap().build_id().unwrap();
+            id.unwrap().to_vec()
+        };
+        let (sup, other) = match (
+            Fixture::build("sup_str", &sup_source("sup_fn_alt"), &["-Wl,--build-id"]),
+            Fixture::build(
+                "sup_other",
+                &sup_source("sup_fn_other"),
+                &["-Wl,--build-id"],
+            ),
+        ) {
+            (Some(sup), Some(other)) => (sup, other),
+            _ => return,
+        };
+        // The second image finds a file of another build under the linked name.
+        for (name, file) in [("sup", &sup), ("sup_bad", &other)] {
+            let fixture = match Fixture::build(name, ALT_NAMED_SOURCE, &[]) {
+                Some(fixture) => fixture,
+                None => return,
+            };
+            let path = &fixture.path;
+            let debug = path.with_file_name("sup.debug");
+            std::fs::copy(&file.path, &debug).unwrap();
+            let link = path.with_file_name("altlink");
+            let mut contents = b"sup.debug\0".to_vec();
+            contents.extend(build_id(&sup.path));
+            std::fs::write(&link, contents).unwrap();
+            let added = Fixture::run(
+                Command::new("objcopy")
+                    .arg(format!(
+                        "--add-section=.gnu_debugaltlink={}",
+                        link.display()
+                    ))
+                    .arg(path),
+            );
+            if !added {
+                return;
+            }
+            let fixture = fixture.open();
+            let g = GlobalContext::new();
+            let image = fixture.image(&g);
+            let resolved = g.resolve_symbol(fixture.symbol("sup_fn"));
+            let names = resolved
+                .associated_frames
+                .iter()
+                .filter_map(frame_name)
+                .collect::<Vec<_>>();
+            if std::ptr::eq(file, &sup) {
+                assert!(image.dbg_info.sup().is_some());
+                assert!(image.mismatched_debug_files().is_empty());
+                #[cfg(feature = "line-info")]
+                assert_eq!(names, ["sup_fn_alt"]);
+            } else {
+                assert!(image.dbg_info.sup().is_none());
+                assert_eq!(image.mismatched_debug_files(), [debug]);
+                assert!(!names.iter().any(|x| x.starts_with("sup_fn_")));
+            }
+        }
+    }
+
+    #[test]
+    fn it_respects_load_budget() {
+        let avma = libc::getpid as *const () as usize;
+        let options = [
+            crate::image::LoadOptions {
+                max_images: Some(1),
+                ..Default::default()
+            },
+            crate::image::LoadOptions {
+                time_budget: Some(std::time::Duration::ZERO),
+                ..Default::default()
+            },
+        ];
+        for options in options {
+            let g = GlobalContext::new_with_options(&options);
+            // A spent time budget still loads the first batch, which may hold libc.
+            let batch = options
+                .time_budget
+                .map_or(1, |_| crate::image::batch_size());
+            assert!(g.images.len() <= batch);
+            if batch == 1 {
+                assert!(!g.deferred.is_empty());
+                assert!(g.images.iter().all(|x| !x.has(avma)));
+            }
+            let resolved = g.resolve_symbol(avma);
+            assert!(resolved.object_name.unwrap().contains("libc"));
+            assert!(!resolved.associated_frames.is_empty());
+        }
+    }
+
+    #[cfg(feature = "line-info")]
+    #[test]
+    fn it_resolves_after_eviction() {
+        let mut g = GlobalContext::new();
+        let avma = it_resolves_after_eviction as *const () as usize;
+        let before = g.resolve_symbol(avma).associated_frames.len();
+        assert!(g.images.iter().any(|x| x.has_line_context()));
+        g.evict_line_contexts();
+        assert!(g.images.iter().all(|x| !x.has_line_context()));
+        assert_eq!(g.resolve_symbol(avma).associated_frames.len(), before);
+    }
+
     #[test]
     fn it_resolves() {
         let g = GlobalContext::new();
-        let resolved = g.resolve_symbol(it_resolves as usize);
+        let resolved = g.resolve_symbol(it_resolves as *const () as usize);
         println!("AVMA: {:?}", resolved.avma);
         println!("SVMA: {:?}", resolved.svma);
         println!("object: {:?}", resolved.object_name);
         for i in resolved.associated_frames {
             match i {
+                #[cfg(feature = "line-info")]
                 Frame::Dwarf(frame) => {
                     println!(
                         "dwarf name: {:?}",
@@ -167,6 +2228,15 @@ mod tests {
                 Frame::SymbolMap(symbol) => {
                     println!("symbol map name: {}", symbol);
                 }
+                Frame::Section(name) => {
+                    println!("section: {}", name);
+                }
+                Frame::ModuleOffset { object, offset } => {
+                    println!("module offset: {}+{:#x}", object, offset);
+                }
+                Frame::Elided(count) => {
+                    println!("elided: {}", count);
+                }
             }
         }
     }
@@ -0,0 +1,41 @@
+//! Unwinding from a constructor in `.init_array`, which runs before `main`. It lives in its
+//! own test binary so that nothing else runs before the test harness is set up.
+#![cfg(all(target_os = "linux", feature = "std"))]
+
+use std::sync::OnceLock;
+use unwinder::cursor::state::NativeState;
+use unwinder::cursor::{DynamicCursor, Unwinding};
+use unwinder::GlobalContext;
+
+/// The names of the frames seen by the constructor.
+static CONSTRUCTOR_FRAMES: OnceLock<Vec<Option<String>>> = OnceLock::new();
+
+#[used]
+#[link_section = ".init_array"]
+static CONSTRUCTOR: extern "C" fn() = init_array_constructor;
+
+#[inline(never)]
+extern "C" fn init_array_constructor() {
+    let g = GlobalContext::new();
+    let mut cursor = DynamicCursor::<NativeState>::new(&g).unwrap();
+    let mut names = Vec::new();
+    loop {
+        let frames = cursor.get_sym_info().public_frames();
+        names.extend(frames.into_iter().map(|x| x.name));
+        if cursor.next().is_err() {
+            break;
+        }
+    }
+    CONSTRUCTOR_FRAMES.set(names).unwrap();
+}
+
+#[test]
+fn it_unwinds_from_constructor() {
+    let names = CONSTRUCTOR_FRAMES.get().unwrap();
+    assert!(names[0]
+        .as_deref()
+        .unwrap()
+        .contains("init_array_constructor"));
+    // The constructor is called by the C runtime or the dynamic linker.
+    assert!(names.len() > 1);
+}
@@ -0,0 +1,78 @@
+//! Unwinding a synthetic stack with only the API left without the `std` feature: the image is
+//! built from bytes in memory, and the stack is read through a target that knows nothing but
+//! its memory. With `--no-default-features`, the library under test is built as `no_std`.
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+use object::{Object, ObjectSymbol};
+use unwinder::cursor::state::{CursorState, FramePointerBasedState};
+use unwinder::cursor::{DynamicCursor, Target, Unwinding};
+use unwinder::{image, GlobalContext, UnwindError};
+
+#[no_mangle]
+#[inline(never)]
+extern "C" fn synthetic_leaf() {}
+
+#[no_mangle]
+#[inline(never)]
+extern "C" fn synthetic_outer() {
+    synthetic_leaf()
+}
+
+/// A stack of words laid out at a fake base address, like one dumped on a target board.
+#[derive(Clone)]
+struct SyntheticStack {
+    base: usize,
+    words: Vec<usize>,
+}
+
+impl Target for SyntheticStack {
+    fn read_memory(&self, address: usize, buf: &mut [u8]) -> Result<(), UnwindError> {
+        let bytes: Vec<u8> = self.words.iter().flat_map(|x| x.to_ne_bytes()).collect();
+        let start = address.wrapping_sub(self.base);
+        let data = bytes
+            .get(start..start.saturating_add(buf.len()))
+            .ok_or(UnwindError::UnwindEnded)?;
+        buf.copy_from_slice(data);
+        Ok(())
+    }
+}
+
+#[test]
+fn it_unwinds_synthetic_stack_of_image_in_memory() {
+    // Keep the functions in the image, which only names them in its symbol table.
+    std::hint::black_box(synthetic_outer as extern "C" fn());
+    // Only the bytes of the image are taken; it is placed at an address of our choosing.
+    let path = std::env::current_exe().unwrap();
+    let data: &'static [u8] = std::fs::read(path).unwrap().leak();
+    let object = object::File::parse(data).unwrap();
+    let address = |name| {
+        let symbol = object.symbols().find(|x| x.name() == Ok(name)).unwrap();
+        symbol.address() as usize
+    };
+    let bias = 0x4000_0000;
+    let (leaf, outer) = (
+        bias + address("synthetic_leaf"),
+        bias + address("synthetic_outer"),
+    );
+    let image = image::load_bytes("synthetic", data, bias).unwrap();
+    let g = GlobalContext::with_images(vec![image]);
+    let frames = g.resolve_symbol(leaf).public_frames();
+    assert_eq!(frames[0].name.as_deref(), Some("synthetic_leaf"));
+
+    // On entry of the leaf, its return address is on top of the stack. The caller's frame is
+    // looked up at the call, right before the return address, which is its entry here.
+    let target = SyntheticStack {
+        base: 0x7000_0000,
+        words: vec![outer + 1, 0],
+    };
+    let state = FramePointerBasedState::from_pc_sp(leaf, target.base);
+    let mut cursor = DynamicCursor::from_parts(&g, target, state);
+    // Nothing but the memory of the target is known.
+    assert!(cursor.target().initial_context().is_err());
+    cursor.next().unwrap();
+    assert_eq!(cursor.state().get_program_counter(), outer + 1);
+    assert_eq!(cursor.state().get_stack_pointer(), 0x7000_0008);
+    cursor.next().unwrap();
+    assert_eq!(cursor.state().get_program_counter(), 0);
+    assert!(cursor.next().is_err());
+}